@@ -40,7 +40,18 @@ CREATE TABLE IF NOT EXISTS models (
     id TEXT PRIMARY KEY,
     object TEXT NOT NULL,
     created BIGINT NOT NULL,
-    owned_by TEXT NOT NULL
+    owned_by TEXT NOT NULL,
+    context_length INTEGER NOT NULL DEFAULT 0,
+    modality TEXT NOT NULL DEFAULT 'text',
+    prompt_price DOUBLE PRECISION NOT NULL DEFAULT 0,
+    completion_price DOUBLE PRECISION NOT NULL DEFAULT 0,
+    cached_price DOUBLE PRECISION NOT NULL DEFAULT 0,
+    fallbacks TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS model_aliases (
+    alias TEXT PRIMARY KEY,
+    model TEXT NOT NULL REFERENCES models(id) ON DELETE CASCADE
 );
 
 CREATE TABLE IF NOT EXISTS services (
@@ -49,7 +60,10 @@ CREATE TABLE IF NOT EXISTS services (
     status TEXT NOT NULL,
     url TEXT NOT NULL,
     model_name TEXT NOT NULL,
-    active_model TEXT NOT NULL
+    active_model TEXT NOT NULL,
+    weight INTEGER NOT NULL DEFAULT 1,
+    allowed_params TEXT NOT NULL DEFAULT '',
+    denied_params TEXT NOT NULL DEFAULT ''
 );
 
 CREATE TABLE IF NOT EXISTS models_service (
@@ -57,4 +71,71 @@ CREATE TABLE IF NOT EXISTS models_service (
     modelid TEXT NOT NULL,
     PRIMARY KEY (serviceid, modelid),
     FOREIGN KEY (serviceid) REFERENCES services(id) ON DELETE CASCADE
-);
\ No newline at end of file
+);
+CREATE TABLE IF NOT EXISTS traffic_splits (
+    id TEXT PRIMARY KEY,
+    model TEXT NOT NULL,
+    target_model TEXT NOT NULL DEFAULT '',
+    target_service TEXT NOT NULL DEFAULT '',
+    percent INTEGER NOT NULL DEFAULT 0,
+    userkeys TEXT NOT NULL DEFAULT '',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE TABLE IF NOT EXISTS rate_limits (
+    id TEXT PRIMARY KEY,
+    scope TEXT NOT NULL,
+    subject TEXT NOT NULL DEFAULT '*',
+    requests_per_minute INTEGER NOT NULL,
+    burst INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (scope, subject)
+);
+
+CREATE TABLE IF NOT EXISTS model_limits (
+    id TEXT PRIMARY KEY,
+    scope TEXT NOT NULL,
+    subject TEXT NOT NULL DEFAULT '*',
+    model_name TEXT NOT NULL,
+    rpm INTEGER NOT NULL DEFAULT 0,
+    tpm INTEGER NOT NULL DEFAULT 0,
+    rpd INTEGER NOT NULL DEFAULT 0,
+    tpd INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS quota_counters (
+    subject TEXT NOT NULL,
+    model TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    window_secs BIGINT NOT NULL,
+    window_start BIGINT NOT NULL,
+    used BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (subject, model, kind, window_secs, window_start)
+);
+
+CREATE TABLE IF NOT EXISTS usage_records (
+    id BIGSERIAL PRIMARY KEY,
+    account_id TEXT NOT NULL DEFAULT '',
+    api_key TEXT NOT NULL DEFAULT '',
+    project TEXT NOT NULL DEFAULT '',
+    model TEXT NOT NULL,
+    requested_model TEXT NOT NULL DEFAULT '',
+    variant TEXT NOT NULL DEFAULT '',
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    total_tokens INTEGER NOT NULL DEFAULT 0,
+    cached_tokens INTEGER NOT NULL DEFAULT 0,
+    cost DOUBLE PRECISION NOT NULL DEFAULT 0,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS usage_records_start_time ON usage_records (start_time);
+CREATE INDEX IF NOT EXISTS usage_records_account ON usage_records (account_id, start_time);
+
+CREATE TABLE IF NOT EXISTS budgets (
+    id TEXT PRIMARY KEY,
+    scope TEXT NOT NULL,
+    subject TEXT NOT NULL DEFAULT '*',
+    monthly_limit DOUBLE PRECISION NOT NULL DEFAULT 0,
+    soft_limit DOUBLE PRECISION NOT NULL DEFAULT 0,
+    UNIQUE (scope, subject)
+);
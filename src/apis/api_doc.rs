@@ -2,14 +2,23 @@ use utoipa::OpenApi;
 
 use crate::apis::models_api;
 use crate::apis::control_api;
-use crate::meta::models::Model;
+use crate::meta::models::traits::{Model, ModelConfig, ModelAlias, ModelCard, ModelList};
+use crate::meta::traffic_splits::traits::TrafficSplit;
+use crate::meta::rate_limits::traits::RateLimit;
+use crate::meta::budgets::traits::Budget;
+use crate::meta::qos::traits::Limits;
 use crate::apis::models_api::schemas::{EmbeddingRequest, EmbeddingResponse, EmbeddingData};
 use crate::cores::chat_models::chat_controller::{ChatCompletionRequest, Message,Usage};
 use crate::apis::control_api::models::{ModelErrorDetails, ModelErrorName};
 use crate::cores::chat_models::chat_controller::{CompletionsResponse, CompletionsChoice, CompletionsAssistantMessage, 
     CompletionsUsage, CompletionsStreamResponse, CompletionsStreamChoice, CompletionsDelta};
+use crate::cores::chat_models::chat_controller::{Tool, FunctionDefinition, ToolChoice, NamedToolChoice, FunctionName,
+    ResponseFormat, ToolCall, FunctionCall, ToolCallDelta, FunctionCallDelta, MessageContent, ContentPart, ImageUrl, InputAudio,
+    TextCompletionRequest, TextCompletionsResponse, TextCompletionsChoice};
 use crate::apis::schemas::ErrorResponse;
 use crate::meta::files::traits::File;
+use crate::cores::usage::DeliveryStats;
+use crate::meta::usage::traits::{UsageRecord, UsageAggregate};
 
 
 #[derive(OpenApi)]
@@ -17,10 +26,10 @@ use crate::meta::files::traits::File;
     paths(
         models_api::chat::health,
         //models_api::chat::completions,
+        models_api::completions::completions,
         models_api::embeddings::v1_embeddings,
         control_api::models::models,
         control_api::models::model_info,
-        control_api::models::delete_model,
         control_api::files::delete_file,
         control_api::files::get_all_files,
         control_api::files::get_file,
@@ -28,9 +37,12 @@ use crate::meta::files::traits::File;
         //funcs_api::rag::rag_chat_completions,
     ),
     components(
-        schemas(Model, ChatCompletionRequest, Message, ErrorResponse, EmbeddingRequest, EmbeddingResponse, 
+        schemas(Model, ModelConfig, ModelAlias, ModelCard, ModelList, TrafficSplit, RateLimit, Limits, ChatCompletionRequest, Message, ErrorResponse, EmbeddingRequest, EmbeddingResponse, 
             EmbeddingData, Usage, ModelErrorDetails, ModelErrorName, File, CompletionsResponse, CompletionsChoice,
-            CompletionsAssistantMessage, CompletionsUsage, CompletionsStreamResponse, CompletionsStreamChoice, CompletionsDelta)
+            CompletionsAssistantMessage, CompletionsUsage, CompletionsStreamResponse, CompletionsStreamChoice, CompletionsDelta,
+            Tool, FunctionDefinition, ToolChoice, NamedToolChoice, FunctionName, ResponseFormat, ToolCall, FunctionCall,
+            ToolCallDelta, FunctionCallDelta, MessageContent, ContentPart, ImageUrl, InputAudio,
+            TextCompletionRequest, TextCompletionsResponse, TextCompletionsChoice, DeliveryStats, UsageRecord, UsageAggregate, Budget)
     )
 )]
 
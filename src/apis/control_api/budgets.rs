@@ -0,0 +1,125 @@
+use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::apis::control_api::catalog::catalog_error;
+use crate::cores::control::budgets::BudgetManager;
+use crate::meta::budgets::traits::Budget;
+use crate::middleware::auth4manage::Auth4ManageMiddleware;
+
+// Monthly spend budgets per account or api key, checked by `QosMiddleware` before a request is served
+pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ManageMiddleware>) {
+    cfg.service(
+        web::scope("/v1/budgets")
+            .wrap(auth_middleware) // 应用中间件
+            .service(create_budget)
+            .service(get_all_budgets)
+            .service(get_budget)
+            .service(update_budget)
+            .service(delete_budget),
+    );
+}
+
+#[post("")]
+async fn create_budget(
+    budget: web::Json<Budget>,
+) -> Result<impl Responder, Error> {
+    let budget_manager = BudgetManager::default();
+    budget_manager.create_budget(&budget.into_inner())
+        .await
+        .map(|_| HttpResponse::Created().json(json!({
+            "code": 200,
+            "message": "Budget created successfully.",
+            "body": null
+        })))
+        .map_err(|e| catalog_error(e, "Failed to create budget."))
+}
+
+#[get("")]
+async fn get_all_budgets() -> Result<impl Responder, Error> {
+    let budget_manager = BudgetManager::default();
+    budget_manager.get_all_budgets()
+        .await
+        .map(|budgets| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "All budgets get successfully.",
+            "body": budgets
+        })))
+        .map_err(|e| catalog_error(e, "Failed to get all budgets."))
+}
+
+#[get("/{id}")]
+async fn get_budget(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let budget_manager = BudgetManager::default();
+    budget_manager.get_budget(&id)
+        .await
+        .map(|budget| match budget {
+            Some(budget) => HttpResponse::Ok().json(json!({
+                "code": 200,
+                "message": "Budget get successfully.",
+                "body": budget
+            })),
+            None => HttpResponse::NotFound().json(json!({
+                "code": 404,
+                "message": "Budget not found.",
+                "body": null
+            })),
+        })
+        .map_err(|e| catalog_error(e, "Failed to get budget."))
+}
+
+#[put("/{id}")]
+async fn update_budget(
+    id: web::Path<String>,
+    budget: web::Json<Budget>,
+) -> Result<impl Responder, Error> {
+    let mut updated_budget = budget.into_inner();
+    updated_budget.id = id.clone();
+
+    let budget_manager = BudgetManager::default();
+    budget_manager.update_budget(&updated_budget)
+        .await
+        .map(|updated| {
+            if updated > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Budget updated successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Budget not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to update budget."))
+}
+
+#[delete("/{id}")]
+async fn delete_budget(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let budget_manager = BudgetManager::default();
+    budget_manager.delete_budget(&id)
+        .await
+        .map(|deleted| {
+            if deleted > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Budget deleted successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Budget not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to delete budget."))
+}
@@ -0,0 +1,237 @@
+use actix_web::{delete, error, get, post, put, web, Error, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::cores::control::models::{CatalogError, ModelManager};
+use crate::meta::models::traits::{ModelAlias, ModelConfig};
+use crate::middleware::auth4manage::Auth4ManageMiddleware;
+
+// The model catalog: metadata of every model and its aliases, linked to the services serving it
+pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ManageMiddleware>) {
+    cfg.service(
+        web::scope("/v1/catalog")
+            .wrap(auth_middleware.clone()) // 应用中间件
+            .service(create_model)
+            .service(get_all_models)
+            .service(get_model)
+            .service(update_model)
+            .service(delete_model),
+    );
+    cfg.service(
+        web::scope("/v1/aliases")
+            .wrap(auth_middleware)
+            .service(get_all_aliases)
+            .service(get_alias)
+            .service(set_alias)
+            .service(delete_alias),
+    );
+}
+
+// 400, 404 and 409 for changes the catalog refuses, 500 for database errors
+pub fn catalog_error(err: Box<dyn std::error::Error>, message: &str) -> Error {
+    match err.downcast_ref::<CatalogError>() {
+        Some(CatalogError::Invalid(_)) => error::ErrorBadRequest(json!({
+            "code": 400,
+            "message": message,
+            "body": format!("{}", err)
+        })),
+        Some(CatalogError::NotFound(_)) => error::ErrorNotFound(json!({
+            "code": 404,
+            "message": message,
+            "body": format!("{}", err)
+        })),
+        Some(CatalogError::Conflict(_)) => error::ErrorConflict(json!({
+            "code": 409,
+            "message": message,
+            "body": format!("{}", err)
+        })),
+        None => error::ErrorInternalServerError(json!({
+            "code": 500,
+            "message": message,
+            "body": format!("{}", err)
+        })),
+    }
+}
+
+#[post("")]
+async fn create_model(
+    model: web::Json<ModelConfig>,
+) -> Result<impl Responder, Error> {
+    let model_manager = ModelManager::default();
+    model_manager.create_model(&model.into_inner())
+        .await
+        .map(|_| HttpResponse::Created().json(json!({
+            "code": 200,
+            "message": "Model created successfully.",
+            "body": null
+        })))
+        .map_err(|e| catalog_error(e, "Failed to create model."))
+}
+
+#[get("")]
+async fn get_all_models() -> Result<impl Responder, Error> {
+    let model_manager = ModelManager::default();
+    model_manager.get_all_models()
+        .await
+        .map(|models| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "All models get successfully.",
+            "body": models
+        })))
+        .map_err(|e| catalog_error(e, "Failed to get all models."))
+}
+
+#[get("/{id:.*}")]
+async fn get_model(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let model_manager = ModelManager::default();
+    model_manager.get_model(&id)
+        .await
+        .map(|model| match model {
+            Some(model) => HttpResponse::Ok().json(json!({
+                "code": 200,
+                "message": "Model get successfully.",
+                "body": model
+            })),
+            None => HttpResponse::NotFound().json(json!({
+                "code": 404,
+                "message": "Model not found.",
+                "body": null
+            })),
+        })
+        .map_err(|e| catalog_error(e, "Failed to get model."))
+}
+
+#[put("/{id:.*}")]
+async fn update_model(
+    id: web::Path<String>,
+    model: web::Json<ModelConfig>,
+) -> Result<impl Responder, Error> {
+    let mut updated_model = model.into_inner();
+    updated_model.model.id = id.clone();
+
+    let model_manager = ModelManager::default();
+    model_manager.update_model(&updated_model)
+        .await
+        .map(|_| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "Model updated successfully.",
+            "body": null
+        })))
+        .map_err(|e| catalog_error(e, "Failed to update model."))
+}
+
+#[delete("/{id:.*}")]
+async fn delete_model(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let model_manager = ModelManager::default();
+    model_manager.delete_model(&id)
+        .await
+        .map(|deleted| {
+            if deleted > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Model deleted successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Model not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to delete model."))
+}
+
+// ------------------------------------------ Aliases ------------------------------------------
+#[derive(Deserialize)]
+struct AliasTarget {
+    model: String,
+}
+
+#[get("")]
+async fn get_all_aliases() -> Result<impl Responder, Error> {
+    let model_manager = ModelManager::default();
+    model_manager.get_all_aliases()
+        .await
+        .map(|aliases| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "All aliases get successfully.",
+            "body": aliases
+        })))
+        .map_err(|e| catalog_error(e, "Failed to get all aliases."))
+}
+
+#[get("/{alias:.*}")]
+async fn get_alias(
+    alias: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let model_manager = ModelManager::default();
+    model_manager.get_alias(&alias)
+        .await
+        .map(|alias| match alias {
+            Some(alias) => HttpResponse::Ok().json(json!({
+                "code": 200,
+                "message": "Alias get successfully.",
+                "body": alias
+            })),
+            None => HttpResponse::NotFound().json(json!({
+                "code": 404,
+                "message": "Alias not found.",
+                "body": null
+            })),
+        })
+        .map_err(|e| catalog_error(e, "Failed to get alias."))
+}
+
+// Create the alias, or re-point it when it exists: `{"model": "Qwen2.5-7B-Instruct"}`
+#[put("/{alias:.*}")]
+async fn set_alias(
+    alias: web::Path<String>,
+    target: web::Json<AliasTarget>,
+) -> Result<impl Responder, Error> {
+    let alias = ModelAlias {
+        alias: alias.into_inner(),
+        model: target.into_inner().model,
+    };
+
+    let model_manager = ModelManager::default();
+    model_manager.set_alias(&alias)
+        .await
+        .map(|_| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "Alias set successfully.",
+            "body": alias
+        })))
+        .map_err(|e| catalog_error(e, "Failed to set alias."))
+}
+
+#[delete("/{alias:.*}")]
+async fn delete_alias(
+    alias: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let model_manager = ModelManager::default();
+    model_manager.delete_alias(&alias)
+        .await
+        .map(|deleted| {
+            if deleted > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Alias deleted successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Alias not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to delete alias."))
+}
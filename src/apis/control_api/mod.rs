@@ -1,8 +1,13 @@
 pub mod users;
 pub mod files;
 pub mod models;
+pub mod catalog;
 pub mod projects;
 pub mod invitation_code;
 pub mod schemas;
 pub mod services;
-pub mod model_limits;
\ No newline at end of file
+pub mod model_limits;
+pub mod traffic_splits;
+pub mod rate_limits;
+pub mod usage;
+pub mod budgets;
\ No newline at end of file
@@ -3,6 +3,7 @@ use actix_web::error::ErrorInternalServerError;
 use serde_json::json;
 use std::sync::Arc;
 
+use crate::apis::control_api::catalog::catalog_error;
 use crate::cores::control::model_limits::LimitsManager;
 use crate::meta::qos::traits::Limits;
 use crate::middleware::auth4manage::Auth4ManageMiddleware;
@@ -43,19 +44,12 @@ pub async fn create_model_limits(
         });
         HttpResponse::Created().json(create_model_limits_response)
     })
-    .map_err(|e| {
-        let error_response = json!({
-            "code": 500,
-            "message": "Failed to create Model limits object.",
-            "body": format!("{}", e),
-        });
-        ErrorInternalServerError(error_response)
-    })
+    .map_err(|e| catalog_error(e, "Failed to create Model limits object."))
 }
 
 #[utoipa::path(
     delete,
-    path = "/v1/limits/{model_name}",
+    path = "/v1/limits/{id}",
     responses(
         (status = 200, body = HttpResponse),
         (status = 404, body = ErrorResponse),
@@ -63,19 +57,28 @@ pub async fn create_model_limits(
     )
 )]
 
-#[delete("/{model_name}")]
+#[delete("/{id}")]
 async fn delete_model_limits(
-    model_name: web::Path<String>,
+    id: web::Path<String>,
 ) -> Result<impl Responder, Error> {
     let limits_manager = LimitsManager::default();
-    limits_manager.delete_limits_object(&model_name).await
-    .map(|_| {
-        let delete_model_limits_response = json!({
-            "code": 200,
-            "message": "Model limits object deleted successfully.",
-            "body": null
-        });
-        HttpResponse::Ok().json(delete_model_limits_response)
+    limits_manager.delete_limits_object(&id).await
+    .map(|deleted| {
+        if deleted > 0 {
+            let delete_model_limits_response = json!({
+                "code": 200,
+                "message": "Model limits object deleted successfully.",
+                "body": null
+            });
+            HttpResponse::Ok().json(delete_model_limits_response)
+        } else {
+            let error_response = json!({
+                "code": 404,
+                "message": "Model limits object not found.",
+                "body": null,
+            });
+            HttpResponse::NotFound().json(error_response)
+        }
     })
     .map_err(|e| {
         let error_response = json!({
@@ -87,12 +90,16 @@ async fn delete_model_limits(
     })
 }
 
-#[put("/{model_name:.*}")]
+#[put("/{id}")]
 async fn update_model_limits(
+    id: web::Path<String>,
     limits: web::Json<Limits>,
 ) -> Result<impl Responder, Error> {
+    let mut limits = limits.into_inner();
+    limits.id = id.into_inner();
+
     let limits_manager = LimitsManager::default();
-    limits_manager.update_limits_object(limits.into_inner()).await
+    limits_manager.update_limits_object(limits).await
     .map(|rows_updated| {
         if rows_updated > 0{
             let update_model_limits_response = json!({
@@ -110,14 +117,7 @@ async fn update_model_limits(
             HttpResponse::NotFound().json(error_response)
         }
     })
-    .map_err(|e| {
-        let error_response = json!({
-            "code": 500,
-            "message": "Failed to update Model limits object.",
-            "body": format!("{}", e),
-        });
-        ErrorInternalServerError(error_response)
-    })
+    .map_err(|e| catalog_error(e, "Failed to update Model limits object."))
 }
 
 #[utoipa::path(
@@ -154,7 +154,7 @@ async fn get_all_model_limits() -> Result<impl Responder, Error> {
 
 #[utoipa::path(
     get,
-    path = "/v1/limits/{model_name}",
+    path = "/v1/limits/{id}",
     responses(
         (status = 200, body = HttpResponse),
         (status = 404, body = ErrorResponse),
@@ -162,13 +162,13 @@ async fn get_all_model_limits() -> Result<impl Responder, Error> {
     )
 )]
 
-// get https://***/v1/limits/{model_name}
-#[get("/{model_name:.*}")]
+// get https://***/v1/limits/{id}
+#[get("/{id}")]
 async fn get_model_limits(
-    model_name: web::Path<String>,
+    id: web::Path<String>,
 ) -> Result<impl Responder, Error> {
     let limits_manager = LimitsManager::default();
-    limits_manager.get_limits_object(&model_name).await
+    limits_manager.get_limits_object(&id).await
     .map(|limit|match limit {
         Some(limit) => {
             let response = json!({
@@ -1,11 +1,12 @@
-use actix_web::{get, delete, web, HttpResponse, Responder};
+use actix_web::{get, web, HttpMessage, HttpRequest, HttpResponse, Responder};
 use serde_json::json;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use std::sync::Arc;
 
-use crate::cores::models::{get_model, get_models};
-use crate::middleware::auth4manage::Auth4ManageMiddleware;
+use crate::cores::models::list_served_models;
+use crate::middleware::auth4model::Auth4ModelMiddleware;
+use crate::middleware::model_list_auth::{ModelGrants, ModelListAuthMiddleware};
 
 #[derive(Deserialize,Serialize,ToSchema)]
 pub struct ModelErrorDetails {
@@ -19,13 +20,14 @@ pub struct ModelErrorName {
     pub model_name: String,
 }
 
-pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ManageMiddleware>) {
+// Listing and retrieving models is open to every api key (model-side auth),
+// the catalog behind it is managed through `/v1/catalog`
+pub fn configure(cfg: &mut web::ServiceConfig, auth_model: Arc<Auth4ModelMiddleware>) {
     cfg.service(
         web::scope("/v1/models")
-            .wrap(auth_middleware) // 应用中间件
+            .wrap(ModelListAuthMiddleware::new(auth_model.cache.clone())) // 应用中间件
             .service(models)
-            .service(model_info)
-            .service(delete_model),
+            .service(model_info),
     );
 }
 
@@ -33,82 +35,45 @@ pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ManageM
     get,  // 请求方法
     path = "/v1/models",  // 路径
     responses(
-        (status = 200, body = Vec<Model>),
-        (status = 500, body = ModelErrorDetails)
+        (status = 200, body = ModelList),
     )  // 响应内容
 )]
 
 // Lists the currently available models, and provides basic information about each one such as the owner and availability.
+// Only the models the caller's api key has been granted are listed.
 #[get("")]
-pub async fn models() -> impl Responder {
-    match get_models().await {
-        Ok(models) => {
-            // 成功获取模型数据，返回 JSON 响应
-            HttpResponse::Ok().json(models)
-        }
-        Err(err) => {
-            // 处理错误，返回 500 状态码和错误信息
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch models",
-                "details": err.to_string()
-            }))
-        }
-    }
+pub async fn models(req: HttpRequest) -> impl Responder {
+    let grants = req.extensions().get::<ModelGrants>().cloned();
+    HttpResponse::Ok().json(list_served_models(grants.as_ref()))
 }
 
 #[utoipa::path(
     get,  // 请求方法
     path = "/v1/models/{model}",  // 路径
     responses(
-        (status = 200, body = Model),
+        (status = 200, body = ModelCard),
         (status = 404, body = ModelErrorName),
-        (status = 500, body = ModelErrorDetails)
     )  // 响应内容
     //params(("model_name",),)
 )]
 
 // Retrieves a model instance, providing basic information about the model such as the owner and permissioning.
-#[get("/{model}")]
-pub async fn model_info(path: web::Path<String>) -> impl Responder {
+#[get("/{model:.*}")]
+pub async fn model_info(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     let model_name = path.into_inner(); // 提取路径参数
-    // 调用封装的函数查询指定模型
-    match get_model(&model_name).await {
-        Ok(Some(model)) => {
-            // 查询成功，返回模型信息
-            HttpResponse::Ok().json(model)
-        }
-        Ok(None) => {
-            // 查询结果为空，返回 404
+    let grants = req.extensions().get::<ModelGrants>().cloned();
+    let model = list_served_models(grants.as_ref())
+        .data
+        .into_iter()
+        .find(|model| model.id == model_name);
+    match model {
+        Some(model) => HttpResponse::Ok().json(model),
+        None => {
+            // 模型不存在或未授权，返回 404
             HttpResponse::NotFound().json(json!({
                 "error": "Model not found",
                 "model_name": model_name
             }))
         }
-        Err(err) => {
-            // 查询出现错误，返回 500
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch model info",
-                "details": err.to_string()
-            }))
-        }
     }
 }
-
-#[utoipa::path(
-    delete,  // 请求方法
-    path = "/v1/models/{model}",  // 路径
-    responses(
-        (status = 501, description = "Not implemented", body = String)
-    )  // 响应内容
-    //params(("model_name",),)
-)]
-
-// Delete a fine-tuned model. You must have the Owner role in your organization to delete a model.
-// And we don't support this feature now.
-#[delete("/{model}")]
-pub async fn delete_model(path: web::Path<String>) -> impl Responder {
-    let model_name = path.into_inner();
-    // log the model name
-    println!("Deleting model: {}", model_name);
-    HttpResponse::NotImplemented().body("Not implemented")
-}
\ No newline at end of file
@@ -0,0 +1,125 @@
+use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::apis::control_api::catalog::catalog_error;
+use crate::cores::control::rate_limits::RateLimitManager;
+use crate::meta::rate_limits::traits::RateLimit;
+use crate::middleware::auth4manage::Auth4ManageMiddleware;
+
+// Request rate limits per api key, account, model or client ip, enforced by `RateLimitMiddleware`
+pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ManageMiddleware>) {
+    cfg.service(
+        web::scope("/v1/rate-limits")
+            .wrap(auth_middleware) // 应用中间件
+            .service(create_rate_limit)
+            .service(get_all_rate_limits)
+            .service(get_rate_limit)
+            .service(update_rate_limit)
+            .service(delete_rate_limit),
+    );
+}
+
+#[post("")]
+async fn create_rate_limit(
+    limit: web::Json<RateLimit>,
+) -> Result<impl Responder, Error> {
+    let limit_manager = RateLimitManager::default();
+    limit_manager.create_rate_limit(&limit.into_inner())
+        .await
+        .map(|_| HttpResponse::Created().json(json!({
+            "code": 200,
+            "message": "Rate limit created successfully.",
+            "body": null
+        })))
+        .map_err(|e| catalog_error(e, "Failed to create rate limit."))
+}
+
+#[get("")]
+async fn get_all_rate_limits() -> Result<impl Responder, Error> {
+    let limit_manager = RateLimitManager::default();
+    limit_manager.get_all_rate_limits()
+        .await
+        .map(|limits| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "All traffic limits get successfully.",
+            "body": limits
+        })))
+        .map_err(|e| catalog_error(e, "Failed to get all traffic limits."))
+}
+
+#[get("/{id}")]
+async fn get_rate_limit(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let limit_manager = RateLimitManager::default();
+    limit_manager.get_rate_limit(&id)
+        .await
+        .map(|limit| match limit {
+            Some(limit) => HttpResponse::Ok().json(json!({
+                "code": 200,
+                "message": "Rate limit get successfully.",
+                "body": limit
+            })),
+            None => HttpResponse::NotFound().json(json!({
+                "code": 404,
+                "message": "Rate limit not found.",
+                "body": null
+            })),
+        })
+        .map_err(|e| catalog_error(e, "Failed to get rate limit."))
+}
+
+#[put("/{id}")]
+async fn update_rate_limit(
+    id: web::Path<String>,
+    limit: web::Json<RateLimit>,
+) -> Result<impl Responder, Error> {
+    let mut updated_limit = limit.into_inner();
+    updated_limit.id = id.clone();
+
+    let limit_manager = RateLimitManager::default();
+    limit_manager.update_rate_limit(&updated_limit)
+        .await
+        .map(|updated| {
+            if updated > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Rate limit updated successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Rate limit not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to update rate limit."))
+}
+
+#[delete("/{id}")]
+async fn delete_rate_limit(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let limit_manager = RateLimitManager::default();
+    limit_manager.delete_rate_limit(&id)
+        .await
+        .map(|deleted| {
+            if deleted > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Rate limit deleted successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Rate limit not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to delete rate limit."))
+}
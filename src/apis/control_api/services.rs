@@ -3,6 +3,8 @@ use serde_json::json;
 use std::sync::Arc;
 
 use crate::cores::control::services::ServiceManager;
+use crate::cores::control::health::{get_health, remove_health};
+use crate::cores::control::route_cache::refresh_route_table;
 use crate::meta::services::traits::ServiceConfig;
 use crate::middleware::auth4manage::Auth4ManageMiddleware;
 use crate::middleware::auth4model::Auth4ModelMiddleware;
@@ -73,11 +75,16 @@ async fn get_service(
     service_manager.get_service(&id)
         .await
         .map(|service| match service {
-            Some(service) => HttpResponse::Ok().json(json!({
-                "code": 200,
-                "message": "Service get successfully.",
-                "body": service
-            })),
+            Some(service) => {
+                // 附带熔断器状态
+                let mut body = json!(service);
+                body["health"] = json!(get_health(&service.id));
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Service get successfully.",
+                    "body": body
+                }))
+            }
             None => HttpResponse::NotFound().json(json!({
                 "code": 404,
                 "message": "Service not found.",
@@ -152,6 +159,7 @@ async fn delete_service(
     id: web::Path<String>,
 ) -> Result<impl Responder, Error> {
     let service_manager = ServiceManager::default();
+    remove_health(&id);
     service_manager.delete_service(&id)
         .await
         .map(|delete_num| 
@@ -222,10 +230,25 @@ async fn invalidate_cache(
                 }))
             }
         }
+        "route" => {
+            // 重新加载路由表 (services 与 key 授权)
+            match refresh_route_table().await {
+                Ok(_) => HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Route table reloaded successfully.",
+                    "body": null
+                })),
+                Err(err) => HttpResponse::InternalServerError().json(json!({
+                    "code": 500,
+                    "message": "Failed to reload route table.",
+                    "body": format!("{}", err)
+                })),
+            }
+        }
         _ => {
             HttpResponse::BadRequest().json(json!({
                 "code": 400,
-                "message": "Invalid cache type. Must be either 'manage' or 'model'.",
+                "message": "Invalid cache type. Must be one of 'manage', 'model' or 'route'.",
                 "body": null
             }))
         }
@@ -0,0 +1,126 @@
+use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::apis::control_api::catalog::catalog_error;
+use crate::cores::control::traffic_splits::TrafficSplitManager;
+use crate::meta::traffic_splits::traits::TrafficSplit;
+use crate::middleware::auth4manage::Auth4ManageMiddleware;
+
+// Traffic split rules for canary releases and A/B tests between model versions
+pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ManageMiddleware>) {
+    cfg.service(
+        web::scope("/v1/traffic-splits")
+            .wrap(auth_middleware) // 应用中间件
+            .service(create_split)
+            .service(get_all_splits)
+            .service(get_split)
+            .service(update_split)
+            .service(delete_split),
+    );
+}
+
+#[post("")]
+async fn create_split(
+    split: web::Json<TrafficSplit>,
+) -> Result<impl Responder, Error> {
+    let split_manager = TrafficSplitManager::default();
+    split_manager.create_split(&split.into_inner())
+        .await
+        .map(|_| HttpResponse::Created().json(json!({
+            "code": 200,
+            "message": "Traffic split created successfully.",
+            "body": null
+        })))
+        .map_err(|e| catalog_error(e, "Failed to create traffic split."))
+}
+
+#[get("")]
+async fn get_all_splits() -> Result<impl Responder, Error> {
+    let split_manager = TrafficSplitManager::default();
+    split_manager.get_all_splits()
+        .await
+        .map(|splits| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "All traffic splits get successfully.",
+            "body": splits
+        })))
+        .map_err(|e| catalog_error(e, "Failed to get all traffic splits."))
+}
+
+#[get("/{id}")]
+async fn get_split(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let split_manager = TrafficSplitManager::default();
+    split_manager.get_split(&id)
+        .await
+        .map(|split| match split {
+            Some(split) => HttpResponse::Ok().json(json!({
+                "code": 200,
+                "message": "Traffic split get successfully.",
+                "body": split
+            })),
+            None => HttpResponse::NotFound().json(json!({
+                "code": 404,
+                "message": "Traffic split not found.",
+                "body": null
+            })),
+        })
+        .map_err(|e| catalog_error(e, "Failed to get traffic split."))
+}
+
+// Change the percentage or the listed keys to move a rollout forward, set `enabled` to false to stop it
+#[put("/{id}")]
+async fn update_split(
+    id: web::Path<String>,
+    split: web::Json<TrafficSplit>,
+) -> Result<impl Responder, Error> {
+    let mut updated_split = split.into_inner();
+    updated_split.id = id.clone();
+
+    let split_manager = TrafficSplitManager::default();
+    split_manager.update_split(&updated_split)
+        .await
+        .map(|updated| {
+            if updated > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Traffic split updated successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Traffic split not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to update traffic split."))
+}
+
+#[delete("/{id}")]
+async fn delete_split(
+    id: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let split_manager = TrafficSplitManager::default();
+    split_manager.delete_split(&id)
+        .await
+        .map(|deleted| {
+            if deleted > 0 {
+                HttpResponse::Ok().json(json!({
+                    "code": 200,
+                    "message": "Traffic split deleted successfully.",
+                    "body": null
+                }))
+            } else {
+                HttpResponse::NotFound().json(json!({
+                    "code": 404,
+                    "message": "Traffic split not found.",
+                    "body": null
+                }))
+            }
+        })
+        .map_err(|e| catalog_error(e, "Failed to delete traffic split."))
+}
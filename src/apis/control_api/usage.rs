@@ -0,0 +1,69 @@
+use actix_web::{get, web, Error, HttpResponse, Responder};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::apis::control_api::catalog::catalog_error;
+use crate::cores::usage::delivery_stats;
+use crate::cores::usage::records::{usage_csv, usage_query, UsageParams, UsageRecordsManager};
+use crate::middleware::auth4manage::Auth4ManageMiddleware;
+
+// Token usage of finished requests summed from `usage_records`, and the delivery of usage events to the configured sink
+pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ManageMiddleware>) {
+    cfg.service(
+        web::scope("/v1/usage")
+            .wrap(auth_middleware) // 应用中间件
+            .service(get_usage_summary)
+            .service(export_usage)
+            .service(get_delivery_stats),
+    );
+}
+
+// Such as /v1/usage/summary?account_id=111111&model=Qwen2.5-7B-Instruct&start=2024-01-01&end=2024-01-02&utc_offset=480
+// or /v1/usage/summary?group_by=account,model&bucket=day
+#[get("/summary")]
+async fn get_usage_summary(
+    params: web::Query<UsageParams>,
+) -> Result<impl Responder, Error> {
+    let query = usage_query(&params).map_err(|e| catalog_error(Box::new(e), "Failed to get usage summary."))?;
+    let usage_manager = UsageRecordsManager::default();
+    usage_manager.aggregate_usage(&query)
+        .await
+        .map(|usage| HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "Usage summary get successfully.",
+            "body": usage
+        })))
+        .map_err(|e| catalog_error(e, "Failed to get usage summary."))
+}
+
+// The summary as a CSV file, one row per group
+#[get("/export")]
+async fn export_usage(
+    params: web::Query<UsageParams>,
+) -> Result<impl Responder, Error> {
+    let query = usage_query(&params).map_err(|e| catalog_error(Box::new(e), "Failed to export usage."))?;
+    let usage_manager = UsageRecordsManager::default();
+    usage_manager.aggregate_usage(&query)
+        .await
+        .map(|usage| HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(("Content-Disposition", "attachment; filename=\"usage.csv\""))
+            .body(usage_csv(&usage, &query)))
+        .map_err(|e| catalog_error(e, "Failed to export usage."))
+}
+
+#[get("/delivery")]
+async fn get_delivery_stats() -> Result<impl Responder, Error> {
+    Ok(match delivery_stats().await {
+        Some(stats) => HttpResponse::Ok().json(json!({
+            "code": 200,
+            "message": "Usage delivery stats get successfully.",
+            "body": stats
+        })),
+        None => HttpResponse::ServiceUnavailable().json(json!({
+            "code": 503,
+            "message": "Usage pipeline is not started.",
+            "body": null
+        })),
+    })
+}
@@ -1,15 +1,18 @@
 use actix_web::{get, post, web, Error, HttpResponse, Responder, HttpRequest, HttpMessage};
-use actix_web::error::ErrorBadRequest;
 use log::{info, error};
 use std::sync::Arc;
 
 use crate::cores::chat_models::chat_controller::ChatCompletionRequest;
 use crate::apis::schemas::ErrorResponse;
 
-use crate::cores::chat_models::chat_controller::Completions;
-use crate::cores::chat_models::support_models::{qwen, glm, llama, bailian, deepseek};
+use crate::cores::chat_models::chat_router::{self, RouteContext};
+use crate::cores::chat_models::chat_utils::{chat_prompt_tokens, check_context_window, check_multimodal_limits, requested_completion_tokens};
+use crate::utils::tokenizer::{has_tokenizer, prepare_tokenizer};
+use crate::cores::control::route_cache::route_table;
+use crate::cores::control::model_limits::PROJECT_HEADER;
+use crate::GLOBAL_CONFIG;
 use crate::middleware::auth4model::Auth4ModelMiddleware;
-use crate::middleware::qos::Qos;
+use crate::middleware::qos::{PromptTokens, Qos, QuotaTokens};
 use crate::utils::log::log_request;
 
 pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ModelMiddleware>, qos: Arc<Qos>) {
@@ -40,21 +43,6 @@ pub async fn health() -> impl Responder {
     "OK"
 }
 
-// define an interface layer that calls the completions method of the large model
-struct LLM {
-    model: Box<dyn Completions>,
-}
-
-impl LLM {
-    fn new(model: Box<dyn Completions>) -> Self {
-        LLM { model }
-    }
-
-    async fn completions(&self, req_body: web::Json<ChatCompletionRequest>, userid: String, appkey: String) -> Result<HttpResponse, Error> {
-        self.model.completions(req_body, userid, appkey).await
-    }
-}
-
 #[utoipa::path(
     post,  // 请求方法
     path = "/v1/chat/completions",  // 路径
@@ -79,25 +67,39 @@ pub async fn completions(req: HttpRequest, req_body: web::Json<ChatCompletionReq
         };
         return Ok(HttpResponse::BadRequest().json(error_response));
     }
-
-    // 2. Parse the model name and series from the model field (Qwen/Qwen2.5-7B-Instruct)
-    let parts: Vec<&str> = req_body.model.split('/').collect();
-    if parts.len() != 2 {
-        return Err(ErrorBadRequest("Invalid model format"));
+    // Limits are configured for catalog models, an alias uses the limits of the model it points to
+    let limits_model = route_table().canonical_model(&req_body.model).to_string();
+    if let Some(limit) = GLOBAL_CONFIG.multimodal_limits.get(&limits_model) {
+        if let Err(err) = check_multimodal_limits(&req_body, limit) {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse { error: err }));
+        }
+    }
+    // The prompt is counted once per request, by the QoS middleware when it checks the token quotas
+    let counted = req.extensions().get::<PromptTokens>().map(|tokens| tokens.0);
+    prepare_tokenizer(&limits_model).await;
+    let prompt_tokens = counted.unwrap_or_else(|| chat_prompt_tokens(&limits_model, &req_body));
+    // Prompts that can't fit the context window are rejected here, when the model's tokenizer counts them exactly
+    if has_tokenizer(&limits_model) {
+        let context_length = route_table().model(&limits_model).map(|model| model.context_length).unwrap_or(0);
+        if let Err(err) = check_context_window(context_length, prompt_tokens, requested_completion_tokens(&req_body)) {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse { error: err }));
+        }
     }
 
-    //  3. Call the underlying API and return a unified data format
-    let model: LLM = match parts[0] {
-        "Qwen" => LLM::new(Box::new(qwen::Qwen {model_name: parts[1].to_string()})),
-        "GLM" => LLM::new(Box::new(glm::GLM {model_name: parts[1].to_string()})),
-        "meta-llama" => LLM::new(Box::new(llama::Llama {model_name: parts[1].to_string()})),
-        "Bailian" => LLM::new(Box::new(bailian::Bailian {model_name: parts[1].to_string()})),
-        "deepseek-ai" => LLM::new(Box::new(deepseek::DeepSeek {model_name: parts[1].to_string()})),
-        _ => return Err(ErrorBadRequest(format!("Unsupported {} model series!", parts[0]))),
-    };
-
-    // 4. Send the request to the model service
-    let response = model.completions(req_body, userid, appkey).await;
+    // 2. Route the request to the service registered for the model and return a unified data format
+    let quota_tokens = req.extensions().get::<QuotaTokens>().map(|tokens| tokens.0);
+    let api_key = req.headers().get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.replace("Bearer ", ""))
+        .unwrap_or_default();
+    let project = req.headers().get(PROJECT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let ctx = RouteContext::new(api_key, &userid, req_body.user.as_deref())
+        .with_project(project)
+        .with_prompt_tokens(prompt_tokens)
+        .with_quota_tokens(quota_tokens);
+    let response = chat_router::completions(req_body.into_inner(), userid, appkey, ctx).await;
     match response {
         Ok(resp) => {
             info!(target: "access_log", "{}", log_request(req.clone(),  resp.status().as_u16(), None).await.unwrap());
@@ -0,0 +1,92 @@
+use actix_web::{post, web, Error, HttpResponse, Responder, HttpRequest, HttpMessage};
+use log::{info, error};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::cores::chat_models::chat_controller::TextCompletionRequest;
+use crate::apis::schemas::ErrorResponse;
+
+use crate::cores::chat_models::chat_router::{self, RouteContext};
+use crate::cores::chat_models::chat_utils::{check_context_window, text_prompt_tokens};
+use crate::cores::control::route_cache::route_table;
+use crate::utils::tokenizer::{has_tokenizer, prepare_tokenizer};
+use crate::cores::control::model_limits::PROJECT_HEADER;
+use crate::middleware::auth4model::Auth4ModelMiddleware;
+use crate::middleware::qos::{PromptTokens, Qos, QuotaTokens};
+use crate::utils::log::log_request;
+
+pub fn configure(cfg: &mut web::ServiceConfig, auth_middleware: Arc<Auth4ModelMiddleware>, qos: Arc<Qos>) {
+    cfg.service(
+        web::scope("/v1/completions")
+            .wrap(qos)
+            .wrap(auth_middleware)
+            .service(completions)
+    );
+}
+
+#[utoipa::path(
+    post,  // 请求方法
+    path = "/v1/completions",  // 路径
+    request_body = TextCompletionRequest,
+    responses(
+        (status = 200, body = TextCompletionsResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse),
+    )  // 响应内容
+)]
+
+#[post("")]
+pub async fn completions(req: HttpRequest, req_body: web::Json<TextCompletionRequest>) -> Result<impl Responder, Error> {
+    let appkey = "".to_string();
+    let userid = req.extensions().get::<String>().cloned().unwrap_or_else(|| "".to_string());
+
+    // 1. Validate that required fields exist in the request data
+    let empty_prompt = match &req_body.prompt {
+        Value::String(prompt) => prompt.is_empty(),
+        Value::Array(prompts) => prompts.is_empty(),
+        _ => true,
+    };
+    if req_body.model.is_empty() || empty_prompt {
+        let error_response = ErrorResponse {
+            error: "Invalid request: model or prompt cannot be empty.".into(),
+        };
+        return Ok(HttpResponse::BadRequest().json(error_response));
+    }
+    // The prompt is counted once per request, by the QoS middleware when it checks the token quotas
+    let limits_model = route_table().canonical_model(&req_body.model).to_string();
+    let counted = req.extensions().get::<PromptTokens>().map(|tokens| tokens.0);
+    prepare_tokenizer(&limits_model).await;
+    let prompt_tokens = counted.unwrap_or_else(|| text_prompt_tokens(&limits_model, &req_body));
+    // Prompts that can't fit the context window are rejected here, when the model's tokenizer counts them exactly
+    if has_tokenizer(&limits_model) {
+        let context_length = route_table().model(&limits_model).map(|model| model.context_length).unwrap_or(0);
+        if let Err(err) = check_context_window(context_length, prompt_tokens, req_body.max_tokens) {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse { error: err }));
+        }
+    }
+
+    // 2. Route the request to the service registered for the model and return a unified data format
+    let quota_tokens = req.extensions().get::<QuotaTokens>().map(|tokens| tokens.0);
+    let api_key = req.headers().get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.replace("Bearer ", ""))
+        .unwrap_or_default();
+    let project = req.headers().get(PROJECT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let ctx = RouteContext::new(api_key, &userid, req_body.user.as_deref())
+        .with_project(project)
+        .with_prompt_tokens(prompt_tokens)
+        .with_quota_tokens(quota_tokens);
+    let response = chat_router::text_completions(req_body.into_inner(), userid, appkey, ctx).await;
+    match response {
+        Ok(resp) => {
+            info!(target: "access_log", "{}", log_request(req.clone(),  resp.status().as_u16(), None).await.unwrap());
+            Ok(resp)
+        }
+        Err(err) => {
+            error!(target: "error_log", "{}", log_request(req.clone(), err.as_response_error().status_code().as_u16(), Some(&format!("{}", err))).await.unwrap());
+            Err(err)
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod schemas;
 pub mod chat;
+pub mod completions;
 pub mod embeddings;
 pub mod image;
\ No newline at end of file
@@ -1,9 +1,12 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::{File, metadata};
 use std::io::Read;
 use once_cell::sync::Lazy;
 use serde_yaml;
 
+use crate::cores::control::balancer::Strategy;
+
 // ---------------------------------------------- Server Config ----------------------------------------------
 // ChatChat API
 #[derive(Debug, Deserialize, Clone)]
@@ -58,6 +61,14 @@ pub fn load_server_config() -> Result<ServerConfig, Box<dyn std::error::Error>>
     Ok(config)
 }
 
+// Per-model limits on the images of a multimodal chat request
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MultimodalLimit {
+    pub max_images: Option<usize>,          // max number of image parts over all messages
+    pub max_image_bytes: Option<usize>,     // max decoded size of one inline (base64 data url) image
+}
+
 // ---------------------------------------------- Config ----------------------------------------------
 #[derive(Debug, Deserialize, Clone)]
 #[serde(default)]
@@ -76,6 +87,9 @@ pub struct Config {
     pub auth_remote_enabled: bool,
     pub auth_remote_server: String,
     pub coil_enabled: bool,
+    pub quota_engine: String,
+    pub quota_shared: bool,
+    pub quota_fail_closed: bool,
     pub cloud_region_id: String,
     pub cloud_region_name: String,
     pub server_cert_file: String,
@@ -86,6 +100,44 @@ pub struct Config {
     pub auth_cache_time: u64,
     pub auth_cache_capacity: usize,
     pub localuserid: String,
+    pub health_check_enabled: bool,
+    pub health_check_interval: u64,
+    pub health_check_timeout: u64,
+    pub breaker_failure_threshold: u32,
+    pub breaker_open_secs: u64,
+    pub load_balance_strategy: String,
+    pub load_balance_models: HashMap<String, String>,
+    pub upstream_pool_max_idle: usize,
+    pub upstream_pool_idle_timeout: u64,
+    pub upstream_http2: bool,
+    pub upstream_connect_timeout: u64,
+    pub upstream_request_timeout: u64,
+    pub upstream_read_timeout: u64,
+    pub route_cache_ttl: u64,
+    pub multimodal_limits: HashMap<String, MultimodalLimit>,
+    pub tokenizer_dir: String,
+    pub usage_sink: String,
+    pub usage_file_path: String,
+    pub usage_webhook_url: String,
+    pub usage_kafka_brokers: Vec<String>,
+    pub usage_kafka_topic: String,
+    pub usage_kafka_tls: bool,
+    pub usage_kafka_ca_file: String,
+    pub usage_kafka_sasl_mechanism: String,
+    pub usage_kafka_username: String,
+    pub usage_kafka_password: String,
+    pub usage_sink_timeout: u64,
+    pub usage_queue_size: usize,
+    pub usage_batch_size: usize,
+    pub usage_flush_interval_ms: u64,
+    pub usage_retry_times: u32,
+    pub usage_retry_backoff_ms: u64,
+    pub usage_spool_dir: String,
+    pub usage_spool_max_bytes: u64,
+    pub usage_records_enabled: bool,
+    pub budget_timezone: String,
+    pub budget_refresh_secs: u64,
+    pub budget_webhook_url: String,
 }
 
 impl Default for Config {
@@ -105,6 +157,9 @@ impl Default for Config {
             auth_remote_enabled: false,
             auth_remote_server: "".to_string(),
             coil_enabled: false,
+            quota_engine: "coil".to_string(),
+            quota_shared: false,
+            quota_fail_closed: false,
             cloud_region_id: "".to_string(),
             cloud_region_name: "".to_string(),
             server_cert_file: "/etc/chatig/https/server_cert_file.crt".to_string(),
@@ -116,13 +171,56 @@ impl Default for Config {
             connections_per_server: 32,
             auth_cache_time: 1200,
             auth_cache_capacity: 3000,
-            localuserid: "111111".to_string()
+            localuserid: "111111".to_string(),
+            health_check_enabled: true,
+            health_check_interval: 10,
+            health_check_timeout: 3,
+            breaker_failure_threshold: 3,
+            breaker_open_secs: 30,
+            load_balance_strategy: "random".to_string(),
+            load_balance_models: HashMap::new(),
+            upstream_pool_max_idle: 64,
+            upstream_pool_idle_timeout: 90,
+            upstream_http2: false,
+            upstream_connect_timeout: 10,
+            upstream_request_timeout: 300,
+            upstream_read_timeout: 60,
+            route_cache_ttl: 30,
+            multimodal_limits: HashMap::new(),
+            tokenizer_dir: "".to_string(),
+            usage_sink: "log".to_string(),
+            usage_file_path: "/var/log/chatig/usage.jsonl".to_string(),
+            usage_webhook_url: "".to_string(),
+            usage_kafka_brokers: Vec::new(),
+            usage_kafka_topic: "chatig-usage".to_string(),
+            usage_kafka_tls: false,
+            usage_kafka_ca_file: "".to_string(),
+            usage_kafka_sasl_mechanism: "".to_string(),
+            usage_kafka_username: "".to_string(),
+            usage_kafka_password: "".to_string(),
+            usage_sink_timeout: 10,
+            usage_queue_size: 10000,
+            usage_batch_size: 100,
+            usage_flush_interval_ms: 1000,
+            usage_retry_times: 3,
+            usage_retry_backoff_ms: 500,
+            usage_spool_dir: "/var/lib/chatig/usage_spool".to_string(),
+            usage_spool_max_bytes: 1073741824,
+            usage_records_enabled: true,
+            budget_timezone: "Asia/Shanghai".to_string(),
+            budget_refresh_secs: 60,
+            budget_webhook_url: "".to_string(),
         }
     }
 }
 
 
 impl Config {
+    // Whether `model_limits` are enforced, by the coil servers or by the local quota engine
+    pub fn quota_enabled(&self) -> bool {
+        self.quota_engine == "local" || self.coil_enabled
+    }
+
     pub fn load_config() -> Config {
         let config_path = if metadata("/etc/chatig/configs.yaml").is_ok() {
             "/etc/chatig/configs.yaml"
@@ -132,7 +230,20 @@ impl Config {
         let mut file = File::open(config_path).expect("Failed to open config file");
         let mut contents = String::new();
         file.read_to_string(&mut contents).expect("Failed to read config file");
-        serde_yaml::from_str(&contents).expect("Failed to parse config file")
+        let config: Config = serde_yaml::from_str(&contents).expect("Failed to parse config file");
+        if let Err(err) = config.check() {
+            panic!("Invalid config file {}: {}", config_path, err);
+        }
+        config
+    }
+
+    // Settings that parse but can't be used
+    pub fn check(&self) -> Result<(), String> {
+        Strategy::parse(&self.load_balance_strategy).map_err(|err| format!("load_balance_strategy: {}", err))?;
+        for (model, name) in &self.load_balance_models {
+            Strategy::parse(name).map_err(|err| format!("load_balance_models of {}: {}", model, err))?;
+        }
+        Ok(())
     }
 }
 
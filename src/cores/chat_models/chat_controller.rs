@@ -1,49 +1,195 @@
-use actix_web::{web, Error, HttpResponse};
 use async_trait::async_trait;
 use core::str;
+use reqwest::Response;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use utoipa::ToSchema;
 
+use crate::meta::services::traits::ServiceConfig;
+
 // ==================================================== Completion Request Struct ====================================================
 #[derive(Deserialize, Serialize, ToSchema, Clone, Debug)]
 pub struct ChatCompletionRequest {
     pub model: String,                      // (Required) Name of the model used
     pub messages: Vec<Message>,             // (Required) List of messages, each message must contain `role` and `content`.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,           // Controls the creativity of the generated text.
-    #[allow(dead_code)]
-    pub top_p: Option<u32>,                 // An alternative sampling method to `temperature`. `top_p` selects tokens based on cumulative probability.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,                 // An alternative sampling method to `temperature`. `top_p` selects tokens based on cumulative probability.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub n: Option<u32>,                     // Number of generated responses.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,               // Whether to enable streaming response. If `true`, the response will return parts of the content incrementally.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stop: Option<Vec<String>>,          // Strings that stop the generation, supports an array of strings.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,            // Maximum number of tokens generated per request.
-    #[allow(dead_code)]
-    pub presence_penalty: Option<i32>,      // Encourages the model to talk about new topics. Value ranges from `-2.0` to `2.0`.
-    #[allow(dead_code)]
-    pub frequency_penalty: Option<i32>,     // Controls the likelihood of generating repetitive tokens. Value ranges from `-2.0` to `2.0`, positive values reduce repetition.
-    #[allow(dead_code)]
-    pub logit_bias: Option<i32>,            // Adjusts the probability of specific tokens appearing. Value ranges from `-100` to `100`.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,      // Encourages the model to talk about new topics. Value ranges from `-2.0` to `2.0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,     // Controls the likelihood of generating repetitive tokens. Value ranges from `-2.0` to `2.0`, positive values reduce repetition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<String, f32>>, // Maps token ids to a bias added to their logits. Values range from `-100` to `100`.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,               // User ID to identify the source of the request.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stream_options: Option<StreamOptions>, // Stream options for the request.
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub file_id: Option<String>,            // File ID to identify the file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,           // Tools the model may call, currently only functions are supported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,    // Controls which (if any) tool is called by the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,  // Whether to enable parallel function calling during tool use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>, // Format the model must output, e.g. `json_object` or `json_schema`.
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extra: Map<String, Value>,          // Any other parameter, e.g. `seed`, `logprobs` or vLLM `guided_json`, forwarded verbatim.
 }
 
 #[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
 pub struct Message {
-    pub role: String,
-    pub content: String,
+    pub role: String,                       // `system`, `user`, `assistant` or `tool`.
+    #[serde(default)]
+    pub content: Option<MessageContent>,    // A string or an array of content parts, null for assistant messages that only carry `tool_calls`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,               // Optional name of the participant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,  // Tool calls generated by the model, for `assistant` messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,       // Tool call that this message is responding to, for `tool` messages.
+}
+
+impl Message {
+    // A plain text message without any tool calling fields
+    pub fn new(role: &str, content: String) -> Self {
+        Message { role: role.to_string(), content: Some(MessageContent::Text(content)), name: None, tool_calls: None, tool_call_id: None }
+    }
+
+    // The text of the message, text parts are joined with a newline
+    pub fn text(&self) -> String {
+        match &self.content {
+            Some(MessageContent::Text(text)) => text.clone(),
+            Some(MessageContent::Parts(parts)) => parts.iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),                           // Plain text content.
+    Parts(Vec<ContentPart>),                // Content parts, used for images and audio.
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    InputAudio { input_audio: InputAudio },
+    // Any other part, e.g. Qwen-VL `video` or `video_url`, forwarded verbatim
+    #[serde(untagged)]
+    #[schema(value_type = Object)]
+    Other(Value),
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct ImageUrl {
+    pub url: String,                        // An http(s) url or a base64 encoded `data:image/...;base64,` url.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,             // `low`, `high` or `auto`.
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extra: Map<String, Value>,          // Any other field, e.g. Qwen-VL `min_pixels` and `max_pixels`, forwarded verbatim.
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct InputAudio {
+    pub data: String,                       // Base64 encoded audio data.
+    pub format: String,                     // `wav` or `mp3`.
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extra: Map<String, Value>,          // Any other field, forwarded verbatim.
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub tool_type: String,                  // The type of the tool, currently only `function`.
+    pub function: FunctionDefinition,
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct FunctionDefinition {
+    pub name: String,                       // The name of the function to be called.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,        // What the function does, used by the model to choose when to call it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub parameters: Option<Value>,          // The parameters the function accepts, described as a JSON Schema object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,               // Whether to enable strict schema adherence.
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    Mode(String),                           // `none`, `auto` or `required`.
+    Named(NamedToolChoice),                 // Forces the model to call the given function.
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct NamedToolChoice {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionName,
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct FunctionName {
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,                // `text`, `json_object` or `json_schema`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub json_schema: Option<Value>,         // The schema to follow when `type` is `json_schema`.
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct ToolCall {
+    pub id: String,                         // The ID of the tool call.
+    #[serde(rename = "type")]
+    pub call_type: String,                  // The type of the tool, currently only `function`.
+    pub function: FunctionCall,
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema, Debug)]
+pub struct FunctionCall {
+    pub name: String,                       // The name of the function to call.
+    pub arguments: String,                  // The arguments to call the function with, as a JSON string generated by the model.
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct StreamOptions {
-    pub include_usage: bool,
+    #[serde(default)]
+    pub include_usage: bool,                // Whether to send a last chunk with the usage of the request.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,          // Any other option, e.g. vLLM `continuous_usage_stats`, forwarded verbatim.
 }
 
 // ==================================================== Completion Response Struct ====================================================
@@ -88,13 +234,13 @@ pub struct CompletionsResponse {
     pub choices: Vec<CompletionsChoice>,      // List of generated text options returned.
     pub usage: CompletionsUsage,              // Usage statistics for the request.
     pub system_fingerprint: Option<String>,   // System fingerprint used for the request.
-    pub prompt_logprobs: Option<String>,      // Log probabilities for the prompt.
+    pub prompt_logprobs: Option<Value>,       // Log probabilities for the prompt.
 }
 #[derive(Deserialize, Serialize, Debug, ToSchema)]
 pub struct CompletionsChoice {
     pub index: u32,                                  // Index of the completion.
     pub message: CompletionsAssistantMessage,        // Message object containing the completion.
-    pub logprobs: Option<String>,                    // Log probabilities for the completion.
+    pub logprobs: Option<Value>,                     // Log probabilities for the completion.
     pub finish_reason: String,                       // Reason for finishing the completion.
     pub stop_reason: Option<String>,                 // Reason for stopping the completion.
 }
@@ -103,9 +249,9 @@ pub struct CompletionsChoice {
 pub struct CompletionsAssistantMessage{
     pub role: String,                               // Role of the assistant.
     pub reasoning_content: Option<String>,         // Reasoning content.
-    pub content: String,                            // Content of the completion.
+    pub content: Option<String>,                    // Content of the completion, null when the model only calls tools.
     pub refusal: Option<String>,                    // Refusal message.
-    pub tool_calls: Option<Vec<String>>,            // Tool calls.
+    pub tool_calls: Option<Vec<ToolCall>>,          // Tool calls.
 }
 
 #[derive(Deserialize, Serialize, Debug, ToSchema)]
@@ -116,6 +262,13 @@ pub struct CompletionsUsage {
     pub prompt_tokens_details: Option<PromptTokensDetails>,  // Details of the prompt tokens used.
 }
 
+impl CompletionsUsage {
+    // Prompt tokens the upstream served from its prefix cache
+    pub fn cached_tokens(&self) -> u32 {
+        self.prompt_tokens_details.as_ref().map_or(0, |details| details.cached_tokens)
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, ToSchema)]
 pub struct PromptTokensDetails {
     pub cached_tokens: u32,                   // Number of tokens used for the completion.
@@ -137,23 +290,119 @@ pub struct CompletionsStreamResponse {
 pub struct CompletionsStreamChoice {
     pub finish_reason: Option<String>,             // Reason for finishing the completion.
     pub index: u32,                                // Index of the completion.
-    pub logprobs: Option<String>,                  // Log probabilities for the completion.
+    pub logprobs: Option<Value>,                   // Log probabilities for the completion.
     pub delta: CompletionsDelta,                   // delta object containing the completion.
     pub stop_reason: Option<String>,               // Reason for stopping the completion.
 }
 
 #[derive(Deserialize, Serialize, ToSchema, Debug)]
 pub struct CompletionsDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub role: Option<String>,               // Role of the assistant.
     pub content: Option<String>,            // Content of the completion.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub refusal: Option<String>,            // Refusal message.
-    pub function_call: Option<String>,      // Function call.
-    pub tool_calls: Option<Vec<String>>,    // Tool calls.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCallDelta>, // Function call (deprecated by `tool_calls`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,   // Tool calls, streamed incrementally.
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extra: Map<String, Value>,          // Any other field, e.g. DeepSeek `reasoning_content`, forwarded verbatim.
+}
+
+// A streamed tool call only carries `id`, `type` and the function name in its first delta,
+// the `arguments` string is then sent piece by piece under the same `index`.
+#[derive(Deserialize, Serialize, ToSchema, Clone, Debug)]
+pub struct ToolCallDelta {
+    pub index: u32,                               // Index of the tool call within the choice.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,                       // The ID of the tool call.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub call_type: Option<String>,                // The type of the tool, currently only `function`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Clone, Debug)]
+pub struct FunctionCallDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,                     // The name of the function to call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,                // A fragment of the arguments JSON string.
+}
+
+
+// ==================================================== Text Completion Struct ====================================================
+// The legacy prompt based `/v1/completions` API
+#[derive(Deserialize, Serialize, ToSchema, Clone, Debug)]
+pub struct TextCompletionRequest {
+    pub model: String,                      // (Required) Name of the model used
+    #[schema(value_type = Object)]
+    pub prompt: Value,                      // (Required) A string, an array of strings, or token ids to complete.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,             // The text that comes after the completion, used for insertion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,            // Maximum number of tokens generated per request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,           // Controls the creativity of the generated text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,                 // An alternative sampling method to `temperature`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,                     // Number of completions to generate for each prompt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,               // Whether to stream back partial progress.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<u32>,              // Include the log probabilities of the most likely tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub echo: Option<bool>,                 // Echo back the prompt in addition to the completion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,          // Strings that stop the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,      // Value ranges from `-2.0` to `2.0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,     // Value ranges from `-2.0` to `2.0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_of: Option<u32>,               // Generates `best_of` completions server-side and returns the best.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<String, f32>>, // Maps token ids to a bias added to their logits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,               // User ID to identify the source of the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>, // Stream options for the request.
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extra: Map<String, Value>,          // Any other parameter, forwarded verbatim.
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct TextCompletionsResponse {
+    pub id: String,                                 // Unique identifier for each generated response.
+    pub object: String,                             // Always `"text_completion"`.
+    pub created: u64,                               // Timestamp of when the response was generated.
+    pub model: String,                              // Name of the model used.
+    pub choices: Vec<TextCompletionsChoice>,        // List of generated completions.
+    pub usage: Option<CompletionsUsage>,            // Usage statistics, only in the last chunk when streaming.
+    pub system_fingerprint: Option<String>,         // System fingerprint used for the request.
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct TextCompletionsChoice {
+    pub index: u32,                                 // Index of the completion.
+    pub text: String,                               // The generated text, or a piece of it when streaming.
+    #[schema(value_type = Option<Object>)]
+    pub logprobs: Option<Value>,                    // Log probabilities of the generated tokens.
+    pub finish_reason: Option<String>,              // Reason for finishing the completion.
+    #[schema(value_type = Option<Object>)]
+    pub stop_reason: Option<Value>,                 // Stop string or token id that ended the completion (vLLM).
 }
 
 
 // ==================================================== Completion Trait ====================================================
+// A provider knows how to send an already-built request body to one upstream service.
+// The provider of a service is chosen by its `servicetype` in `chat_registry`.
 #[async_trait]
 pub trait Completions: Send + Sync {
-    async fn completions(&self, req_body: web::Json<ChatCompletionRequest>, userid: String, appkey: String) -> Result<HttpResponse, Error>;
+    async fn completions(&self, service: &ServiceConfig, request_body: &Value) -> Result<Response, reqwest::Error>;
+    async fn text_completions(&self, service: &ServiceConfig, request_body: &Value) -> Result<Response, reqwest::Error>;
 }
\ No newline at end of file
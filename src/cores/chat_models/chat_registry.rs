@@ -0,0 +1,15 @@
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+
+use crate::cores::chat_models::chat_controller::Completions;
+use crate::cores::chat_models::support_models::openai::OpenAICompatible;
+
+static OPENAI_COMPATIBLE: Lazy<Arc<dyn Completions>> = Lazy::new(|| Arc::new(OpenAICompatible));
+
+// The provider of a service, by the `servicetype` column of the `services` table.
+// Every service type (openai, vllm, mindie, bailian, deepseek, ...) speaks the OpenAI API, so new model
+// families only need a row in `services` (POST /v1/services), not a code change.
+// A service type with a wire format of its own gets its provider here.
+pub fn get_provider(_servicetype: &str) -> Arc<dyn Completions> {
+    OPENAI_COMPATIBLE.clone()
+}
@@ -0,0 +1,322 @@
+use actix_web::{HttpResponse, Error};
+use actix_web::error::{ErrorInternalServerError, ErrorBadRequest, ErrorServiceUnavailable, ErrorTooManyRequests, InternalError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use chrono::Utc;
+use chrono_tz::Asia::Shanghai;
+use log::{info, error};
+use reqwest::Response;
+use serde_json::Value;
+use std::future::Future;
+use std::time::Instant;
+
+use crate::cores::control::route_cache::route_table;
+use crate::cores::control::model_limits::QuotaSubjects;
+use crate::cores::control::health;
+use crate::cores::control::balancer::{self, InflightGuard};
+use crate::cores::chat_models::chat_controller::{ChatCompletionRequest, TextCompletionRequest};
+use crate::cores::chat_models::chat_registry::get_provider;
+use crate::cores::chat_models::chat_utils::{completions_response_stream, completions_response_non_stream, get_request_body, filter_request_params, RequestInfo,
+    get_text_request_body, text_completions_response_stream, text_completions_response_non_stream};
+use crate::utils::tokenizer::prepare_tokenizer;
+use crate::meta::services::traits::ServiceConfig;
+use crate::meta::traffic_splits::traits::TrafficSplit;
+use crate::middleware::qos::take_model_quota;
+
+// Find all replicas serving a requested model name from the in-memory route table.
+// Aliases from the model catalog are resolved first, then the full name is tried (`Qwen2.5-7B-Instruct`,
+// `Qwen/Qwen2.5-7B-Instruct`), then the part after the series prefix for clients that send `Series/Name`.
+pub fn resolve_services(model: &str) -> Vec<ServiceConfig> {
+    let table = route_table();
+    let services = table.services_by_model(table.canonical_model(model));
+    if !services.is_empty() {
+        return services;
+    }
+
+    match model.split_once('/') {
+        Some((_, name)) if !name.is_empty() => table.services_by_model(name),
+        _ => services,
+    }
+}
+
+// The upstream API a request is sent to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endpoint {
+    Chat,   // `/v1/chat/completions`
+    Text,   // `/v1/completions`
+}
+
+// Send the request to the replicas one after another until one of them answers.
+// Connection errors and 5xx responses move on to the next replica; other statuses are returned
+// to the caller as they are. Nothing has been streamed to the client at this point, so retrying is safe.
+pub async fn send_with_failover(model: &str, services: Vec<ServiceConfig>, request_body: &Value, endpoint: Endpoint) -> Result<(ServiceConfig, Response, InflightGuard), Error> {
+    let total = services.len();
+    let mut last_error = format!("{} model is not supported", model);
+
+    for (attempt, service) in services.into_iter().enumerate() {
+        // A recovering replica takes one trial request, it may have been taken since the replicas were selected
+        if !health::admit(&service.id) {
+            last_error = format!("{} request failed: service {} is recovering", model, service.id);
+            continue;
+        }
+        let provider = get_provider(&service.servicetype);
+        let mut body = request_body.clone();
+        body["model"] = Value::String(service.model_name.clone());
+        filter_request_params(&mut body, &service);
+
+        let inflight = balancer::begin_request(&service.id);
+        let sent_at = Instant::now();
+        let result = match endpoint {
+            Endpoint::Chat => provider.completions(&service, &body).await,
+            Endpoint::Text => provider.text_completions(&service, &body).await,
+        };
+        match result {
+            Ok(response) if response.status().is_server_error() => {
+                last_error = format!("{} request failed: {}", model, response.status());
+                health::record_failure(&service.id, last_error.clone());
+                error!(target: "error_log", "Attempt {}/{} for model {} on service {} ({}) failed: {}",
+                    attempt + 1, total, model, service.id, service.url, response.status());
+            }
+            Ok(response) => {
+                health::record_success(&service.id);
+                balancer::record_latency(&service.id, sent_at.elapsed());
+                info!(target: "access_log", "Attempt {}/{} for model {} on service {} ({}) returned {}",
+                    attempt + 1, total, model, service.id, service.url, response.status());
+                return Ok((service, response, inflight));
+            }
+            Err(err) => {
+                last_error = format!("Request failed: {}", err);
+                health::record_failure(&service.id, last_error.clone());
+                error!(target: "error_log", "Attempt {}/{} for model {} on service {} ({}) failed: {}",
+                    attempt + 1, total, model, service.id, service.url, err);
+            }
+        }
+    }
+
+    Err(InternalError::new(last_error, StatusCode::INTERNAL_SERVER_ERROR).into())
+}
+
+// Read the healthy replicas serving the model, ordered by the model's balancing strategy
+pub fn select_services(model: &str) -> Result<Vec<ServiceConfig>, Error> {
+    let services = resolve_services(model);
+    if services.is_empty() {
+        return Err(ErrorBadRequest(format!("{} model is not supported", model)));
+    }
+    let services: Vec<ServiceConfig> = services.into_iter().filter(health::is_available).collect();
+    if services.is_empty() {
+        return Err(ErrorServiceUnavailable(format!("No healthy service for {} model", model)));
+    }
+    Ok(balancer::order_services(route_table().canonical_model(model), services))
+}
+
+// Response header naming the model that actually served the request, which differs from
+// the requested one when the request fell back along the model's fallback chain
+pub const SERVED_MODEL_HEADER: &str = "x-chatig-served-model";
+
+// What the middlewares found out about the caller, used to pick the route
+#[derive(Clone, Debug, Default)]
+pub struct RouteContext {
+    pub api_key: String,
+    pub account: String,            // the account id set by the auth middleware, a quota subject
+    pub sticky_id: String,          // keeps a user on one traffic split variant
+    pub project: String,            // the `OpenAI-Project` header, a quota subject
+    pub prompt_tokens: u32,         // counted once with the tokenizer of the requested model
+    pub quota_tokens: Option<i64>,  // tokens checked against the quotas of each model tried, None when quotas are off
+}
+
+impl RouteContext {
+    // The sticky id is the OpenAI `user` field, else the key, else the account id set by the auth middleware.
+    // The key comes first since local auth gives every key the same account id.
+    pub fn new(api_key: String, userid: &str, user: Option<&str>) -> Self {
+        let sticky_id = [user.unwrap_or(""), &api_key, userid]
+            .into_iter()
+            .find(|id| !id.is_empty())
+            .unwrap_or("")
+            .to_string();
+        RouteContext { api_key, account: userid.to_string(), sticky_id, project: String::new(), prompt_tokens: 0, quota_tokens: None }
+    }
+
+    pub fn with_project(mut self, project: &str) -> Self {
+        self.project = project.to_string();
+        self
+    }
+
+    pub fn with_prompt_tokens(mut self, prompt_tokens: u32) -> Self {
+        self.prompt_tokens = prompt_tokens;
+        self
+    }
+
+    pub fn with_quota_tokens(mut self, quota_tokens: Option<i64>) -> Self {
+        self.quota_tokens = quota_tokens;
+        self
+    }
+
+    // The subjects the request and its tokens are counted for
+    pub fn quota_subjects(&self) -> QuotaSubjects {
+        QuotaSubjects {
+            key: self.api_key.clone(),
+            account: self.account.clone(),
+            project: self.project.clone(),
+        }
+    }
+
+    // Check the quotas of a model the request is about to be sent to and count the request for it
+    async fn take_quota(&self, model: &str) -> Result<bool, Error> {
+        match self.quota_tokens {
+            Some(estimated) => take_model_quota(&self.quota_subjects(), model, estimated).await,
+            None => Ok(true),
+        }
+    }
+}
+
+// Walk the fallback chain of the requested model until one model answers. A model is passed over when
+// it has no healthy service, when all of its replicas fail, when its upstream rejects the request for
+// capacity (429) or when the caller is over its quota for it. Each model's quota is checked and counted
+// only when the request is about to be sent to it.
+// A traffic split `variant` puts its model first, or narrows the requested model to its service.
+// The error of the first model tried is returned when none of them can serve.
+pub async fn send_with_fallback(model: &str, variant: Option<&TrafficSplit>, ctx: &RouteContext, request_body: &Value, endpoint: Endpoint) -> Result<(String, Response, InflightGuard), Error> {
+    let table = route_table();
+    // (name to route, catalog model); the requested name is routed as it was sent, so `Series/Name` keeps working
+    let mut chain: Vec<(String, String)> = table.fallback_chain(model)
+        .into_iter()
+        .enumerate()
+        .map(|(index, served)| (if index == 0 { model.to_string() } else { served.clone() }, served))
+        .collect();
+    if let Some(target) = variant.filter(|variant| !variant.target_model.is_empty()) {
+        let served = table.canonical_model(&target.target_model).to_string();
+        chain.retain(|(_, model)| *model != served);
+        chain.insert(0, (target.target_model.clone(), served));
+    }
+    let requested = table.canonical_model(model).to_string();
+    let mut first_error: Option<Error> = None;
+
+    for (index, (target, served)) in chain.iter().enumerate() {
+        let services = match select_services(target) {
+            Ok(services) if *served == requested => split_services(services, variant, table.traffic_splits(model)),
+            Ok(services) => services,
+            Err(err) => {
+                error!(target: "error_log", "Model {} can't serve the request: {}", served, err);
+                first_error.get_or_insert(err);
+                continue;
+            }
+        };
+        if !ctx.take_quota(served).await? {
+            first_error.get_or_insert_with(|| ErrorTooManyRequests("Throttle for request"));
+            continue;
+        }
+
+        match send_with_failover(target, services, request_body, endpoint).await {
+            Ok((_, response, _)) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                error!(target: "error_log", "Model {} rejected the request for capacity", served);
+                first_error.get_or_insert_with(|| ErrorTooManyRequests(format!("{} is over capacity", target)));
+            }
+            Ok((_, response, inflight)) => {
+                if index > 0 {
+                    info!(target: "access_log", "Model {} fell back to {}", model, served);
+                }
+                return Ok((served.clone(), response, inflight));
+            }
+            Err(err) => {
+                error!(target: "error_log", "Model {} can't serve the request: {}", served, err);
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    Err(first_error.unwrap_or_else(|| ErrorBadRequest(format!("{} model is not supported", model))))
+}
+
+// Canary services of the model only get the requests assigned to them. When the assigned service
+// is unavailable the request goes to the other services, and if only canaries are left, to them.
+fn split_services(services: Vec<ServiceConfig>, variant: Option<&TrafficSplit>, splits: &[TrafficSplit]) -> Vec<ServiceConfig> {
+    if let Some(target) = variant.filter(|variant| !variant.target_service.is_empty()) {
+        let pinned: Vec<ServiceConfig> = services.iter().filter(|service| service.id == target.target_service).cloned().collect();
+        if !pinned.is_empty() {
+            return pinned;
+        }
+    }
+
+    let reserved: Vec<&str> = splits.iter()
+        .filter(|split| split.enabled && !split.target_service.is_empty())
+        .map(|split| split.target_service.as_str())
+        .collect();
+    let general: Vec<ServiceConfig> = services.iter().filter(|service| !reserved.contains(&service.id.as_str())).cloned().collect();
+    if general.is_empty() { services } else { general }
+}
+
+pub async fn completions(req_body: ChatCompletionRequest, userid: String, appkey: String, ctx: RouteContext) -> Result<HttpResponse, Error> {
+    // 1. Build the request body, the model name is set per replica
+    let (request_body, is_stream) = get_request_body(req_body.model.clone(), &req_body);
+    let include_usage = req_body.stream_options.as_ref().is_some_and(|options| options.include_usage);
+
+    route_completions(req_body.model, &request_body, Endpoint::Chat, include_usage, RequestOwner { userid, appkey }, ctx, |response, req_info| async move {
+        if is_stream {
+            completions_response_stream(response, req_info).await
+        } else {
+            completions_response_non_stream(response, req_info).await
+        }
+    }).await
+}
+
+// The legacy `/v1/completions` API, routed, balanced and accounted exactly like chat
+pub async fn text_completions(req_body: TextCompletionRequest, userid: String, appkey: String, ctx: RouteContext) -> Result<HttpResponse, Error> {
+    // 1. Build the request body, the model name is set per replica
+    let (request_body, is_stream) = get_text_request_body(req_body.model.clone(), &req_body);
+    let include_usage = req_body.stream_options.as_ref().is_some_and(|options| options.include_usage);
+
+    route_completions(req_body.model, &request_body, Endpoint::Text, include_usage, RequestOwner { userid, appkey }, ctx, |response, req_info| async move {
+        if is_stream {
+            text_completions_response_stream(response, req_info).await
+        } else {
+            text_completions_response_non_stream(response, req_info).await
+        }
+    }).await
+}
+
+// Who the request is accounted to in the token log
+struct RequestOwner {
+    userid: String,
+    appkey: String,
+}
+
+// Route a chat or text completion shared by both APIs: pick the traffic split variant, send the request along
+// the fallback chain and hand the upstream response to `respond`, which returns it in the endpoint's format
+async fn route_completions<F, Fut>(model: String, request_body: &Value, endpoint: Endpoint, include_usage: bool, owner: RequestOwner, ctx: RouteContext, respond: F) -> Result<HttpResponse, Error>
+where
+    F: FnOnce(Response, RequestInfo) -> Fut,
+    Fut: Future<Output = Result<HttpResponse, Error>>,
+{
+    // 2. Send the request along the fallback chain, failing over to other replicas before any byte reaches the client
+    let start_time = Utc::now().with_timezone(&Shanghai);
+    let variant = route_table().traffic_split(&model, &ctx.api_key, &ctx.sticky_id).cloned();
+    let (served_model, response, inflight) = send_with_fallback(&model, variant.as_ref(), &ctx, request_body, endpoint).await?;
+
+    if !response.status().is_success() {
+        return Err(ErrorInternalServerError(format!("{} request failed: {}", model, response.status())));
+    }
+
+    // 3. Return the response based on the request's streaming status
+    // Completions of upstreams that don't report usage are counted with the served model's tokenizer
+    prepare_tokenizer(&served_model).await;
+    let req_info = RequestInfo{
+        model_name: served_model.clone(),
+        req_model_name: model,
+        variant: variant.map(|variant| variant.id).unwrap_or_default(),
+        quota: ctx.quota_subjects(),
+        include_usage,
+        prompt_tokens: ctx.prompt_tokens,
+        userid: owner.userid,
+        appkey: owner.appkey,
+        start_time,
+        inflight: Some(inflight),
+    };
+    respond(response, req_info).await.map(|response| with_served_model(response, &served_model))
+}
+
+fn with_served_model(mut response: HttpResponse, served_model: &str) -> HttpResponse {
+    if let Ok(value) = HeaderValue::from_str(served_model) {
+        response.headers_mut().insert(HeaderName::from_static(SERVED_MODEL_HEADER), value);
+    }
+    response
+}
@@ -1,78 +1,198 @@
-use actix_web::{web, HttpResponse, Error};
+use actix_web::{HttpResponse, Error};
 use actix_web::error::ErrorInternalServerError;
 use reqwest::Response;
 use serde_json::{Value, json};
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use bytes::Bytes;
 use chrono::{Utc, DateTime};
 use chrono_tz::Tz;
 use chrono_tz::Asia::Shanghai;
-use log::info;
-use std::error;
+use std::time::Duration;
+use tokio::time::timeout;
 
-use crate::cores::chat_models::chat_controller::{CompletionsResponse, CompletionsStreamResponse, ChatCompletionRequest};
+use crate::cores::chat_models::chat_controller::{CompletionsResponse, CompletionsStreamResponse, CompletionsStreamChoice, ChatCompletionRequest,
+    MessageContent, ContentPart, TextCompletionRequest, TextCompletionsResponse, CompletionsUsage};
 use crate::middleware::qos::consume;
 use crate::GLOBAL_CONFIG;
-use crate::configs::settings::Config;
+use crate::configs::settings::{Config, MultimodalLimit};
+use crate::cores::control::balancer::InflightGuard;
+use crate::cores::control::model_limits::QuotaSubjects;
+use crate::cores::control::budgets::record_spend;
+use crate::cores::control::route_cache::route_table;
+use crate::utils::tokenizer::count_model_tokens;
+use crate::cores::usage::emit_usage_event;
+use crate::cores::usage::records::store_usage_record;
+use crate::meta::usage::traits::UsageRecord;
+use crate::utils::sse::{SseDecoder, SseEvent};
+use crate::meta::services::traits::ServiceConfig;
 
 pub struct RequestInfo{
-    pub req_model_name: String,
+    pub req_model_name: String,     // the name the client asked for, possibly an alias, reported back in responses
+    pub model_name: String,         // the catalog model it resolves to, used for accounting
+    pub variant: String,            // the traffic split rule that picked the route, empty for the default route
     pub userid: String, 
+    pub quota: QuotaSubjects,       // the key, account and project the tokens are counted for
+    pub include_usage: bool,        // whether the client asked for the usage chunk of a stream
+    pub prompt_tokens: u32,         // counted by the gateway, for upstreams that don't report usage
     pub appkey: String, 
-    pub start_time: DateTime<Tz>
+    pub start_time: DateTime<Tz>,
+    pub inflight: Option<InflightGuard>,    // released when the response has been fully sent
 }
 
-pub fn get_request_body(model_name: String, req_body: web::Json<ChatCompletionRequest>) -> (Value, bool) {
-    // Build the basic request body
-    let mut request_body = json!({
-        "model": model_name,
-        "messages": req_body.messages.clone(),
-    });
-
-    let mut is_stream = false;
+pub fn get_request_body(model_name: String, req_body: &ChatCompletionRequest) -> (Value, bool) {
+    // Typed fields and the unknown ones collected in `extra` are serialized back as they came
+    let mut request_body = serde_json::to_value(req_body).unwrap_or_else(|_| json!({}));
+    request_body["model"] = json!(model_name);
 
-    // Add optional parameters
-    if let Some(temperature) = req_body.temperature {
-        request_body["temperature"] = json!(temperature);
-    }
-    if let Some(top_p) = req_body.top_p {
-        request_body["top_p"] = json!(top_p);
-    }
-    if let Some(n) = req_body.n {
-        request_body["n"] = json!(n);
-    }
-    if let Some(stream) = req_body.stream {
-        request_body["stream"] = json!(stream);
-        is_stream = stream;
+    // Options put under `extra_body` by clients that don't merge it themselves belong to the top level
+    if let Some(Value::Object(extra_body)) = request_body.as_object_mut().and_then(|body| body.remove("extra_body")) {
+        for (key, value) in extra_body {
+            request_body[key] = value;
+        }
     }
-    if let Some(stop) = req_body.stop.clone() {
-        request_body["stop"] = json!(stop);
+
+    let is_stream = req_body.stream.unwrap_or(false);
+    if is_stream {
+        force_include_usage(&mut request_body);
     }
-    if let Some(max_tokens) = req_body.max_tokens {
-        request_body["max_tokens"] = json!(max_tokens);
+    (request_body, is_stream)
+}
+
+pub fn get_text_request_body(model_name: String, req_body: &TextCompletionRequest) -> (Value, bool) {
+    let mut request_body = serde_json::to_value(req_body).unwrap_or_else(|_| json!({}));
+    request_body["model"] = json!(model_name);
+
+    let is_stream = req_body.stream.unwrap_or(false);
+    if is_stream {
+        force_include_usage(&mut request_body);
     }
-    if let Some(presence_penalty) = req_body.presence_penalty {
-        request_body["presence_penalty"] = json!(presence_penalty);
+    (request_body, is_stream)
+}
+
+// Streams always ask the upstream for the usage chunk, so that every request is logged and counted
+// against its quota. The chunk is only forwarded to clients that asked for it (`RequestInfo.include_usage`).
+pub fn force_include_usage(request_body: &mut Value) {
+    if !request_body["stream_options"].is_object() {
+        request_body["stream_options"] = json!({});
     }
-    if let Some(frequency_penalty) = req_body.frequency_penalty {
-        request_body["frequency_penalty"] = json!(frequency_penalty);
+    request_body["stream_options"]["include_usage"] = json!(true);
+}
+
+// Prompt tokens counted by the gateway with the model's tokenizer, used to check the context window
+// and when the upstream doesn't report usage
+pub fn chat_prompt_tokens(model: &str, req_body: &ChatCompletionRequest) -> u32 {
+    req_body.messages.iter().map(|message| count_model_tokens(model, &message.text())).sum()
+}
+
+pub fn text_prompt_tokens(model: &str, req_body: &TextCompletionRequest) -> u32 {
+    match &req_body.prompt {
+        Value::String(text) => count_model_tokens(model, text),
+        // An array of strings, or of token ids
+        Value::Array(items) => items.iter()
+            .map(|item| match item {
+                Value::String(text) => count_model_tokens(model, text),
+                Value::Array(ids) => ids.len() as u32,
+                _ => 1,
+            })
+            .sum(),
+        _ => 0,
     }
-    if let Some(logit_bias) = req_body.logit_bias {
-        request_body["logit_bias"] = json!(logit_bias);
+}
+
+// The completion tokens a chat request asks for, `max_completion_tokens` or the older `max_tokens`
+pub fn requested_completion_tokens(req_body: &ChatCompletionRequest) -> Option<u32> {
+    req_body.extra.get("max_completion_tokens")
+        .and_then(Value::as_u64)
+        .map(|tokens| tokens as u32)
+        .or(req_body.max_tokens)
+}
+
+// Check the prompt and the requested completion against the context length of the catalog model,
+// in the words OpenAI uses. Models without a known context length are not checked.
+pub fn check_context_window(context_length: i32, prompt_tokens: u32, completion_tokens: Option<u32>) -> Result<(), String> {
+    if context_length <= 0 {
+        return Ok(());
     }
-    if let Some(user) = req_body.user.clone() {
-        request_body["user"] = json!(user);
+    let requested = prompt_tokens as u64 + completion_tokens.unwrap_or(0) as u64;
+    if requested <= context_length as u64 {
+        return Ok(());
     }
-    if let Some(file_id) = req_body.file_id.clone() {
-        request_body["file_id"] = json!(file_id);
+    Err(match completion_tokens {
+        Some(completion_tokens) => format!(
+            "This model's maximum context length is {} tokens. However, you requested {} tokens ({} in the messages, {} in the completion). Please reduce the length of the messages or completion.",
+            context_length, requested, prompt_tokens, completion_tokens
+        ),
+        None => format!(
+            "This model's maximum context length is {} tokens. However, your messages resulted in {} tokens. Please reduce the length of the messages.",
+            context_length, prompt_tokens
+        ),
+    })
+}
+
+// Parameters every backend needs, they are never filtered out
+const REQUIRED_PARAMS: [&str; 4] = ["model", "messages", "prompt", "stream"];
+
+// Drop the parameters the service does not accept, according to its allow and deny lists
+pub fn filter_request_params(request_body: &mut Value, service: &ServiceConfig) {
+    let Some(body) = request_body.as_object_mut() else {
+        return;
+    };
+    body.retain(|key, _| {
+        if REQUIRED_PARAMS.contains(&key.as_str()) {
+            return true;
+        }
+        if !service.allowed_params.is_empty() && !service.allowed_params.contains(key) {
+            return false;
+        }
+        !service.denied_params.contains(key)
+    });
+}
+
+
+// Check the image parts of all messages against the limits configured for the model
+pub fn check_multimodal_limits(req_body: &ChatCompletionRequest, limit: &MultimodalLimit) -> Result<(), String> {
+    let images: Vec<&str> = req_body.messages.iter()
+        .filter_map(|message| match &message.content {
+            Some(MessageContent::Parts(parts)) => Some(parts),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|part| match part {
+            ContentPart::ImageUrl { image_url } => Some(image_url.url.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    if let Some(max_images) = limit.max_images {
+        if images.len() > max_images {
+            return Err(format!("Invalid request: model {} accepts at most {} images, got {}.", req_body.model, max_images, images.len()));
+        }
     }
 
-    // Add optional stream_options
-    if let Some(stream_options) = req_body.stream_options.clone() {
-        request_body["stream_options"] = json!(stream_options);
+    // Remote images are fetched by the backend, only inline images can be measured here
+    if let Some(max_image_bytes) = limit.max_image_bytes {
+        for (index, url) in images.iter().enumerate() {
+            if let Some(size) = data_url_size(url) {
+                if size > max_image_bytes {
+                    return Err(format!("Invalid request: image {} is {} bytes, model {} accepts at most {} bytes per image.",
+                        index, size, req_body.model, max_image_bytes));
+                }
+            }
+        }
     }
 
-    return (request_body, is_stream);
+    Ok(())
+}
+
+// Decoded size of a `data:` url, None for any other url
+fn data_url_size(url: &str) -> Option<usize> {
+    let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+    if !header.ends_with(";base64") {
+        return Some(data.len());
+    }
+    let data = data.trim_end();
+    let padding = data.bytes().rev().take_while(|byte| *byte == b'=').count();
+    Some((data.len() - padding) * 3 / 4)
 }
 
 
@@ -92,39 +212,39 @@ pub async fn completions_response_non_stream(response: Response, req_info: Reque
     
     // 2. Return a custom response body
     let req_model_name = req_info.req_model_name.clone();
+    // Forward every choice, so that `n > 1` requests get all of their completions back
+    let choices: Vec<Value> = chat_response.choices.iter().map(|choice| json!({
+        "index": choice.index,
+        "message": {
+            "role": choice.message.role,
+            "reasoning_content": choice.message.reasoning_content,
+            "content": choice.message.content,
+            "tool_calls": choice.message.tool_calls
+        },
+        "logprobs": choice.logprobs,
+        "finish_reason": choice.finish_reason,
+        "stop_reason": choice.stop_reason
+    })).collect();
+
     let res = json!({
       "id": chat_response.id,
       "object": chat_response.object,
       "created": chat_response.created,
       "model": req_model_name,
-      "choices": [
-            {
-                "index": chat_response.choices[0].index,
-                "message": {
-                    "role": chat_response.choices[0].message.role,
-                    "reasoning_content": chat_response.choices[0].message.reasoning_content,
-                    "content": chat_response.choices[0].message.content,
-                    "tool_calls": chat_response.choices[0].message.tool_calls
-                },
-                "logprobs": chat_response.choices[0].logprobs,
-                "finish_reason": chat_response.choices[0].finish_reason,
-                "stop_reason": chat_response.choices[0].stop_reason
-            }
-      ],
+      "choices": choices,
       "usage": chat_response.usage,
       "prompt_logprobs": chat_response.prompt_logprobs
     });
 
-    // 4. push kafka data
+    // 4. push usage event and record
     let config = &*GLOBAL_CONFIG;
-    push_kafka_data(req_model_name.clone(), config, chat_response.usage.total_tokens, chat_response.usage.completion_tokens, 
-        chat_response.usage.prompt_tokens, req_info.userid.clone(), req_info.appkey, req_info.start_time);
+    push_usage(&req_info, config, chat_response.usage.total_tokens, chat_response.usage.completion_tokens, chat_response.usage.prompt_tokens, chat_response.usage.cached_tokens());
 
     // 5. Consume tokens
-    if config.coil_enabled {
+    if config.quota_enabled() {
         // 下述的model需要换成上述的chat_response.model；apikey需要传入
         // let status_is_success = consume("sk-4XNwrsq6bS9KD11E6xkrKEItGBcR".to_string(), "deepseek-ai/DeepSeek-R1-Distill-Llama-8B".to_string(), chat_response.usage.total_tokens).await?;
-        if consume(req_info.userid, req_model_name, chat_response.usage.total_tokens).await? != "success" {
+        if consume(&req_info.quota, &req_info.model_name, chat_response.usage.total_tokens).await? != "success" {
             return Err(ErrorInternalServerError("Failed to consume tokens"));
         }
     }
@@ -134,79 +254,67 @@ pub async fn completions_response_non_stream(response: Response, req_info: Reque
 
 
 // Handle streaming response requests
-pub async fn completions_response_stream(response: Response, req_info: RequestInfo) -> Result<HttpResponse, Error> {
+pub async fn completions_response_stream(response: Response, mut req_info: RequestInfo) -> Result<HttpResponse, Error> {
     // 1. create an asynchronous stream that sends each chunk of data obtained from the response to the client
-    let mut body_stream = response.bytes_stream();
     let req_model_name = req_info.req_model_name.clone();
+    let inflight = req_info.inflight.take();
     let stream = async_stream::stream! {
-        // let mut body_stream = body_stream;
-        while let Some(chunk) = body_stream.next().await {
-            match chunk {
-                Ok(bytes) => {
-                    // Convert bytes to string
-                    let json_str = String::from_utf8_lossy(&bytes).to_string();
-                    // Check if the chunk contains the "data: [DONE]" string
-                    if json_str.contains("data: [DONE]") {
-                        break;
-                    }
-                    //  Remove the prefix "data: " and the suffix "\n\n"
-                    let json_str = json_str.trim_start_matches("data: ");
-                    let json_str = json_str.trim_end(); 
-                    
-                    // Deserialize the JSON string into a Value
-                    let json_value = match serde_json::from_str::<Value>(&json_str) {
-                        Ok(value) => value,
-                        Err(err) => {
-                            yield Err(format!("Chunk failed to parse JSON form json_str: {}, Err: {}", json_str, err));
-                            continue;
-                        },   
-                    };
-
-                    // Try to convert json_value to CompletionsStreamResponse
-                    let chat_response: CompletionsStreamResponse = match serde_json::from_value(json_value.clone()) {
-                        Ok(chat_response) => chat_response,
-                        Err(err) => {
-                            yield Err(format!("Chunk failed to deserialize into CompletionsStreamResponse: {}, Err: {}", json_value, err));
-                            continue;
-                        },
-                    };
-
-                    // 判断是否为usage chunk
-                    if let Some(usage) = &chat_response.usage {
-                        let config = &*GLOBAL_CONFIG;
-                        push_kafka_data(req_model_name.clone(), config, usage.total_tokens, usage.completion_tokens, 
-                            usage.prompt_tokens, req_info.userid.clone(), req_info.appkey.clone(), req_info.start_time);
-
-                        if config.coil_enabled {
-                            if let Err(_) = consume(req_info.userid.clone(), req_model_name.clone(), usage.total_tokens).await {
-                                yield Err(format!("Failed to consume tokens"));
-                            }
-                        }
-
-                        let usage_chunk = json!({
-                            "id": chat_response.id,
-                            "model": req_model_name,
-                            "created": chat_response.created,
-                            "object": chat_response.object,
-                            "choices": [],
-                            "usage": usage
-                        });
-
-                        let usage_chunk = format!("data: {}\n\n", serde_json::to_string(&usage_chunk).unwrap());
-                        yield Ok::<Bytes, String>(Bytes::from(usage_chunk));
-                        break;
-                    }
-
-                    let chunk = transfer_chunk(chat_response, req_model_name.clone()).await.unwrap();
-                    let chunk_str = format!("data: {}\n\n", serde_json::to_string(&chunk).unwrap());
-                    yield Ok::<Bytes, String>(Bytes::from(chunk_str));
-                },
+        // Keep the service counted as busy until the stream ends
+        let _inflight = inflight;
+        let mut last_usage: Option<CompletionsUsage> = None;
+        let mut completion_text = String::new();
+        let mut events = Box::pin(upstream_events(response));
+        while let Some(event) = events.next().await {
+            let event = match event {
+                Ok(event) => event,
                 Err(err) => {
-                    // If reading data fails, return an error
-                    yield Err(format!("Stream read error: {}", err));
+                    yield Err(err);
+                    continue;
                 }
+            };
+
+            // Deserialize the event data into a Value
+            let json_value = match serde_json::from_str::<Value>(&event.data) {
+                Ok(value) => value,
+                Err(err) => {
+                    yield Err(format!("Chunk failed to parse JSON form event data: {}, Err: {}", event.data, err));
+                    continue;
+                },
+            };
+
+            // Try to convert json_value to CompletionsStreamResponse
+            let chat_response: CompletionsStreamResponse = match serde_json::from_value(json_value.clone()) {
+                Ok(chat_response) => chat_response,
+                Err(err) => {
+                    yield Err(format!("Chunk failed to deserialize into CompletionsStreamResponse: {}, Err: {}", json_value, err));
+                    continue;
+                },
+            };
+
+            for choice in &chat_response.choices {
+                push_delta_text(&mut completion_text, choice);
+            }
+            for chunk in stream_chunks(&chat_response, &req_model_name, req_info.include_usage) {
+                yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&chunk).unwrap())));
+            }
+            // Usage may come on every chunk (vLLM `continuous_usage_stats`), the last one has the totals
+            if chat_response.usage.is_some() {
+                last_usage = chat_response.usage;
             }
         }
+
+        let result = match &last_usage {
+            Some(usage) => record_usage(&req_info, usage.total_tokens, usage.completion_tokens, usage.prompt_tokens, usage.cached_tokens()).await,
+            // The upstream ignored `include_usage`, count the tokens ourselves
+            None => {
+                let completion_tokens = count_model_tokens(&req_info.model_name, &completion_text);
+                let prompt_tokens = req_info.prompt_tokens;
+                record_usage(&req_info, prompt_tokens + completion_tokens, completion_tokens, prompt_tokens, 0).await
+            }
+        };
+        if let Err(err) = result {
+            yield Err(err);
+        }
     };  
 
     // 2. Create a new stream that combines the original stream and the response string
@@ -225,98 +333,288 @@ pub async fn completions_response_stream(response: Response, req_info: RequestIn
     Ok(HttpResponse::Ok().content_type("text/event-stream").streaming(combined_stream))  
 }
 
-async fn transfer_chunk(chat_response: CompletionsStreamResponse, model_name: String) -> Result<Value, Box<dyn error::Error>> {
-    // 判断是否为stop chunk
-    if chat_response.choices[0].finish_reason == Some("stop".to_string()){
-        let stop_chunk = json!({
-            "id": chat_response.id,
-            "model": model_name,
-            "created": chat_response.created,
-            "object": chat_response.object,
-            "choices": [
-                {
-                    "index": chat_response.choices[0].index,
-                    "delta": {
-                        "content": ""
-                    },
-                    "finish_reason": "stop",
-                    "stop_reason": null,
-                    "logprobs": chat_response.choices[0].logprobs
+// Read the SSE events of an upstream streaming response until `data: [DONE]`, an `error` event or the end of the body.
+// Read errors are yielded and reading goes on; the stream ends after an error event or when the upstream
+// stays silent for longer than `upstream_read_timeout`.
+pub fn upstream_events(response: Response) -> impl Stream<Item = Result<SseEvent, String>> {
+    let mut body_stream = response.bytes_stream();
+    let read_timeout = Duration::from_secs(GLOBAL_CONFIG.upstream_read_timeout);
+    async_stream::stream! {
+        let mut decoder = SseDecoder::new();
+        let mut finished = false;
+        'read: while !finished {
+            let events = match timeout(read_timeout, body_stream.next()).await {
+                Ok(Some(Ok(bytes))) => decoder.push(&bytes),
+                Ok(Some(Err(err))) => {
+                    // If reading data fails, return an error
+                    yield Err(format!("Stream read error: {}", err));
+                    continue;
                 }
-            ]
-        });
+                Ok(None) => {
+                    finished = true;
+                    decoder.finish().into_iter().collect()
+                }
+                Err(_) => {
+                    yield Err(format!("Stream read timed out after {}s", read_timeout.as_secs()));
+                    break;
+                }
+            };
 
-        return Ok(stop_chunk);
+            for event in events {
+                // The end of the stream
+                if event.data == "[DONE]" {
+                    break 'read;
+                }
+                if event.event.as_deref() == Some("error") {
+                    yield Err(format!("Upstream stream error: {}", event.data));
+                    break 'read;
+                }
+                yield Ok(event);
+            }
+        }
     }
+}
+
+// Handle non-streaming `/v1/completions` responses
+pub async fn text_completions_response_non_stream(response: Response, req_info: RequestInfo) -> Result<HttpResponse, Error> {
+    // 1. Convet the response body to a TextCompletionsResponse struct
+    let response_text = response.text().await
+        .map_err(|err| ErrorInternalServerError(format!("Failed to read response: {}", err)))?;
+
+    let mut text_response: TextCompletionsResponse = serde_json::from_str(&response_text)
+        .map_err(|err| ErrorInternalServerError(format!("Failed to deserialize into TextCompletionsResponse: {}, {}", err, response_text)))?;
+
+    // 2. Report the model name the client asked for
+    text_response.model = req_info.req_model_name.clone();
+
+    // 3. push kafka data and consume tokens, counting them ourselves when the upstream didn't
+    let (total_tokens, completion_tokens, prompt_tokens, cached_tokens) = match &text_response.usage {
+        Some(usage) => (usage.total_tokens, usage.completion_tokens, usage.prompt_tokens, usage.cached_tokens()),
+        None => {
+            let completion_tokens = text_response.choices.iter().map(|choice| count_model_tokens(&req_info.model_name, &choice.text)).sum::<u32>();
+            (req_info.prompt_tokens + completion_tokens, completion_tokens, req_info.prompt_tokens, 0)
+        }
+    };
+    record_usage(&req_info, total_tokens, completion_tokens, prompt_tokens, cached_tokens).await.map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(text_response))
+}
+
+// Handle streaming `/v1/completions` responses
+pub async fn text_completions_response_stream(response: Response, mut req_info: RequestInfo) -> Result<HttpResponse, Error> {
+    let req_model_name = req_info.req_model_name.clone();
+    let inflight = req_info.inflight.take();
+    let stream = async_stream::stream! {
+        // Keep the service counted as busy until the stream ends
+        let _inflight = inflight;
+        let mut last_usage: Option<CompletionsUsage> = None;
+        let mut completion_text = String::new();
+        let mut events = Box::pin(upstream_events(response));
+        while let Some(event) = events.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    yield Err(err);
+                    continue;
+                }
+            };
+
+            let mut text_response: TextCompletionsResponse = match serde_json::from_str(&event.data) {
+                Ok(text_response) => text_response,
+                Err(err) => {
+                    yield Err(format!("Chunk failed to deserialize into TextCompletionsResponse: {}, Err: {}", event.data, err));
+                    continue;
+                },
+            };
+            text_response.model = req_model_name.clone();
+
+            for choice in &text_response.choices {
+                completion_text.push_str(&choice.text);
+            }
+            // Usage may come on every chunk (vLLM `continuous_usage_stats`), the last one has the totals
+            let usage = if req_info.include_usage { None } else { text_response.usage.take() };
+            if !(usage.is_some() && text_response.choices.is_empty()) {
+                let chunk_str = format!("data: {}\n\n", serde_json::to_string(&text_response).unwrap());
+                yield Ok::<Bytes, String>(Bytes::from(chunk_str));
+            }
+            if let Some(usage) = usage.or(text_response.usage) {
+                last_usage = Some(usage);
+            }
+        }
 
-    // 判断是否为first chunk
-    if let Some(role) = &chat_response.choices[0].delta.role {
-        let first_chunk = json!({
+        let result = match &last_usage {
+            Some(usage) => record_usage(&req_info, usage.total_tokens, usage.completion_tokens, usage.prompt_tokens, usage.cached_tokens()).await,
+            // The upstream ignored `include_usage`, count the tokens ourselves
+            None => {
+                let completion_tokens = count_model_tokens(&req_info.model_name, &completion_text);
+                let prompt_tokens = req_info.prompt_tokens;
+                record_usage(&req_info, prompt_tokens + completion_tokens, completion_tokens, prompt_tokens, 0).await
+            }
+        };
+        if let Err(err) = result {
+            yield Err(err);
+        }
+        yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
+    };
+
+    Ok(HttpResponse::Ok().content_type("text/event-stream").streaming(stream))
+}
+
+// The chunks forwarded for an upstream chunk: its choices, with the usage when the client asked for it,
+// and a chunk of its own for the usage sent without choices
+pub fn stream_chunks(chat_response: &CompletionsStreamResponse, req_model_name: &str, include_usage: bool) -> Vec<Value> {
+    let usage = chat_response.usage.as_ref().filter(|_| include_usage);
+    if chat_response.choices.is_empty() {
+        return usage.map(|usage| json!({
             "id": chat_response.id,
-            "model": model_name,
+            "model": req_model_name,
             "created": chat_response.created,
             "object": chat_response.object,
-            "choices": [
-                {
-                    "index": chat_response.choices[0].index,
-                    "delta": {
-                        "role": role,
-                        "content": chat_response.choices[0].delta.content
-                    },
-                    "finish_reason": null,
-                    "logprobs": chat_response.choices[0].logprobs
-                }
-            ]
-        });
-    
-        return Ok(first_chunk);
+            "choices": [],
+            "usage": usage
+        })).into_iter().collect();
+    }
+    let mut chunk = transfer_chunk(chat_response, req_model_name.to_string());
+    if let Some(usage) = usage {
+        chunk["usage"] = json!(usage);
     }
+    vec![chunk]
+}
 
-    // 其他情况
-    let normal_chunk = json!({
+fn transfer_chunk(chat_response: &CompletionsStreamResponse, model_name: String) -> Value {
+    // With `n > 1` a chunk may carry deltas of several choices, rewrite each of them
+    let choices: Vec<Value> = chat_response.choices.iter().map(transfer_choice).collect();
+
+    let chunk = json!({
         "id": chat_response.id,
-        "model": chat_response.model,
+        "model": model_name,
         "created": chat_response.created,
         "object": chat_response.object,
-        "choices": [
-            {
-                "index": chat_response.choices[0].index,
-                "delta": {
-                    "content": chat_response.choices[0].delta.content
-                },
-                "finish_reason": "",
-                "logprobs": chat_response.choices[0].logprobs
-            }
-        ] 
+        "choices": choices
     });
 
-    return Ok(normal_chunk);
+    chunk
+}
+
+// The generated text of a streamed choice: content, refusal and tool call arguments
+fn push_delta_text(text: &mut String, choice: &CompletionsStreamChoice) {
+    let delta = &choice.delta;
+    for piece in [&delta.content, &delta.refusal].into_iter().flatten() {
+        text.push_str(piece);
+    }
+    for tool_call in delta.tool_calls.iter().flatten() {
+        if let Some(arguments) = tool_call.function.as_ref().and_then(|function| function.arguments.as_ref()) {
+            text.push_str(arguments);
+        }
+    }
+}
+
+// Log the usage of a request and count its tokens against the quotas that applied to it
+async fn record_usage(req_info: &RequestInfo, total_tokens: u32, completion_tokens: u32, prompt_tokens: u32, cached_tokens: u32) -> Result<(), String> {
+    let config = &*GLOBAL_CONFIG;
+    push_usage(req_info, config, total_tokens, completion_tokens, prompt_tokens, cached_tokens);
+
+    if config.quota_enabled() {
+        match consume(&req_info.quota, &req_info.model_name, total_tokens).await {
+            Ok(status) if status == "success" => {}
+            _ => return Err("Failed to consume tokens".to_string()),
+        }
+    }
+    Ok(())
 }
 
+fn transfer_choice(choice: &CompletionsStreamChoice) -> Value {
+    // Every field of the delta is forwarded: content, role, refusal, legacy `function_call`, tool call
+    // fragments (clients join them by `index`) and fields of other upstreams
+    let mut delta = json!(choice.delta);
+
+    // 判断是否为该choice的stop chunk
+    if let Some(finish_reason) = choice.finish_reason.as_deref().filter(|reason| !reason.is_empty()) {
+        if delta["content"].is_null() {
+            delta["content"] = json!("");
+        }
+        return json!({
+            "index": choice.index,
+            "delta": delta,
+            "finish_reason": finish_reason,
+            "stop_reason": null,
+            "logprobs": choice.logprobs
+        });
+    }
+
+    // 判断是否为该choice的first chunk
+    if choice.delta.role.is_some() {
+        return json!({
+            "index": choice.index,
+            "delta": delta,
+            "finish_reason": null,
+            "logprobs": choice.logprobs
+        });
+    }
 
-fn push_kafka_data(model_name: String,
+    // 其他情况
+    json!({
+        "index": choice.index,
+        "delta": delta,
+        "finish_reason": "",
+        "logprobs": choice.logprobs
+    })
+}
+
+
+// The usage event and record keep the model that served the request and the name the client asked for.
+// The cost is priced by the catalog model and counted against the budgets of the account and the key.
+fn push_usage(req_info: &RequestInfo,
                    config: &Config,
                    total_tokens: u32,
                    completion_tokens: u32,
                    prompt_tokens: u32,
-                   userid: String,
-                   appkey: String,
-                   start_time: DateTime<Tz>) {
+                   cached_tokens: u32) {
+    let table = route_table();
+    let cost = table.model(&req_info.model_name).map_or(0.0, |model| model.cost(prompt_tokens, cached_tokens, completion_tokens));
+    let budgets = table.budgets(&req_info.quota);
+
     let utc_time = Utc::now().with_timezone(&Shanghai); // 转换为上海时间
     let end_time = Utc::now().with_timezone(&Shanghai); // 转换为上海时间
     let data: Value = json!({
-        "accountId": userid,
+        "accountId": req_info.userid,
         "cloudRegionName": config.cloud_region_name,
         "cloudRegionId": config.cloud_region_id,
-        "modelName": model_name,
-        "appKey": appkey,
-        "startTime": start_time.with_timezone(&Shanghai).to_rfc3339(),
+        "modelName": req_info.model_name,
+        "requestedModel": req_info.req_model_name,
+        "variant": req_info.variant,
+        "appKey": req_info.appkey,
+        "startTime": req_info.start_time.with_timezone(&Shanghai).to_rfc3339(),
         "endTime": end_time.to_rfc3339(),
         "totalTokens": total_tokens,
         "completionTokens": completion_tokens,
         "promptTokens": prompt_tokens,
+        "cachedTokens": cached_tokens,
+        "cost": cost,
         "time": utc_time.to_rfc3339(),
     });
-    let kafka_json: String = serde_json::to_string(&data).unwrap();
-    info!(target: "token", "{}", kafka_json);
+    emit_usage_event(serde_json::to_string(&data).unwrap());
+
+    let tokens = |tokens: u32| i32::try_from(tokens).unwrap_or(i32::MAX);
+    let record = UsageRecord {
+        id: 0,
+        account_id: req_info.userid.clone(),
+        api_key: req_info.quota.key.clone(),
+        project: req_info.quota.project.clone(),
+        model: req_info.model_name.clone(),
+        requested_model: req_info.req_model_name.clone(),
+        variant: req_info.variant.clone(),
+        prompt_tokens: tokens(prompt_tokens),
+        completion_tokens: tokens(completion_tokens),
+        total_tokens: tokens(total_tokens),
+        cached_tokens: tokens(cached_tokens),
+        cost,
+        start_time: req_info.start_time.timestamp(),
+        end_time: end_time.timestamp(),
+    };
+    // One task, spend first: a reload of the spend from `usage_records` must not already include this record
+    tokio::spawn(async move {
+        record_spend(&budgets, cost).await;
+        store_usage_record(record).await;
+    });
 }
\ No newline at end of file
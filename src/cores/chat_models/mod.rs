@@ -1,3 +1,5 @@
 pub mod chat_controller;
 pub mod chat_utils;
+pub mod chat_registry;
+pub mod chat_router;
 pub mod support_models;
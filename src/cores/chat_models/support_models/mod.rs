@@ -1,5 +1 @@
-pub mod deepseek;
-pub mod bailian;
-pub mod llama;
-pub mod qwen;
-pub mod glm;
\ No newline at end of file
+pub mod openai;
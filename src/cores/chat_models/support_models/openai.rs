@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response};
+use serde_json::Value;
+
+use crate::meta::services::traits::ServiceConfig;
+use crate::cores::chat_models::chat_controller::Completions;
+use crate::cores::control::clients::get_client;
+
+// Any backend exposing the OpenAI `/v1/chat/completions` protocol (vllm, mindie, bailian, deepseek, ...)
+pub struct OpenAICompatible;
+
+#[async_trait]
+impl Completions for OpenAICompatible {
+    async fn completions(&self, service: &ServiceConfig, request_body: &Value) -> Result<Response, reqwest::Error> {
+        let client = get_client(&service.id)?;
+
+        with_api_key(client.post(&service.url), service)
+            .header("Content-Type", "application/json")
+            .json(request_body)
+            .send()
+            .await
+    }
+
+    async fn text_completions(&self, service: &ServiceConfig, request_body: &Value) -> Result<Response, reqwest::Error> {
+        let client = get_client(&service.id)?;
+
+        with_api_key(client.post(text_completions_url(&service.url)), service)
+            .header("Content-Type", "application/json")
+            .json(request_body)
+            .send()
+            .await
+    }
+}
+
+// Hosted services (bailian, deepseek, ...) take the key of the service as a bearer token
+pub fn with_api_key(request: RequestBuilder, service: &ServiceConfig) -> RequestBuilder {
+    if service.api_key.is_empty() {
+        request
+    } else {
+        request.bearer_auth(&service.api_key)
+    }
+}
+
+// Services are registered with their chat url, the prompt based API sits next to it:
+// `http://ip:port/v1/chat/completions` -> `http://ip:port/v1/completions`
+pub fn text_completions_url(chat_url: &str) -> String {
+    match chat_url.trim_end_matches('/').strip_suffix("/chat/completions") {
+        Some(base) => format!("{}/completions", base),
+        None => chat_url.to_string(),
+    }
+}
+
+// The model list next to the chat url, which every OpenAI-compatible service serves:
+// `https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions` -> `.../compatible-mode/v1/models`
+pub fn models_url(chat_url: &str) -> String {
+    let chat_url = chat_url.trim_end_matches('/');
+    format!("{}/models", chat_url.strip_suffix("/chat/completions").unwrap_or(chat_url))
+}
@@ -0,0 +1,148 @@
+use once_cell::sync::Lazy;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::meta::services::traits::ServiceConfig;
+
+// Smoothing factor of the latency moving average, higher values follow recent requests faster
+const EWMA_ALPHA: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Random,
+    WeightedRoundRobin,
+    LeastRequests,
+    Latency,
+}
+
+pub const STRATEGIES: [&str; 4] = ["random", "weighted_round_robin", "least_requests", "latency"];
+
+impl Strategy {
+    pub fn parse(name: &str) -> Result<Strategy, String> {
+        match name {
+            "random" => Ok(Strategy::Random),
+            "weighted_round_robin" => Ok(Strategy::WeightedRoundRobin),
+            "least_requests" => Ok(Strategy::LeastRequests),
+            "latency" => Ok(Strategy::Latency),
+            _ => Err(format!("Load balance strategy {} is not one of {}", name, STRATEGIES.join(", "))),
+        }
+    }
+
+    // Per-model strategy from `load_balance_models`, otherwise `load_balance_strategy`.
+    // Both are checked when the config is loaded.
+    pub fn for_model(model: &str) -> Strategy {
+        let config = &*GLOBAL_CONFIG;
+        let name = config.load_balance_models.get(model).unwrap_or(&config.load_balance_strategy);
+        Strategy::parse(name).unwrap_or(Strategy::Random)
+    }
+}
+
+#[derive(Default)]
+struct ServiceStats {
+    inflight: AtomicUsize,
+    ewma_latency_ms: Mutex<Option<f64>>,
+}
+
+static STATS: Lazy<RwLock<HashMap<String, Arc<ServiceStats>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+// Current weights of the smooth weighted round-robin, per model and service id
+static WRR_STATE: Lazy<Mutex<HashMap<String, HashMap<String, i64>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn stats(service_id: &str) -> Arc<ServiceStats> {
+    if let Some(stats) = STATS.read().unwrap().get(service_id) {
+        return stats.clone();
+    }
+    STATS.write().unwrap().entry(service_id.to_string()).or_default().clone()
+}
+
+// Counts a request as outstanding on a service until the guard is dropped.
+// Streaming responses keep the guard alive until the last chunk is sent.
+pub struct InflightGuard {
+    stats: Arc<ServiceStats>,
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.stats.inflight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn begin_request(service_id: &str) -> InflightGuard {
+    let stats = stats(service_id);
+    stats.inflight.fetch_add(1, Ordering::Relaxed);
+    InflightGuard { stats }
+}
+
+pub fn inflight(service_id: &str) -> usize {
+    stats(service_id).inflight.load(Ordering::Relaxed)
+}
+
+pub fn record_latency(service_id: &str, latency: Duration) {
+    let stats = stats(service_id);
+    let mut ewma = stats.ewma_latency_ms.lock().unwrap();
+    let sample = latency.as_secs_f64() * 1000.0;
+    *ewma = Some(match *ewma {
+        Some(value) => EWMA_ALPHA * sample + (1.0 - EWMA_ALPHA) * value,
+        None => sample,
+    });
+}
+
+pub fn ewma_latency_ms(service_id: &str) -> Option<f64> {
+    *stats(service_id).ewma_latency_ms.lock().unwrap()
+}
+
+// Smooth weighted round-robin (as in nginx): every candidate gains its weight, the largest wins and pays back the total
+pub fn pick_weighted(model: &str, services: &[ServiceConfig]) -> usize {
+    let mut state = WRR_STATE.lock().unwrap();
+    let current = state.entry(model.to_string()).or_default();
+    current.retain(|id, _| services.iter().any(|service| &service.id == id));
+
+    let total: i64 = services.iter().map(|service| service.weight.max(0) as i64).sum();
+    let mut best = 0;
+    let mut best_weight = i64::MIN;
+    for (index, service) in services.iter().enumerate() {
+        let weight = current.entry(service.id.clone()).or_insert(0);
+        *weight += service.weight.max(0) as i64;
+        if *weight > best_weight {
+            best_weight = *weight;
+            best = index;
+        }
+    }
+    *current.get_mut(&services[best].id).unwrap() -= total;
+    best
+}
+
+// Order the candidate replicas of a model: the first one receives the request, the rest are failover targets
+pub fn order_services(model: &str, services: Vec<ServiceConfig>) -> Vec<ServiceConfig> {
+    order_with_strategy(model, services, Strategy::for_model(model))
+}
+
+pub fn order_with_strategy(model: &str, mut services: Vec<ServiceConfig>, strategy: Strategy) -> Vec<ServiceConfig> {
+    if services.len() <= 1 {
+        return services;
+    }
+    services.shuffle(&mut rand::thread_rng());
+
+    match strategy {
+        Strategy::Random => {}
+        Strategy::WeightedRoundRobin => {
+            let chosen = pick_weighted(model, &services);
+            services.swap(0, chosen);
+        }
+        Strategy::LeastRequests => {
+            services.sort_by_key(|service| inflight(&service.id));
+        }
+        Strategy::Latency => {
+            // Latency weighted by the outstanding requests; services without samples go first so they get measured
+            let cost = |service: &ServiceConfig| {
+                ewma_latency_ms(&service.id).unwrap_or(0.0) * (inflight(&service.id) + 1) as f64
+            };
+            services.sort_by(|a, b| cost(a).total_cmp(&cost(b)));
+        }
+    }
+    services
+}
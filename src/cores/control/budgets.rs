@@ -0,0 +1,251 @@
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::{error, info};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::meta::budgets::traits::{Budget, BudgetsTrait, BUDGET_SCOPES};
+use crate::meta::budgets::impls::BudgetsImpl;
+use crate::meta::usage::impls::UsageRecordsImpl;
+use crate::meta::usage::traits::UsageRecordsTrait;
+use crate::cores::control::models::CatalogError;
+use crate::cores::control::model_limits::QuotaSubjects;
+use crate::cores::control::route_cache::refresh_route_table_or_log;
+
+pub struct BudgetManager {
+    budgets: Box<dyn BudgetsTrait>,
+}
+
+// Default implementation for BudgetManager
+impl Default for BudgetManager {
+    fn default() -> Self {
+        BudgetManager {
+            budgets: Box::new(BudgetsImpl),
+        }
+    }
+}
+
+impl BudgetManager {
+    pub async fn create_budget(&self, budget: &Budget) -> Result<(), Box<dyn Error>> {
+        check_budget(budget)?;
+        if self.budgets.get_budget(&budget.id).await?.is_some() {
+            return Err(Box::new(CatalogError::Conflict(format!("Budget {} already exists", budget.id))));
+        }
+        if let Some(other) = self.get_all_budgets().await?.into_iter().find(|other| other.scope == budget.scope && other.subject == budget.subject) {
+            return Err(Box::new(CatalogError::Conflict(format!("Budget {} already covers {} {}", other.id, budget.scope, budget.subject))));
+        }
+
+        self.budgets.create_budget(budget).await?;
+        refresh_route_table_or_log().await;
+        Ok(())
+    }
+
+    pub async fn update_budget(&self, budget: &Budget) -> Result<u64, Box<dyn Error>> {
+        check_budget(budget)?;
+
+        let rows_updated = self.budgets.update_budget(budget).await?;
+        refresh_route_table_or_log().await;
+        Ok(rows_updated)
+    }
+
+    pub async fn delete_budget(&self, id: &str) -> Result<u64, Box<dyn Error>> {
+        let delete_num = self.budgets.delete_budget(id).await?;
+        refresh_route_table_or_log().await;
+        Ok(delete_num)
+    }
+
+    pub async fn get_budget(&self, id: &str) -> Result<Option<Budget>, Box<dyn Error>> {
+        self.budgets.get_budget(id).await
+    }
+
+    pub async fn get_all_budgets(&self) -> Result<Vec<Budget>, Box<dyn Error>> {
+        let mut budgets = self.budgets.get_all_budgets().await?;
+        budgets.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(budgets)
+    }
+}
+
+fn check_budget(budget: &Budget) -> Result<(), Box<dyn Error>> {
+    if !BUDGET_SCOPES.contains(&budget.scope.as_str()) {
+        return Err(Box::new(CatalogError::Invalid(format!("Scope {} is not one of {}", budget.scope, BUDGET_SCOPES.join(", ")))));
+    }
+    if budget.subject.is_empty() {
+        return Err(Box::new(CatalogError::Invalid("Subject cannot be empty, use \"*\" for every subject".to_string())));
+    }
+    if !budget.monthly_limit.is_finite() || !budget.soft_limit.is_finite() || budget.monthly_limit < 0.0 || budget.soft_limit < 0.0 {
+        return Err(Box::new(CatalogError::Invalid("monthly_limit and soft_limit cannot be negative".to_string())));
+    }
+    if budget.monthly_limit > 0.0 && budget.soft_limit > budget.monthly_limit {
+        return Err(Box::new(CatalogError::Invalid("soft_limit cannot be above monthly_limit".to_string())));
+    }
+    Ok(())
+}
+
+// The budgets that apply to a request, each with the subject its spend is counted for.
+// In each scope the budget naming the subject wins over the "*" budget.
+pub fn matching_budgets(budgets: &[Budget], subjects: &QuotaSubjects) -> Vec<(String, Budget)> {
+    let mut matched = Vec::new();
+    for scope in BUDGET_SCOPES {
+        let subject = subjects.subject(scope);
+        if subject.is_empty() {
+            continue;
+        }
+        let in_scope = || budgets.iter().filter(|budget| budget.scope == scope);
+        if let Some(budget) = in_scope().find(|budget| budget.subject == subject).or_else(|| in_scope().find(|budget| budget.subject == "*")) {
+            matched.push((format!("{}:{}", scope, subject), budget.clone()));
+        }
+    }
+    matched
+}
+
+// The start of the month `now` falls in, as a Unix timestamp
+pub fn month_start(now: i64, tz: Tz) -> i64 {
+    let local = DateTime::from_timestamp(now, 0).unwrap_or_default().with_timezone(&tz);
+    tz.with_ymd_and_hms(local.year(), local.month(), 1, 0, 0, 0)
+        .earliest()
+        .map(|start| start.timestamp())
+        .unwrap_or(now)
+}
+
+// The spend of a subject in the current month
+struct MonthSpend {
+    month_start: i64,
+    spent: f64,
+    loaded_at: i64,
+}
+
+// Spend per budget subject, loaded from `usage_records` and kept up to date with the cost of every request
+// this instance serves. Entries are loaded again after `refresh_secs`, which picks up the spend of other
+// gateway instances; without usage records they are only counted in memory.
+pub struct BudgetTracker {
+    records: Box<dyn UsageRecordsTrait>,
+    refresh_secs: Option<i64>,
+    tz: Tz,
+    spend: Mutex<HashMap<String, MonthSpend>>,
+}
+
+impl Default for BudgetTracker {
+    fn default() -> Self {
+        let config = &*GLOBAL_CONFIG;
+        let tz = config.budget_timezone.parse().unwrap_or_else(|err| {
+            error!(target: "error_log", "Invalid budget_timezone {}, using UTC: {}", config.budget_timezone, err);
+            Tz::UTC
+        });
+        BudgetTracker::new(Box::new(UsageRecordsImpl), config.usage_records_enabled.then_some(config.budget_refresh_secs as i64), tz)
+    }
+}
+
+static BUDGET_TRACKER: Lazy<BudgetTracker> = Lazy::new(BudgetTracker::default);
+
+static WEBHOOK_CLIENT: Lazy<Client> = Lazy::new(|| Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default());
+
+impl BudgetTracker {
+    pub fn new(records: Box<dyn UsageRecordsTrait>, refresh_secs: Option<i64>, tz: Tz) -> Self {
+        BudgetTracker { records, refresh_secs, tz, spend: Mutex::new(HashMap::new()) }
+    }
+
+    // The month's spend of a "scope:subject"
+    pub async fn spent(&self, subject: &str, now: i64) -> Result<f64, Box<dyn Error>> {
+        let month = month_start(now, self.tz);
+        if let Some(entry) = self.spend.lock().unwrap().get(subject) {
+            let fresh = self.refresh_secs.is_none_or(|refresh| now - entry.loaded_at < refresh);
+            if entry.month_start == month && fresh {
+                return Ok(entry.spent);
+            }
+        }
+
+        let (scope, id) = subject.split_once(':').unwrap_or(("account", subject));
+        let column = if scope == "key" { "api_key" } else { "account_id" };
+        let spent = if self.refresh_secs.is_some() { self.records.get_spend(column, id, month).await? } else { 0.0 };
+        self.spend.lock().unwrap().insert(subject.to_string(), MonthSpend { month_start: month, spent, loaded_at: now });
+        Ok(spent)
+    }
+
+    // The first budget whose monthly limit is used up, with its subject and spend
+    pub async fn exceeded(&self, budgets: &[(String, Budget)], now: i64) -> Result<Option<(String, Budget, f64)>, Box<dyn Error>> {
+        for (subject, budget) in budgets.iter().filter(|(_, budget)| budget.monthly_limit > 0.0) {
+            let spent = self.spent(subject, now).await?;
+            if spent >= budget.monthly_limit {
+                return Ok(Some((subject.clone(), budget.clone(), spent)));
+            }
+        }
+        Ok(None)
+    }
+
+    // Add the cost of a finished request. The spend of each budget with a limit is loaded first, so a soft limit
+    // is checked against the month's spend. Returns the budgets whose soft limit it crossed, with the spend after it.
+    pub async fn add_spend(&self, budgets: &[(String, Budget)], cost: f64, now: i64) -> Result<Vec<(String, Budget, f64)>, Box<dyn Error>> {
+        let mut crossed = Vec::new();
+        for (subject, budget) in budgets.iter().filter(|(_, budget)| budget.monthly_limit > 0.0 || budget.soft_limit > 0.0) {
+            self.spent(subject, now).await?;
+            let mut spend = self.spend.lock().unwrap();
+            let Some(entry) = spend.get_mut(subject) else { continue };
+            let before = entry.spent;
+            entry.spent += cost;
+            if budget.soft_limit > 0.0 && before < budget.soft_limit && entry.spent >= budget.soft_limit {
+                crossed.push((subject.clone(), budget.clone(), entry.spent));
+            }
+        }
+        Ok(crossed)
+    }
+}
+
+// The budget a request would go over, as the message it is rejected with
+pub async fn check_budgets(budgets: &[(String, Budget)]) -> Result<Option<String>, Box<dyn Error>> {
+    Ok(BUDGET_TRACKER.exceeded(budgets, Utc::now().timestamp()).await?.map(|(subject, budget, spent)| {
+        let (scope, _) = subject.split_once(':').unwrap_or((&budget.scope, ""));
+        format!("You exceeded your current quota: the monthly budget of this {} is {}, {:.4} has been spent.", scope, budget.monthly_limit, spent)
+    }))
+}
+
+// Count the cost of a finished request and warn about the soft limits it crossed.
+// Called before the request's usage record is stored, so that the spend loaded from `usage_records`
+// doesn't include the cost it adds.
+pub async fn record_spend(budgets: &[(String, Budget)], cost: f64) {
+    if budgets.is_empty() || cost <= 0.0 {
+        return;
+    }
+    let now = Utc::now().timestamp();
+    let crossed = match BUDGET_TRACKER.add_spend(budgets, cost, now).await {
+        Ok(crossed) => crossed,
+        Err(err) => {
+            error!(target: "error_log", "Failed to load the budget spend: {}", err);
+            return;
+        }
+    };
+    for (subject, budget, spent) in crossed {
+        info!(target: "access_log", "Budget {} of {} reached its soft limit {}: {:.4} spent this month", budget.id, subject, budget.soft_limit, spent);
+        let url = &GLOBAL_CONFIG.budget_webhook_url;
+        if url.is_empty() {
+            continue;
+        }
+        if let Err(err) = notify_soft_limit(url, &subject, &budget, spent, month_start(now, BUDGET_TRACKER.tz)).await {
+            error!(target: "error_log", "Failed to send the soft limit warning of budget {} for {}: {}", budget.id, subject, err);
+        }
+    }
+}
+
+async fn notify_soft_limit(url: &str, subject: &str, budget: &Budget, spent: f64, month_start: i64) -> Result<(), Box<dyn Error>> {
+    let (scope, id) = subject.split_once(':').unwrap_or((&budget.scope, subject));
+    let body = json!({
+        "event": "budget.soft_limit",
+        "budget_id": budget.id,
+        "scope": scope,
+        "subject": id,
+        "month_start": month_start,
+        "spent": spent,
+        "soft_limit": budget.soft_limit,
+        "monthly_limit": budget.monthly_limit,
+    });
+    let response = WEBHOOK_CLIENT.post(url).json(&body).send().await?;
+    if !response.status().is_success() {
+        return Err(format!("webhook returned {}", response.status()).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::configs::settings::GLOBAL_CONFIG;
+
+// One pooled client per service id, so keep-alive connections are reused across chat requests.
+// The entry is dropped whenever the service is changed through /v1/services and rebuilt on next use.
+static CLIENTS: Lazy<RwLock<HashMap<String, Client>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+fn build_client() -> Result<Client, reqwest::Error> {
+    let config = &*GLOBAL_CONFIG;
+    let mut builder = Client::builder()
+        .pool_max_idle_per_host(config.upstream_pool_max_idle)
+        .pool_idle_timeout(Duration::from_secs(config.upstream_pool_idle_timeout))
+        .connect_timeout(Duration::from_secs(config.upstream_connect_timeout))
+        .tcp_keepalive(Duration::from_secs(60));
+    if config.upstream_request_timeout > 0 {
+        builder = builder.timeout(Duration::from_secs(config.upstream_request_timeout));
+    }
+    if config.upstream_http2 {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder.build()
+}
+
+pub fn get_client(service_id: &str) -> Result<Client, reqwest::Error> {
+    if let Some(client) = CLIENTS.read().unwrap().get(service_id) {
+        return Ok(client.clone());
+    }
+
+    let client = build_client()?;
+    let mut clients = CLIENTS.write().unwrap();
+    Ok(clients.entry(service_id.to_string()).or_insert(client).clone())
+}
+
+pub fn invalidate_client(service_id: &str) {
+    CLIENTS.write().unwrap().remove(service_id);
+}
+
+pub fn invalidate_all_clients() {
+    CLIENTS.write().unwrap().clear();
+}
@@ -0,0 +1,243 @@
+use chrono::Utc;
+use log::{info, error};
+use once_cell::sync::Lazy;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::cores::control::route_cache::route_table;
+use crate::meta::services::traits::ServiceConfig;
+use crate::cores::chat_models::support_models::openai::{models_url, with_api_key};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,     // requests flow normally
+    Open,       // service is skipped by routing until `breaker_open_secs` elapse
+    HalfOpen,   // cool-down elapsed, one trial request or a probe decides
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ServiceHealth {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_checked: Option<i64>,   // Unix timestamp of the last probe or request outcome
+    #[serde(skip)]
+    opened_at: Option<Instant>,
+    #[serde(skip)]
+    trial_started: Option<Instant>,  // the half-open trial request in flight
+}
+
+impl Default for ServiceHealth {
+    fn default() -> Self {
+        ServiceHealth {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            last_error: None,
+            last_checked: None,
+            opened_at: None,
+            trial_started: None,
+        }
+    }
+}
+
+impl ServiceHealth {
+    // Whether a request could be sent now, without taking the trial of a half-open service
+    pub fn available(&self, now: Instant, open_for: Duration) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::HalfOpen => !self.trial_in_flight(now, open_for),
+            BreakerState::Open => self.cooled_down(now, open_for),
+        }
+    }
+
+    // Whether a request may be sent now, taking the trial of a half-open service. It takes one trial
+    // request at a time, a trial without an outcome after `open_for` is given up so another request can try.
+    pub fn admit(&mut self, now: Instant, open_for: Duration) -> bool {
+        if self.state == BreakerState::Open {
+            if !self.cooled_down(now, open_for) {
+                return false;
+            }
+            self.state = BreakerState::HalfOpen;
+            self.trial_started = None;
+        }
+        if self.state == BreakerState::Closed {
+            return true;
+        }
+        if self.trial_in_flight(now, open_for) {
+            return false;
+        }
+        self.trial_started = Some(now);
+        true
+    }
+
+    fn cooled_down(&self, now: Instant, open_for: Duration) -> bool {
+        self.opened_at
+            .map(|opened_at| now.saturating_duration_since(opened_at) >= open_for)
+            .unwrap_or(true)
+    }
+
+    fn trial_in_flight(&self, now: Instant, open_for: Duration) -> bool {
+        self.trial_started.is_some_and(|started| now.saturating_duration_since(started) < open_for)
+    }
+
+    // Returns whether the breaker was closed by it
+    pub fn record_success(&mut self) -> bool {
+        let closed = self.state != BreakerState::Closed;
+        self.state = BreakerState::Closed;
+        self.consecutive_failures = 0;
+        self.opened_at = None;
+        self.trial_started = None;
+        self.last_checked = Some(Utc::now().timestamp());
+        closed
+    }
+
+    // A probe answering says the service is up, not that it serves requests: failures of real requests
+    // are kept, and an open breaker only turns half-open so that a trial request decides.
+    // Returns whether the breaker turned half-open.
+    pub fn record_probe_success(&mut self) -> bool {
+        self.last_checked = Some(Utc::now().timestamp());
+        if self.state != BreakerState::Open {
+            return false;
+        }
+        self.state = BreakerState::HalfOpen;
+        self.trial_started = None;
+        true
+    }
+
+    // Returns whether the breaker was opened by it
+    pub fn record_failure(&mut self, reason: String, threshold: u32, now: Instant) -> bool {
+        self.consecutive_failures += 1;
+        self.last_error = Some(reason);
+        self.last_checked = Some(Utc::now().timestamp());
+        self.trial_started = None;
+
+        let should_open = self.state == BreakerState::HalfOpen
+            || (self.state == BreakerState::Closed && self.consecutive_failures >= threshold);
+        if should_open {
+            self.state = BreakerState::Open;
+            self.opened_at = Some(now);
+        }
+        should_open
+    }
+}
+
+// Circuit breaker per service id, fed by both the background prober and real chat requests
+static HEALTH: Lazy<RwLock<HashMap<String, ServiceHealth>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+pub fn get_health(service_id: &str) -> ServiceHealth {
+    HEALTH.read().unwrap().get(service_id).cloned().unwrap_or_default()
+}
+
+pub fn remove_health(service_id: &str) {
+    HEALTH.write().unwrap().remove(service_id);
+}
+
+// Whether routing may consider the service, used to filter the candidates of a request
+pub fn is_available(service: &ServiceConfig) -> bool {
+    if !service.status.eq_ignore_ascii_case("active") {
+        return false;
+    }
+
+    let config = &*GLOBAL_CONFIG;
+    match HEALTH.read().unwrap().get(&service.id) {
+        Some(entry) => entry.available(Instant::now(), Duration::from_secs(config.breaker_open_secs)),
+        None => true,
+    }
+}
+
+// Called just before a request is sent to the service. An open breaker turns half-open once the cool-down
+// is over, then lets a single trial request through until its outcome is recorded; the other requests go elsewhere.
+pub fn admit(service_id: &str) -> bool {
+    let config = &*GLOBAL_CONFIG;
+    match HEALTH.write().unwrap().get_mut(service_id) {
+        Some(entry) => entry.admit(Instant::now(), Duration::from_secs(config.breaker_open_secs)),
+        None => true,
+    }
+}
+
+pub fn record_success(service_id: &str) {
+    let mut health = HEALTH.write().unwrap();
+    let entry = health.entry(service_id.to_string()).or_default();
+    if entry.record_success() {
+        info!(target: "access_log", "Circuit breaker for service {} closed", service_id);
+    }
+}
+
+pub fn record_failure(service_id: &str, reason: String) {
+    let config = &*GLOBAL_CONFIG;
+    let mut health = HEALTH.write().unwrap();
+    let entry = health.entry(service_id.to_string()).or_default();
+    if entry.record_failure(reason, config.breaker_failure_threshold, Instant::now()) {
+        error!(target: "error_log", "Circuit breaker for service {} opened after {} consecutive failures",
+            service_id, entry.consecutive_failures);
+    }
+}
+
+fn record_probe_success(service_id: &str) {
+    let mut health = HEALTH.write().unwrap();
+    let entry = health.entry(service_id.to_string()).or_default();
+    if entry.record_probe_success() {
+        info!(target: "access_log", "Circuit breaker for service {} half-open after a successful probe", service_id);
+    }
+}
+
+// The url a service is probed on: its `probe_path` resolved against the service url like a link
+// (`health` sits next to the chat url, `/health` on the origin), by default the `models` endpoint
+// next to the chat url. None when the service is not probed.
+pub fn probe_url(service: &ServiceConfig) -> Result<Option<Url>, String> {
+    let probe_path = service.probe_path.trim();
+    if probe_path.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    let url = if probe_path.is_empty() {
+        Url::parse(&models_url(&service.url))
+    } else {
+        Url::parse(&service.url).and_then(|base| base.join(probe_path))
+    };
+    url.map(Some).map_err(|err| format!("Invalid probe url: {}", err))
+}
+
+// GET the probe url with the service's credentials
+async fn probe_service(client: &Client, service: &ServiceConfig, url: Url) -> Result<(), String> {
+    match with_api_key(client.get(url.clone()), service).send().await {
+        Ok(resp) if resp.status().is_success() => Ok(()),
+        Ok(resp) => Err(format!("GET {} returned {}", url, resp.status())),
+        Err(err) => Err(format!("GET {} failed: {}", url, err)),
+    }
+}
+
+pub async fn probe_services() {
+    let config = &*GLOBAL_CONFIG;
+    let table = route_table();
+    let services = table.services();
+
+    let client = match Client::builder().timeout(Duration::from_secs(config.health_check_timeout)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            error!(target: "error_log", "Health check failed to build client: {}", err);
+            return;
+        }
+    };
+
+    let mut probes = Vec::new();
+    for service in services.iter() {
+        match probe_url(service) {
+            Ok(Some(url)) => probes.push(async {
+                (service.id.clone(), probe_service(&client, service, url).await)
+            }),
+            Ok(None) => {}
+            Err(err) => error!(target: "error_log", "Health check skipped service {}: {}", service.id, err),
+        }
+    }
+    for (service_id, result) in futures::future::join_all(probes).await {
+        match result {
+            Ok(()) => record_probe_success(&service_id),
+            Err(reason) => record_failure(&service_id, reason),
+        }
+    }
+}
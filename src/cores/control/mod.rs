@@ -1,3 +1,12 @@
 pub mod services;
 pub mod files;
-pub mod model_limits;
\ No newline at end of file
+pub mod model_limits;
+pub mod health;
+pub mod balancer;
+pub mod clients;
+pub mod route_cache;
+pub mod models;
+pub mod traffic_splits;
+pub mod rate_limits;
+pub mod quota;
+pub mod budgets;
\ No newline at end of file
@@ -1,7 +1,12 @@
+use actix_web::http::header::HeaderMap;
 use std::error::Error;
 
-use crate::meta::qos::traits::{Limits, LimitsTrait};
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::meta::qos::traits::{Limits, LimitsTrait, LIMIT_SCOPES};
 use crate::meta::qos::impls::LimitsImpl;
+use crate::cores::control::models::CatalogError;
+use crate::cores::control::quota::parse_quotas;
+use crate::cores::control::route_cache::refresh_route_table_or_log;
 
 
 pub struct LimitsManager {
@@ -23,22 +28,135 @@ impl LimitsManager{
     }
 
     pub async fn add_limits_object(&self, limits: Limits) -> Result<(), Box<dyn Error>> {
-        self.limits.add_limits_object(limits).await
+        check_limits(&limits)?;
+        if self.limits.get_limits_object(&limits.id).await?.is_some() {
+            return Err(Box::new(CatalogError::Conflict(format!("Model limits {} already exist", limits.id))));
+        }
+        if let Some(other) = self.get_all_limits_objects().await?.into_iter()
+            .find(|other| other.model_name == limits.model_name && other.scope == limits.scope && other.subject == limits.subject) {
+            return Err(Box::new(CatalogError::Conflict(format!("Model limits {} already cover {} {} of {}", other.id, limits.scope, limits.subject, limits.model_name))));
+        }
+
+        self.limits.add_limits_object(limits).await?;
+        refresh_route_table_or_log().await;
+        Ok(())
     }
 
-    pub async fn delete_limits_object(&self, model_name: &str) -> Result<(), Box<dyn Error>> {
-        self.limits.delete_limits_object(model_name).await
+    pub async fn delete_limits_object(&self, id: &str) -> Result<u64, Box<dyn Error>> {
+        let delete_num = self.limits.delete_limits_object(id).await?;
+        refresh_route_table_or_log().await;
+        Ok(delete_num)
     }
 
     pub async fn update_limits_object(&self, limits: Limits) -> Result<u64, Box<dyn Error>> {
-        self.limits.update_limits_object(limits).await
+        check_limits(&limits)?;
+
+        let rows_updated = self.limits.update_limits_object(limits).await?;
+        refresh_route_table_or_log().await;
+        Ok(rows_updated)
     }
 
-    pub async fn get_limits_object(&self, model_name: &str) -> Result<Option<Limits>, Box<dyn Error>> {
-        self.limits.get_limits_object(model_name).await
+    pub async fn get_limits_object(&self, id: &str) -> Result<Option<Limits>, Box<dyn Error>> {
+        self.limits.get_limits_object(id).await
     }
 
     pub async fn get_all_limits_objects(&self) -> Result<Vec<Limits>, Box<dyn Error>> {
-        self.limits.get_all_limits_objects().await
+        let mut limits = self.limits.get_all_limits_objects().await?;
+        limits.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(limits)
+    }
+}
+
+fn check_limits(limits: &Limits) -> Result<(), Box<dyn Error>> {
+    if !LIMIT_SCOPES.contains(&limits.scope.as_str()) {
+        return Err(Box::new(CatalogError::Invalid(format!("Scope {} is not one of {}", limits.scope, LIMIT_SCOPES.join(", ")))));
+    }
+    if limits.subject.is_empty() || limits.model_name.is_empty() {
+        return Err(Box::new(CatalogError::Invalid("Subject and model_name cannot be empty, use \"*\" for every subject".to_string())));
     }
-}
\ No newline at end of file
+    if limits.rpm < 0 || limits.tpm < 0 || limits.rpd < 0 || limits.tpd < 0 {
+        return Err(Box::new(CatalogError::Invalid("rpm, tpm, rpd and tpd cannot be negative".to_string())));
+    }
+    if day_quotas_unenforced(limits, &GLOBAL_CONFIG.quota_engine) {
+        return Err(Box::new(CatalogError::Invalid("rpd and tpd are only enforced by the local quota engine, set quota_engine to \"local\" or leave them 0".to_string())));
+    }
+    Ok(())
+}
+
+// The coil servers only count minute windows, day quotas are enforced by the local engine alone
+pub fn day_quotas_unenforced(limits: &Limits, engine: &str) -> bool {
+    engine != "local" && (limits.rpd > 0 || limits.tpd > 0)
+}
+
+// Who a request is counted for: its api key, the account the key belongs to and the project named
+// by the `OpenAI-Project` header. Empty when unknown, such as the account without authentication.
+#[derive(Clone, Debug, Default)]
+pub struct QuotaSubjects {
+    pub key: String,
+    pub account: String,
+    pub project: String,
+}
+
+// The header OpenAI clients send the project of a request in
+pub const PROJECT_HEADER: &str = "openai-project";
+
+impl QuotaSubjects {
+    pub fn new(headers: &HeaderMap, account: &str) -> Self {
+        let header = |name: &str| headers.get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        QuotaSubjects {
+            key: header("Authorization").replace("Bearer ", ""),
+            account: account.to_string(),
+            project: header(PROJECT_HEADER),
+        }
+    }
+
+    pub fn subject(&self, scope: &str) -> &str {
+        match scope {
+            "key" => &self.key,
+            "account" => &self.account,
+            "project" => &self.project,
+            _ => "",
+        }
+    }
+}
+
+// The limits of a model that apply to a request, each with the counter subject it is counted for.
+// In each scope the row naming the subject wins over the "*" row.
+pub fn matching_limits(limits: &[Limits], subjects: &QuotaSubjects) -> Vec<(String, Limits)> {
+    let mut matched = Vec::new();
+    for scope in LIMIT_SCOPES {
+        let subject = subjects.subject(scope);
+        if subject.is_empty() {
+            continue;
+        }
+        let in_scope = || limits.iter().filter(|limits| limits.scope == scope);
+        if let Some(limits) = in_scope().find(|limits| limits.subject == subject).or_else(|| in_scope().find(|limits| limits.subject == "*")) {
+            matched.push((format!("{}:{}", scope, subject), limits.clone()));
+        }
+    }
+    matched
+}
+
+// Convert a row of the text `model_limits` of earlier releases, which the coil servers counted per account.
+// Minute and day quotas are kept, other windows and values that don't parse are dropped.
+pub fn legacy_limits(model_name: &str, max_requests: &str, max_tokens: &str) -> Limits {
+    let amount = |limit: &str, secs: i64| -> i32 {
+        parse_quotas(limit).unwrap_or_default().iter()
+            .find(|quota| quota.secs == secs)
+            .map(|quota| quota.amount.clamp(0, i32::MAX as i64) as i32)
+            .unwrap_or(0)
+    };
+    Limits {
+        id: model_name.to_string(),
+        scope: "account".to_string(),
+        subject: "*".to_string(),
+        model_name: model_name.to_string(),
+        rpm: amount(max_requests, 60),
+        tpm: amount(max_tokens, 60),
+        rpd: amount(max_requests, 86400),
+        tpd: amount(max_tokens, 86400),
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::Utc;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::meta::models::traits::{Model, ModelAlias, ModelConfig, ModelsTrait};
+use crate::meta::models::impls::ModelsImpl;
+use crate::meta::services::traits::ServiceConfig;
+use crate::cores::control::services::ServiceManager;
+use crate::cores::control::route_cache::refresh_route_table_or_log;
+
+// Catalog changes that would leave models, aliases and services inconsistent
+#[derive(Debug)]
+pub enum CatalogError {
+    NotFound(String),
+    Conflict(String),
+    Invalid(String),
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CatalogError::NotFound(message) => write!(f, "{}", message),
+            CatalogError::Conflict(message) => write!(f, "{}", message),
+            CatalogError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CatalogError {}
+
+pub struct ModelManager {
+    models: Box<dyn ModelsTrait>,
+}
+
+// Default implementation for ModelManager
+impl Default for ModelManager {
+    fn default() -> Self {
+        ModelManager {
+            models: Box::new(ModelsImpl),
+        }
+    }
+}
+
+impl ModelManager {
+    pub async fn create_model(&self, model: &ModelConfig) -> Result<(), Box<dyn Error>> {
+        if self.models.get_model(&model.model.id).await?.is_some() {
+            return Err(Box::new(CatalogError::Conflict(format!("Model {} already exists", model.model.id))));
+        }
+        self.check_names(model).await?;
+
+        let mut model = model.clone();
+        if model.model.created == 0 {
+            model.model.created = Utc::now().timestamp();
+        }
+        self.models.create_model(&model).await?;
+        refresh_route_table_or_log().await;
+        Ok(())
+    }
+
+    pub async fn update_model(&self, model: &ModelConfig) -> Result<u64, Box<dyn Error>> {
+        if self.models.get_model(&model.model.id).await?.is_none() {
+            return Err(Box::new(CatalogError::NotFound(format!("Model {} not found", model.model.id))));
+        }
+        self.check_names(model).await?;
+
+        let rows_updated = self.models.update_model(model).await?;
+        refresh_route_table_or_log().await;
+        Ok(rows_updated)
+    }
+
+    // A model can only leave the catalog once no service serves it any more
+    pub async fn delete_model(&self, model_id: &str) -> Result<u64, Box<dyn Error>> {
+        let services = serving_services(model_id, &ServiceManager::default().get_all_services().await?);
+        if !services.is_empty() {
+            return Err(Box::new(CatalogError::Conflict(format!("Model {} is still served by services: {}", model_id, services.join(", ")))));
+        }
+
+        let delete_num = self.models.delete_model(model_id).await?;
+        refresh_route_table_or_log().await;
+        Ok(delete_num)
+    }
+
+    pub async fn get_model(&self, model_id: &str) -> Result<Option<ModelConfig>, Box<dyn Error>> {
+        Ok(self.get_all_models().await?.into_iter().find(|model| model.model.id == model_id))
+    }
+
+    pub async fn get_all_models(&self) -> Result<Vec<ModelConfig>, Box<dyn Error>> {
+        let models = self.models.get_all_models().await?;
+        let aliases = self.models.get_all_aliases().await?;
+        let services = ServiceManager::default().get_all_services().await?;
+
+        Ok(models.into_iter().map(|model| {
+            let mut model_aliases: Vec<String> = aliases.iter()
+                .filter(|alias| alias.model == model.id)
+                .map(|alias| alias.alias.clone())
+                .collect();
+            model_aliases.sort();
+            ModelConfig {
+                services: serving_services(&model.id, &services),
+                aliases: model_aliases,
+                model,
+            }
+        }).collect())
+    }
+
+    pub async fn get_all_aliases(&self) -> Result<Vec<ModelAlias>, Box<dyn Error>> {
+        let mut aliases = self.models.get_all_aliases().await?;
+        aliases.sort_by(|a, b| a.alias.cmp(&b.alias));
+        Ok(aliases)
+    }
+
+    pub async fn get_alias(&self, alias: &str) -> Result<Option<ModelAlias>, Box<dyn Error>> {
+        self.models.get_alias(alias).await
+    }
+
+    // Create an alias or re-point it to another model. The route table is swapped as a whole,
+    // so a request sees either the old or the new target, never a missing alias.
+    pub async fn set_alias(&self, alias: &ModelAlias) -> Result<(), Box<dyn Error>> {
+        if alias.alias.is_empty() {
+            return Err(Box::new(CatalogError::Invalid("Alias cannot be empty".to_string())));
+        }
+        if self.models.get_model(&alias.model).await?.is_none() {
+            return Err(Box::new(CatalogError::NotFound(format!("Model {} not found", alias.model))));
+        }
+        if self.models.get_model(&alias.alias).await?.is_some() {
+            return Err(Box::new(CatalogError::Conflict(format!("Alias {} is the name of a model", alias.alias))));
+        }
+
+        self.models.set_alias(alias).await?;
+        refresh_route_table_or_log().await;
+        Ok(())
+    }
+
+    pub async fn delete_alias(&self, alias: &str) -> Result<u64, Box<dyn Error>> {
+        let delete_num = self.models.delete_alias(alias).await?;
+        refresh_route_table_or_log().await;
+        Ok(delete_num)
+    }
+
+    // Give every model a service serves a catalog entry, so routing never points at an unknown model
+    pub async fn ensure_service_models(&self, service: &ServiceConfig) -> Result<(), Box<dyn Error>> {
+        let names = std::iter::once(&service.active_model).chain(service.models.iter());
+        for name in names.filter(|name| !name.is_empty()) {
+            if self.models.get_model(name).await?.is_none() {
+                let model = ModelConfig {
+                    model: Model::new(name, &service.servicetype, Utc::now().timestamp()),
+                    aliases: vec![],
+                    services: vec![],
+                };
+                self.models.create_model(&model).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Aliases must be unique over all model names and aliases
+    async fn check_names(&self, model: &ModelConfig) -> Result<(), Box<dyn Error>> {
+        let id = &model.model.id;
+        let prices = [model.model.prompt_price, model.model.completion_price, model.model.cached_price];
+        if prices.iter().any(|price| !price.is_finite() || *price < 0.0) {
+            return Err(Box::new(CatalogError::Invalid("prompt_price, completion_price and cached_price cannot be negative".to_string())));
+        }
+        let aliases = self.models.get_all_aliases().await?;
+        if aliases.iter().any(|alias| &alias.alias == id) {
+            return Err(Box::new(CatalogError::Conflict(format!("{} is already an alias", id))));
+        }
+
+        let mut seen = HashSet::new();
+        for alias in &model.aliases {
+            if alias == id || !seen.insert(alias) {
+                return Err(Box::new(CatalogError::Conflict(format!("Alias {} is given twice", alias))));
+            }
+            if self.models.get_model(alias).await?.is_some() {
+                return Err(Box::new(CatalogError::Conflict(format!("Alias {} is the name of a model", alias))));
+            }
+            if let Some(taken) = aliases.iter().find(|taken| &taken.alias == alias && &taken.model != id) {
+                return Err(Box::new(CatalogError::Conflict(format!("Alias {} already points to {}", alias, taken.model))));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn serving_services(model_id: &str, services: &[ServiceConfig]) -> Vec<String> {
+    services.iter()
+        .filter(|service| service.active_model == model_id || service.models.iter().any(|model| model == model_id))
+        .map(|service| service.id.clone())
+        .collect()
+}
@@ -0,0 +1,212 @@
+use log::error;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::meta::qos::impls::QuotaCountersImpl;
+use crate::meta::qos::traits::{Limits, QuotaCountersTrait, QuotaWindow};
+
+pub const KIND_REQUESTS: &str = "requests";
+pub const KIND_TOKENS: &str = "tokens";
+
+// A quota of `amount` per fixed window of `secs` seconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    pub amount: i64,
+    pub secs: i64,
+}
+
+impl Quota {
+    // The window `now` falls in, aligned to UTC
+    pub fn window(&self, now: i64) -> QuotaWindow {
+        QuotaWindow { secs: self.secs, start: now - now.rem_euclid(self.secs) }
+    }
+}
+
+// Parse a quota of the text `model_limits` of earlier releases: "100" or "100/min" per minute, "5000/hour",
+// "100000/day", or several of them separated by commas, such as "60/min,10000/day"
+pub fn parse_quotas(limit: &str) -> Result<Vec<Quota>, String> {
+    let mut quotas = Vec::new();
+    for part in limit.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let (amount, unit) = part.split_once('/').unwrap_or((part, "min"));
+        let amount: i64 = amount.trim().parse().map_err(|_| format!("Invalid quota: {}", part))?;
+        let secs = match unit.trim() {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 3600,
+            "d" | "day" => 86400,
+            _ => return Err(format!("Invalid quota window: {}", part)),
+        };
+        quotas.push(Quota { amount, secs });
+    }
+    Ok(quotas)
+}
+
+// The request windows of the limits that apply to a request, each with the subject it is counted for
+pub fn request_quotas(limits: &[(String, Limits)]) -> Vec<(String, Quota)> {
+    windows(limits, |limits| [(limits.rpm, 60), (limits.rpd, 86400)])
+}
+
+// The token windows of the limits that apply to a request
+pub fn token_quotas(limits: &[(String, Limits)]) -> Vec<(String, Quota)> {
+    windows(limits, |limits| [(limits.tpm, 60), (limits.tpd, 86400)])
+}
+
+fn windows(limits: &[(String, Limits)], amounts: impl Fn(&Limits) -> [(i32, i64); 2]) -> Vec<(String, Quota)> {
+    let mut quotas = Vec::new();
+    for (subject, limits) in limits {
+        for (amount, secs) in amounts(limits) {
+            if amount > 0 {
+                quotas.push((subject.clone(), Quota { amount: amount as i64, secs }));
+            }
+        }
+    }
+    quotas
+}
+
+// Counters of this gateway instance, used when `quota_shared` is off
+#[derive(Default)]
+struct LocalCounters {
+    used: HashMap<(String, String, &'static str, QuotaWindow), i64>,
+    swept: i64,
+}
+
+static LOCAL_COUNTERS: Lazy<Mutex<LocalCounters>> = Lazy::new(|| Mutex::new(LocalCounters::default()));
+static LAST_SHARED_CLEANUP: AtomicI64 = AtomicI64::new(0);
+
+impl LocalCounters {
+    // Drop the windows that have ended, at most once a minute
+    fn sweep(&mut self, now: i64) {
+        if now - self.swept < 60 {
+            return;
+        }
+        self.swept = now;
+        self.used.retain(|(_, _, _, window), _| window.start + window.secs > now);
+    }
+}
+
+// The built-in quota engine enforcing `model_limits` per subject and model, in place of the coil servers
+pub struct QuotaEngine {
+    counters: Option<Box<dyn QuotaCountersTrait>>,  // shared counters in the database, None for in-process counters
+}
+
+impl Default for QuotaEngine {
+    fn default() -> Self {
+        if GLOBAL_CONFIG.quota_shared {
+            QuotaEngine { counters: Some(Box::new(QuotaCountersImpl)) }
+        } else {
+            QuotaEngine::local()
+        }
+    }
+}
+
+impl QuotaEngine {
+    pub fn local() -> Self {
+        QuotaEngine { counters: None }
+    }
+
+    // Count one request in every window. Nothing is counted when one of them is used up.
+    pub async fn take_request(&self, model: &str, quotas: &[(String, Quota)], now: i64) -> Result<bool, Box<dyn Error>> {
+        self.take(model, KIND_REQUESTS, quotas, 1, now).await
+    }
+
+    // Whether every request window still has room for one more request, without counting it
+    pub async fn requests_available(&self, model: &str, quotas: &[(String, Quota)], now: i64) -> Result<bool, Box<dyn Error>> {
+        for (subject, quota) in quotas {
+            if self.usage(subject, model, KIND_REQUESTS, quota.window(now)).await? + 1 > quota.amount {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Whether every token window still has room for the estimated tokens of a request.
+    // A request larger than a whole window only needs that window to be unused.
+    pub async fn tokens_available(&self, model: &str, quotas: &[(String, Quota)], estimated: i64, now: i64) -> Result<bool, Box<dyn Error>> {
+        for (subject, quota) in quotas {
+            if self.usage(subject, model, KIND_TOKENS, quota.window(now)).await? + estimated.min(quota.amount) > quota.amount {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Add the tokens of a finished request; the last request of a window may go over its quota
+    pub async fn consume_tokens(&self, model: &str, quotas: &[(String, Quota)], tokens: i64, now: i64) -> Result<(), Box<dyn Error>> {
+        match &self.counters {
+            Some(counters) => {
+                for (subject, quota) in quotas {
+                    counters.add_usage(subject, model, KIND_TOKENS, quota.window(now), tokens).await?;
+                }
+                self.cleanup_shared(now).await;
+            }
+            None => {
+                let mut local = LOCAL_COUNTERS.lock().unwrap();
+                local.sweep(now);
+                for (subject, quota) in quotas {
+                    *local.used.entry((subject.clone(), model.to_string(), KIND_TOKENS, quota.window(now))).or_insert(0) += tokens;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn take(&self, model: &str, kind: &'static str, quotas: &[(String, Quota)], amount: i64, now: i64) -> Result<bool, Box<dyn Error>> {
+        match &self.counters {
+            // Check every window first so that a full minute window doesn't use up the day window.
+            // Instances may race between the check and the add, by at most one request each.
+            Some(counters) => {
+                for (subject, quota) in quotas {
+                    if counters.get_usage(subject, model, kind, quota.window(now)).await? + amount > quota.amount {
+                        return Ok(false);
+                    }
+                }
+                for (subject, quota) in quotas {
+                    counters.add_usage(subject, model, kind, quota.window(now), amount).await?;
+                }
+                self.cleanup_shared(now).await;
+                Ok(true)
+            }
+            None => {
+                let mut local = LOCAL_COUNTERS.lock().unwrap();
+                local.sweep(now);
+                let keys: Vec<_> = quotas.iter()
+                    .map(|(subject, quota)| ((subject.clone(), model.to_string(), kind, quota.window(now)), quota.amount))
+                    .collect();
+                if keys.iter().any(|(key, limit)| local.used.get(key).copied().unwrap_or(0) + amount > *limit) {
+                    return Ok(false);
+                }
+                for (key, _) in keys {
+                    *local.used.entry(key).or_insert(0) += amount;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    async fn usage(&self, subject: &str, model: &str, kind: &'static str, window: QuotaWindow) -> Result<i64, Box<dyn Error>> {
+        match &self.counters {
+            Some(counters) => counters.get_usage(subject, model, kind, window).await,
+            None => {
+                let local = LOCAL_COUNTERS.lock().unwrap();
+                Ok(local.used.get(&(subject.to_string(), model.to_string(), kind, window)).copied().unwrap_or(0))
+            }
+        }
+    }
+
+    // Delete ended windows from the database, at most once a minute per instance
+    async fn cleanup_shared(&self, now: i64) {
+        let last = LAST_SHARED_CLEANUP.load(Ordering::Relaxed);
+        if now - last < 60 || LAST_SHARED_CLEANUP.compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return;
+        }
+        if let Some(counters) = &self.counters {
+            if let Err(err) = counters.delete_expired(now).await {
+                error!(target: "error_log", "Failed to delete expired quota counters: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::error::Error;
+
+use crate::meta::rate_limits::traits::{RateLimit, RateLimitsTrait, RATE_LIMIT_SCOPES};
+use crate::meta::rate_limits::impls::RateLimitsImpl;
+use crate::cores::control::models::CatalogError;
+use crate::cores::control::route_cache::refresh_route_table_or_log;
+
+pub struct RateLimitManager {
+    limits: Box<dyn RateLimitsTrait>,
+}
+
+// Default implementation for RateLimitManager
+impl Default for RateLimitManager {
+    fn default() -> Self {
+        RateLimitManager {
+            limits: Box::new(RateLimitsImpl),
+        }
+    }
+}
+
+impl RateLimitManager {
+    pub async fn create_rate_limit(&self, limit: &RateLimit) -> Result<(), Box<dyn Error>> {
+        check_rate_limit(limit)?;
+        if self.limits.get_rate_limit(&limit.id).await?.is_some() {
+            return Err(Box::new(CatalogError::Conflict(format!("Rate limit {} already exists", limit.id))));
+        }
+        if let Some(other) = self.get_all_rate_limits().await?.into_iter().find(|other| other.scope == limit.scope && other.subject == limit.subject) {
+            return Err(Box::new(CatalogError::Conflict(format!("Rate limit {} already covers {} {}", other.id, limit.scope, limit.subject))));
+        }
+
+        self.limits.create_rate_limit(limit).await?;
+        refresh_route_table_or_log().await;
+        Ok(())
+    }
+
+    pub async fn update_rate_limit(&self, limit: &RateLimit) -> Result<u64, Box<dyn Error>> {
+        check_rate_limit(limit)?;
+
+        let rows_updated = self.limits.update_rate_limit(limit).await?;
+        refresh_route_table_or_log().await;
+        Ok(rows_updated)
+    }
+
+    pub async fn delete_rate_limit(&self, id: &str) -> Result<u64, Box<dyn Error>> {
+        let delete_num = self.limits.delete_rate_limit(id).await?;
+        refresh_route_table_or_log().await;
+        Ok(delete_num)
+    }
+
+    pub async fn get_rate_limit(&self, id: &str) -> Result<Option<RateLimit>, Box<dyn Error>> {
+        self.limits.get_rate_limit(id).await
+    }
+
+    pub async fn get_all_rate_limits(&self) -> Result<Vec<RateLimit>, Box<dyn Error>> {
+        let mut limits = self.limits.get_all_rate_limits().await?;
+        limits.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(limits)
+    }
+}
+
+fn check_rate_limit(limit: &RateLimit) -> Result<(), Box<dyn Error>> {
+    if !RATE_LIMIT_SCOPES.contains(&limit.scope.as_str()) {
+        return Err(Box::new(CatalogError::Invalid(format!("Scope {} is not one of {}", limit.scope, RATE_LIMIT_SCOPES.join(", ")))));
+    }
+    if limit.subject.is_empty() {
+        return Err(Box::new(CatalogError::Invalid("Subject cannot be empty, use \"*\" for every subject".to_string())));
+    }
+    if limit.requests_per_minute <= 0 || limit.burst < 0 {
+        return Err(Box::new(CatalogError::Invalid("requests_per_minute must be positive and burst not negative".to_string())));
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use async_trait::async_trait;
+use log::error;
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+use crate::cores::control::services::ServiceManager;
+use crate::meta::connection::DBCrud;
+use crate::meta::models::impls::ModelsImpl;
+use crate::meta::models::traits::{Model, ModelsTrait};
+use crate::meta::middleware::traits::{UserKeys, UserKeysModels, UserKeysTrait};
+use crate::meta::services::traits::ServiceConfig;
+use crate::meta::qos::impls::LimitsImpl;
+use crate::meta::qos::traits::{Limits, LimitsTrait};
+use crate::meta::rate_limits::impls::RateLimitsImpl;
+use crate::meta::rate_limits::traits::{RateLimit, RateLimitsTrait};
+use crate::meta::budgets::impls::BudgetsImpl;
+use crate::meta::budgets::traits::{Budget, BudgetsTrait};
+use crate::meta::traffic_splits::impls::TrafficSplitsImpl;
+use crate::meta::traffic_splits::traits::{TrafficSplit, TrafficSplitsTrait};
+use crate::cores::control::traffic_splits::assign_variant;
+use crate::cores::control::model_limits::{matching_limits, QuotaSubjects};
+use crate::cores::control::budgets::matching_budgets;
+
+// Snapshot of everything the request path needs to route and authorize a chat request.
+// It is rebuilt as a whole and swapped in, so readers never see a half-loaded table.
+#[derive(Default)]
+pub struct RouteTable {
+    services: Vec<ServiceConfig>,
+    services_by_model: HashMap<String, Vec<ServiceConfig>>,
+    models: HashMap<String, Model>,             // the model catalog
+    aliases: HashMap<String, String>,           // alias -> model
+    splits: HashMap<String, Vec<TrafficSplit>>, // model -> traffic split rules, sorted by id
+    rate_limits: HashMap<(String, String), RateLimit>,  // (scope, subject) -> request rate limit
+    model_limits: HashMap<String, Vec<Limits>>, // model -> request and token quotas
+    budgets: Vec<Budget>,                        // monthly spend budgets per account or api key
+    userkeys: HashSet<String>,
+    grants: HashMap<String, HashSet<String>>,    // userkey -> models, "all" grants every model
+}
+
+impl RouteTable {
+    async fn load() -> Result<RouteTable, Box<dyn Error>> {
+        let services = ServiceManager::default().get_all_services().await?;
+        let models = ModelsImpl.get_all_models().await?;
+        let aliases = ModelsImpl.get_all_aliases().await?;
+        let mut splits = TrafficSplitsImpl.get_all_splits().await?;
+        let rate_limits = RateLimitsImpl.get_all_rate_limits().await?;
+        let model_limits = LimitsImpl.get_all_limits_objects().await?;
+        let budgets = BudgetsImpl.get_all_budgets().await?;
+        let userkeys: Vec<UserKeys> = DBCrud::get_all("UserKeys").await?;
+        let grants: Vec<UserKeysModels> = DBCrud::get_all("UserKeysModels").await?;
+
+        let mut table = RouteTable::default();
+        for service in services {
+            table.services_by_model.entry(service.active_model.clone()).or_default().push(service.clone());
+            // A multi-model service also serves the other models of its `models_service` list,
+            // under their own name
+            for model in service.models.iter().filter(|model| !model.is_empty() && **model != service.active_model) {
+                let mut replica = service.clone();
+                replica.model_name = model.clone();
+                replica.active_model = model.clone();
+                table.services_by_model.entry(model.clone()).or_default().push(replica);
+            }
+            table.services.push(service);
+        }
+        table.models = models.into_iter().map(|model| (model.id.clone(), model)).collect();
+        table.aliases = aliases.into_iter().map(|alias| (alias.alias, alias.model)).collect();
+        splits.sort_by(|a, b| a.id.cmp(&b.id));
+        for split in splits {
+            let model = table.canonical_model(&split.model).to_string();
+            table.splits.entry(model).or_default().push(split);
+        }
+        table.rate_limits = rate_limits.into_iter().map(|limit| ((limit.scope.clone(), limit.subject.clone()), limit)).collect();
+        for limits in model_limits {
+            let model = table.canonical_model(&limits.model_name).to_string();
+            table.model_limits.entry(model).or_default().push(limits);
+        }
+        table.budgets = budgets;
+        table.userkeys = userkeys.into_iter().map(|record| record.userkey).collect();
+        for grant in grants {
+            table.grants.entry(grant.userkey).or_default().insert(grant.model);
+        }
+        Ok(table)
+    }
+
+    pub fn services(&self) -> &[ServiceConfig] {
+        &self.services
+    }
+
+    // Names of the models served by at least one active service, sorted
+    pub fn active_models(&self) -> Vec<String> {
+        let mut models: Vec<String> = self.services_by_model.iter()
+            .filter(|(_, services)| services.iter().any(|service| service.status.eq_ignore_ascii_case("active")))
+            .map(|(model, _)| model.clone())
+            .collect();
+        models.sort();
+        models
+    }
+
+    // The catalog entry of a model, looked up by its name or one of its aliases
+    pub fn model(&self, model: &str) -> Option<&Model> {
+        self.models.get(self.canonical_model(model))
+    }
+
+    // The model an alias points to, other names are returned as they are
+    pub fn canonical_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.aliases.get(model).map(String::as_str).unwrap_or(model)
+    }
+
+    pub fn traffic_splits(&self, model: &str) -> &[TrafficSplit] {
+        self.splits.get(self.canonical_model(model)).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    // The rate limit of a subject: its own rule, else the "*" rule of the scope
+    pub fn rate_limit(&self, scope: &str, subject: &str) -> Option<&RateLimit> {
+        self.rate_limits.get(&(scope.to_string(), subject.to_string()))
+            .or_else(|| self.rate_limits.get(&(scope.to_string(), "*".to_string())))
+    }
+
+    pub fn has_rate_limits(&self, scope: &str) -> bool {
+        self.rate_limits.keys().any(|(limit_scope, _)| limit_scope == scope)
+    }
+
+    // Quotas of a model that apply to a request, with the subject each one is counted for
+    pub fn model_limits(&self, model: &str, subjects: &QuotaSubjects) -> Vec<(String, Limits)> {
+        self.model_limits.get(self.canonical_model(model))
+            .map(|limits| matching_limits(limits, subjects))
+            .unwrap_or_default()
+    }
+
+    // Budgets that apply to a request, with the subject each one is counted for
+    pub fn budgets(&self, subjects: &QuotaSubjects) -> Vec<(String, Budget)> {
+        matching_budgets(&self.budgets, subjects)
+    }
+
+    // The canary or A/B variant the request is sent to, if a traffic split rule of the model picks it
+    pub fn traffic_split(&self, model: &str, userkey: &str, sticky_id: &str) -> Option<&TrafficSplit> {
+        let splits = self.splits.get(self.canonical_model(model))?;
+        assign_variant(splits, userkey, sticky_id)
+    }
+
+    // The requested model followed by its fallbacks, which are followed in turn:
+    // `Qwen2.5-72B -> Qwen2.5-14B -> Qwen2.5-7B`. Aliases are resolved and every model appears once.
+    pub fn fallback_chain(&self, model: &str) -> Vec<String> {
+        let mut chain = vec![self.canonical_model(model).to_string()];
+        let mut next = 0;
+        while next < chain.len() {
+            let fallbacks = self.models.get(&chain[next]).map(|entry| entry.fallback_models()).unwrap_or_default();
+            for fallback in fallbacks {
+                let fallback = self.canonical_model(&fallback).to_string();
+                if !chain.contains(&fallback) {
+                    chain.push(fallback);
+                }
+            }
+            next += 1;
+        }
+        chain
+    }
+
+    // Aliases pointing to the given models, as (alias, model) pairs sorted by alias
+    pub fn aliases_to(&self, models: &[String]) -> Vec<(String, String)> {
+        let mut aliases: Vec<(String, String)> = self.aliases.iter()
+            .filter(|(_, model)| models.contains(model))
+            .map(|(alias, model)| (alias.clone(), model.clone()))
+            .collect();
+        aliases.sort();
+        aliases
+    }
+
+    pub fn services_by_model(&self, model: &str) -> Vec<ServiceConfig> {
+        self.services_by_model.get(model).cloned().unwrap_or_default()
+    }
+
+    pub fn has_userkey(&self, userkey: &str) -> bool {
+        self.userkeys.contains(userkey)
+    }
+
+    // A grant on a model also covers its aliases
+    pub fn has_grant(&self, userkey: &str, model: &str) -> bool {
+        let canonical = self.canonical_model(model);
+        self.grants
+            .get(userkey)
+            .map(|models| models.contains(model) || models.contains(canonical) || models.contains("all"))
+            .unwrap_or(false)
+    }
+}
+
+static ROUTE_TABLE: Lazy<RwLock<Arc<RouteTable>>> = Lazy::new(|| RwLock::new(Arc::new(RouteTable::default())));
+
+pub fn route_table() -> Arc<RouteTable> {
+    ROUTE_TABLE.read().unwrap().clone()
+}
+
+// Reload the table from the database. Called at startup, after changes through the control API
+// and periodically (`route_cache_ttl`) to pick up changes made by other gateway instances.
+pub async fn refresh_route_table() -> Result<(), Box<dyn Error>> {
+    let table = RouteTable::load().await?;
+    *ROUTE_TABLE.write().unwrap() = Arc::new(table);
+    Ok(())
+}
+
+// Refresh after a control API change; a failure only delays the change until the next TTL refresh
+pub async fn refresh_route_table_or_log() {
+    if let Err(err) = refresh_route_table().await {
+        error!(target: "error_log", "Failed to refresh route table: {}", err);
+    }
+}
+
+// Key checks for `Auth4ModelMiddleware` answered from the route table instead of the database
+pub struct CachedUserKeysImpl;
+
+#[async_trait]
+impl UserKeysTrait for CachedUserKeysImpl {
+    async fn check_userkey(&self, userkey: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(route_table().has_userkey(userkey))
+    }
+
+    async fn check_userkey_model(&self, userkey: &str, model: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(route_table().has_grant(userkey, model))
+    }
+}
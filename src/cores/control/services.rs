@@ -1,7 +1,11 @@
 use std::error::Error;
+use rand::seq::SliceRandom;
 
 use crate::meta::services::traits::{ServiceConfig, ServicesTrait};
 use crate::meta::services::impls::ServicesImpl;
+use crate::cores::control::clients::{invalidate_client, invalidate_all_clients};
+use crate::cores::control::route_cache::refresh_route_table_or_log;
+use crate::cores::control::models::ModelManager;
 
 pub struct ServiceManager {
     services: Box<dyn ServicesTrait>,
@@ -22,19 +26,38 @@ impl ServiceManager{
     }
 
     pub async fn load_services_table(&self) -> Result<(), Box<dyn Error>> {
-        self.services.load_services_table().await
+        invalidate_all_clients();
+        self.services.load_services_table().await?;
+        for service in self.services.get_all_services().await? {
+            ModelManager::default().ensure_service_models(&service).await?;
+        }
+        refresh_route_table_or_log().await;
+        Ok(())
     }
 
     pub async fn create_service(&self, service: &ServiceConfig) -> Result<(), Box<dyn Error>> {
-        self.services.create_service(service).await
+        self.services.create_service(service).await?;
+        ModelManager::default().ensure_service_models(service).await?;
+        refresh_route_table_or_log().await;
+        Ok(())
     }
 
     pub async fn delete_service(&self, service_id: &str) -> Result<u64, Box<dyn Error>> {
-        self.services.delete_service(service_id).await
+        invalidate_client(service_id);
+        let delete_num = self.services.delete_service(service_id).await?;
+        refresh_route_table_or_log().await;
+        Ok(delete_num)
     }
 
     pub async fn update_service(&self, service: &ServiceConfig) -> Result<u64, Box<dyn Error>> {
-        self.services.update_service(service).await
+        invalidate_client(&service.id);
+        let rows_updated = self.services.update_service(service).await?;
+        // The `models_service` list is not changed by an update, check what is stored
+        if let Some(stored) = self.services.get_service(&service.id).await? {
+            ModelManager::default().ensure_service_models(&stored).await?;
+        }
+        refresh_route_table_or_log().await;
+        Ok(rows_updated)
     }
 
     pub async fn get_service(&self, service_id: &str) -> Result<Option<ServiceConfig>, Box<dyn Error>> {
@@ -42,7 +65,8 @@ impl ServiceManager{
     }
 
     pub async fn get_service_by_model(&self, model_name: &str) -> Result<Option<ServiceConfig>, Box<dyn Error>> {
-        self.services.get_service_by_model(model_name).await
+        let services = self.services.get_services_by_model(model_name).await?;
+        Ok(services.choose(&mut rand::thread_rng()).cloned())
     }
 
     pub async fn get_all_services(&self) -> Result<Vec<ServiceConfig>, Box<dyn Error>> {
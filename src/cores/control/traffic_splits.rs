@@ -0,0 +1,128 @@
+use std::error::Error;
+
+use crate::meta::traffic_splits::traits::{TrafficSplit, TrafficSplitsTrait};
+use crate::meta::traffic_splits::impls::TrafficSplitsImpl;
+use crate::cores::control::models::CatalogError;
+use crate::cores::control::route_cache::{refresh_route_table_or_log, route_table};
+
+pub struct TrafficSplitManager {
+    splits: Box<dyn TrafficSplitsTrait>,
+}
+
+// Default implementation for TrafficSplitManager
+impl Default for TrafficSplitManager {
+    fn default() -> Self {
+        TrafficSplitManager {
+            splits: Box::new(TrafficSplitsImpl),
+        }
+    }
+}
+
+impl TrafficSplitManager {
+    pub async fn create_split(&self, split: &TrafficSplit) -> Result<(), Box<dyn Error>> {
+        if self.splits.get_split(&split.id).await?.is_some() {
+            return Err(Box::new(CatalogError::Conflict(format!("Traffic split {} already exists", split.id))));
+        }
+        check_split(split)?;
+        check_percent_total(split, &self.splits.get_all_splits().await?)?;
+
+        self.splits.create_split(split).await?;
+        refresh_route_table_or_log().await;
+        Ok(())
+    }
+
+    pub async fn update_split(&self, split: &TrafficSplit) -> Result<u64, Box<dyn Error>> {
+        check_split(split)?;
+        check_percent_total(split, &self.splits.get_all_splits().await?)?;
+
+        let rows_updated = self.splits.update_split(split).await?;
+        refresh_route_table_or_log().await;
+        Ok(rows_updated)
+    }
+
+    pub async fn delete_split(&self, id: &str) -> Result<u64, Box<dyn Error>> {
+        let delete_num = self.splits.delete_split(id).await?;
+        refresh_route_table_or_log().await;
+        Ok(delete_num)
+    }
+
+    pub async fn get_split(&self, id: &str) -> Result<Option<TrafficSplit>, Box<dyn Error>> {
+        self.splits.get_split(id).await
+    }
+
+    pub async fn get_all_splits(&self) -> Result<Vec<TrafficSplit>, Box<dyn Error>> {
+        let mut splits = self.splits.get_all_splits().await?;
+        splits.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(splits)
+    }
+}
+
+// A rule needs exactly one variant that routing knows about, and a percentage
+fn check_split(split: &TrafficSplit) -> Result<(), Box<dyn Error>> {
+    if !(0..=100).contains(&split.percent) {
+        return Err(Box::new(CatalogError::Invalid(format!("Percent {} is not between 0 and 100", split.percent))));
+    }
+
+    let table = route_table();
+    if table.model(&split.model).is_none() {
+        return Err(Box::new(CatalogError::NotFound(format!("Model {} not found", split.model))));
+    }
+    match (split.target_model.is_empty(), split.target_service.is_empty()) {
+        (false, true) if table.model(&split.target_model).is_none() => {
+            Err(Box::new(CatalogError::NotFound(format!("Model {} not found", split.target_model))))
+        }
+        (true, false) if !table.services_by_model(table.canonical_model(&split.model)).iter().any(|service| service.id == split.target_service) => {
+            Err(Box::new(CatalogError::NotFound(format!("Service {} does not serve {}", split.target_service, split.model))))
+        }
+        (false, true) | (true, false) => Ok(()),
+        _ => Err(Box::new(CatalogError::Invalid("Exactly one of target_model and target_service must be set".to_string()))),
+    }
+}
+
+// The enabled rules of a model share its requests, so their percentages can't add up to more than 100
+pub fn check_percent_total(split: &TrafficSplit, splits: &[TrafficSplit]) -> Result<(), Box<dyn Error>> {
+    if !split.enabled {
+        return Ok(());
+    }
+    let table = route_table();
+    let model = table.canonical_model(&split.model);
+    let total: i32 = splits.iter()
+        .filter(|other| other.enabled && other.id != split.id && table.canonical_model(&other.model) == model)
+        .map(|other| other.percent)
+        .sum::<i32>() + split.percent;
+    if total > 100 {
+        return Err(Box::new(CatalogError::Invalid(format!("Traffic splits of {} would send {}% of its requests to variants", split.model, total))));
+    }
+    Ok(())
+}
+
+// Pick the variant of a request among the enabled rules of its model. Listed keys go to their rule,
+// the others are spread by a hash of the sticky id (the `user` field, else the key, else the account id),
+// so the same user keeps getting the same variant while the percentages stay unchanged.
+pub fn assign_variant<'a>(splits: &'a [TrafficSplit], userkey: &str, sticky_id: &str) -> Option<&'a TrafficSplit> {
+    let splits: Vec<&TrafficSplit> = splits.iter().filter(|split| split.enabled).collect();
+    if let Some(split) = splits.iter().find(|split| split.has_userkey(userkey)) {
+        return Some(split);
+    }
+
+    let model = splits.first()?.model.as_str();
+    let bucket = split_bucket(model, sticky_id);
+    let mut upper = 0;
+    for split in splits {
+        upper += split.percent.max(0) as u64;
+        if bucket < upper {
+            return Some(split);
+        }
+    }
+    None
+}
+
+// A stable bucket in 0..100 for the user on the model. 64-bit FNV-1a, written out so that the buckets
+// don't move with the standard library's hasher when the toolchain is upgraded.
+pub fn split_bucket(model: &str, sticky_id: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    // 0xff never occurs in UTF-8, so ("ab", "c") and ("a", "bc") hash differently
+    let bytes = model.as_bytes().iter().chain(&[0xff]).chain(sticky_id.as_bytes());
+    bytes.fold(FNV_OFFSET, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)) % 100
+}
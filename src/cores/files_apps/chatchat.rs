@@ -126,17 +126,14 @@ impl FileChatController for ChatChatFile {
         let mut query = String::new();
         for message in req_body.messages.iter().rev() {
             if message.role == "user" {
-                query = message.content.clone();
+                query = message.text();
                 break;
             }
         }
 
         // Construct history messages
         let mut history: Vec<Message> = vec![
-            Message {
-                role: "system".to_string(),
-                content: "现在你是一名及其专业的计算机专家，工作是一名操作系统的运维助手，这份工作极其重要，不能出错！".to_string(),
-            }
+            Message::new("system", "现在你是一名及其专业的计算机专家，工作是一名操作系统的运维助手，这份工作极其重要，不能出错！".to_string())
         ];
         history.extend_from_slice(&req_body.messages[..req_body.messages.len() - 1]);
 
@@ -6,3 +6,4 @@ pub mod models;
 pub mod embedding_models;
 pub mod control;
 pub mod image_models;
+pub mod usage;
@@ -1,93 +1,45 @@
-use crate::meta::connection::{get_db_connection,DbConnection};
-use crate::meta::models::Model;
-use sqlx::Row;
+use crate::cores::control::route_cache::{route_table, RouteTable};
+use crate::meta::models::traits::{ModelCard, ModelList};
+use crate::middleware::model_list_auth::ModelGrants;
 
 
-pub async fn get_models() -> Result<Vec<Model>, Box<dyn std::error::Error>> {
-    let db_conn = get_db_connection().await?;
-    let mut modelsinfo = Vec::new();
-    match db_conn {
-        // 处理 MySQL 数据库
-        DbConnection::MySql(mut conn) => {
-            let rows = sqlx::query("SELECT * FROM models")
-                .fetch_all(&mut *conn)
-                .await?;
-            for row in rows {
-                let model = Model {
-                    id: row.get("id"),
-                    object: row.get("object"),
-                    model_name: row.get("model_name"),
-                    request_url: row.get("request_url"),
-                    created: row.get("created"),
-                    owned_by: row.get("owned_by"),
-                };
-                modelsinfo.push(model);
-            }
-            Ok(modelsinfo)
-        }
-        // 处理 PostgreSQL 数据库
-        DbConnection::Postgres(mut conn) => {
-            let rows = sqlx::query("SELECT * FROM models")
-                .fetch_all(&mut *conn)
-                .await?;
-            for row in rows {
-                let model = Model {
-                    id: row.get("id"),
-                    object: row.get("object"),
-                    model_name: row.get("model_name"),
-                    request_url: row.get("request_url"),
-                    created: row.get("created"),
-                    owned_by: row.get("owned_by"),
-                };
-                modelsinfo.push(model);
-            }
-            Ok(modelsinfo)
-        }
-    }
-}
+// Models served by the active services, limited to `grants` when the caller was authenticated.
+// Aliases of those models are listed too, so SDKs asking for e.g. `gpt-3.5-turbo` find it.
+// `created` and `owned_by` come from the model catalog.
+pub fn list_served_models(grants: Option<&ModelGrants>) -> ModelList {
+    let table = route_table();
 
-pub async fn get_model(model_name: &str) -> Result<Option<Model>, Box<dyn std::error::Error>> {
-    // 获取数据库连接
-    let db_conn = get_db_connection().await?;
+    let models: Vec<String> = table.active_models()
+        .into_iter()
+        .filter(|model| grants.map(|grants| grants.0.contains(model)).unwrap_or(true))
+        .collect();
+    let aliases = table.aliases_to(&models);
 
-    match db_conn {
-        DbConnection::MySql(mut conn) => {
-            let row = sqlx::query("SELECT * FROM models WHERE id = ?")
-                .bind(model_name) // 绑定模型名称
-                .fetch_optional(&mut *conn)
-                .await?;
+    let mut data: Vec<ModelCard> = models.iter()
+        .map(|model| model_card(&table, model, model))
+        .collect();
+    data.extend(aliases.iter().map(|(alias, model)| model_card(&table, alias, model)));
 
-            if let Some(row) = row {
-                Ok(Some(Model {
-                    id: row.get("id"),
-                    object: row.get("object"),
-                    model_name: row.get("model_name"),
-                    request_url: row.get("request_url"),
-                    created: row.get("created"),
-                    owned_by: row.get("owned_by"),
-                }))
-            } else {
-                Ok(None) // 如果未找到，返回 None
-            }
-        }
-        DbConnection::Postgres(mut conn) => {
-            let row = sqlx::query("SELECT * FROM models WHERE id = $1")
-                .bind(model_name)
-                .fetch_optional(&mut *conn)
-                .await?;
+    ModelList { object: "list".to_string(), data }
+}
 
-            if let Some(row) = row {
-                Ok(Some(Model {
-                    id: row.get("id"),
-                    object: row.get("object"),
-                    model_name: row.get("model_name"),
-                    request_url: row.get("request_url"),
-                    created: row.get("created"),
-                    owned_by: row.get("owned_by"),
-                }))
-            } else {
-                Ok(None)
+// The card of `id`, described by the catalog entry of `model`
+fn model_card(table: &RouteTable, id: &str, model: &str) -> ModelCard {
+    match table.model(model) {
+        Some(entry) => ModelCard {
+            id: id.to_string(),
+            object: "model".to_string(),
+            created: entry.created,
+            owned_by: entry.owned_by.clone(),
+        },
+        None => {
+            let owned_by = table.services_by_model(model).first().map(|service| service.servicetype.clone()).unwrap_or_default();
+            ModelCard {
+                id: id.to_string(),
+                object: "model".to_string(),
+                created: 0,
+                owned_by,
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -27,7 +27,7 @@ impl RAGController for ChatChatRAG {
         let mut query = String::new();
         for message in req_body.messages.iter().rev() {
             if message.role == "user" {
-                query = message.content.clone();
+                query = message.text();
                 break;
             }
         }
@@ -35,10 +35,7 @@ impl RAGController for ChatChatRAG {
         // Construct history messages
         let mut history: Vec<Message> = vec![];
         if req_body.messages[0].role != "system" {
-            let message = Message {
-                role: "system".to_string(),
-                content: "现在你是一名专业的计算机专家，工作是一名操作系统的运维助手，负责确保系统的稳定运行和用户的满意。请提供准确的信息。".to_string(),
-            };
+            let message = Message::new("system", "现在你是一名专业的计算机专家，工作是一名操作系统的运维助手，负责确保系统的稳定运行和用户的满意。请提供准确的信息。".to_string());
             history.push(message);
         }
 
@@ -21,7 +21,7 @@ impl RAGController for CopilotRAG {
         let mut query = String::new();
         for message in req_body.messages.iter().rev() {
             if message.role == "user" {
-                query = message.content.clone();
+                query = message.text();
                 break;
             }
         }
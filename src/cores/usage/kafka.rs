@@ -0,0 +1,155 @@
+use chrono::Utc;
+use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+use rskafka::client::{ClientBuilder, Credentials, SaslConfig};
+use rskafka::record::Record;
+use rskafka::BackoffConfig;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+use crate::configs::settings::Config;
+
+// Usage events produced to a Kafka topic with rskafka, a pure Rust client supporting TLS and SASL.
+// The client and its partition connections are kept between batches and rebuilt after a failure.
+// One batch goes to one partition, partitions are taken in turn. rskafka waits for all in-sync replicas.
+
+const CLIENT_ID: &str = "chatig";
+
+pub const SASL_MECHANISMS: [&str; 3] = ["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512"];
+
+pub struct KafkaProducer {
+    brokers: Vec<String>,
+    topic: String,
+    tls: Option<Arc<rustls::ClientConfig>>,
+    sasl: Option<SaslConfig>,
+    timeout: Duration,
+    partitions: Mutex<Vec<Arc<PartitionClient>>>,
+    next_partition: AtomicUsize,
+}
+
+impl KafkaProducer {
+    pub fn new(brokers: Vec<String>, topic: &str, tls: Option<Arc<rustls::ClientConfig>>, sasl: Option<SaslConfig>, timeout: Duration) -> Self {
+        KafkaProducer {
+            brokers,
+            topic: topic.to_string(),
+            tls,
+            sasl,
+            timeout,
+            partitions: Mutex::new(Vec::new()),
+            next_partition: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn from_config(config: &Config, timeout: Duration) -> Result<Self, String> {
+        let tls = if config.usage_kafka_tls { Some(tls_config(&config.usage_kafka_ca_file)?) } else { None };
+        let sasl = sasl_config(&config.usage_kafka_sasl_mechanism, &config.usage_kafka_username, &config.usage_kafka_password)?;
+        Ok(KafkaProducer::new(config.usage_kafka_brokers.clone(), &config.usage_kafka_topic, tls, sasl, timeout))
+    }
+
+    // Append the values to one partition of the topic, within the sink timeout
+    pub async fn send(&self, values: &[String]) -> Result<(), String> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let result = match timeout(self.timeout, self.produce(values)).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("Kafka produce to {} timed out after {:?}", self.topic, self.timeout)),
+        };
+        if result.is_err() {
+            self.partitions.lock().await.clear();
+        }
+        result
+    }
+
+    async fn produce(&self, values: &[String]) -> Result<(), String> {
+        let partition = {
+            let mut partitions = self.partitions.lock().await;
+            if partitions.is_empty() {
+                *partitions = self.connect().await?;
+            }
+            partitions[self.next_partition.fetch_add(1, Ordering::Relaxed) % partitions.len()].clone()
+        };
+        let now = Utc::now();
+        let records = values.iter().map(|value| Record {
+            key: None,
+            value: Some(value.as_bytes().to_vec()),
+            headers: BTreeMap::new(),
+            timestamp: now,
+        }).collect();
+        partition.produce(records, Compression::NoCompression)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Kafka produce to {}-{}: {}", self.topic, partition.partition(), err))
+    }
+
+    async fn connect(&self) -> Result<Vec<Arc<PartitionClient>>, String> {
+        let mut builder = ClientBuilder::new(self.brokers.clone())
+            .client_id(CLIENT_ID)
+            .backoff_config(BackoffConfig { deadline: Some(self.timeout), ..Default::default() });
+        if let Some(tls) = &self.tls {
+            builder = builder.tls_config(tls.clone());
+        }
+        if let Some(sasl) = &self.sasl {
+            builder = builder.sasl_config(sasl.clone());
+        }
+        let client = builder.build().await
+            .map_err(|err| format!("Kafka brokers {}: {}", self.brokers.join(","), err))?;
+        let topic = client.list_topics().await
+            .map_err(|err| format!("Kafka metadata of {}: {}", self.topic, err))?
+            .into_iter()
+            .find(|topic| topic.name == self.topic)
+            .ok_or_else(|| format!("Kafka topic {} does not exist", self.topic))?;
+
+        let mut partitions = Vec::new();
+        for partition in topic.partitions {
+            let client = client.partition_client(self.topic.clone(), partition, UnknownTopicHandling::Error).await
+                .map_err(|err| format!("Kafka leader of {}-{}: {}", self.topic, partition, err))?;
+            partitions.push(Arc::new(client));
+        }
+        if partitions.is_empty() {
+            return Err(format!("Kafka topic {} has no partitions", self.topic));
+        }
+        Ok(partitions)
+    }
+}
+
+// The SASL mechanism of `usage_kafka_sasl_mechanism`, none when it is empty
+pub fn sasl_config(mechanism: &str, username: &str, password: &str) -> Result<Option<SaslConfig>, String> {
+    let credentials = Credentials::new(username.to_string(), password.to_string());
+    match mechanism.to_uppercase().as_str() {
+        "" => Ok(None),
+        "PLAIN" => Ok(Some(SaslConfig::Plain(credentials))),
+        "SCRAM-SHA-256" => Ok(Some(SaslConfig::ScramSha256(credentials))),
+        "SCRAM-SHA-512" => Ok(Some(SaslConfig::ScramSha512(credentials))),
+        other => Err(format!("Kafka SASL mechanism {} is not one of {}", other, SASL_MECHANISMS.join(", "))),
+    }
+}
+
+// TLS trusting the system roots, or only the PEM certificates of `ca_file` when it is set
+pub fn tls_config(ca_file: &str) -> Result<Arc<rustls::ClientConfig>, String> {
+    let mut roots = rustls::RootCertStore::empty();
+    if ca_file.is_empty() {
+        let native = rustls_native_certs::load_native_certs();
+        roots.add_parsable_certificates(native.certs);
+    } else {
+        let mut reader = BufReader::new(File::open(ca_file).map_err(|err| format!("Kafka CA file {}: {}", ca_file, err))?);
+        let certs = rustls_pemfile::certs(&mut reader)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Kafka CA file {}: {}", ca_file, err))?;
+        roots.add_parsable_certificates(certs);
+    }
+    if roots.is_empty() {
+        return Err("No CA certificates to verify the Kafka brokers with".to_string());
+    }
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| format!("Kafka TLS: {}", err))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
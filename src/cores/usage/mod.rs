@@ -0,0 +1,273 @@
+pub mod kafka;
+pub mod records;
+pub mod sinks;
+pub mod spool;
+
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::configs::settings::Config;
+use crate::cores::usage::sinks::{build_sink, UsageSink};
+use crate::cores::usage::spool::Spool;
+
+// Token usage events of finished requests, batched in the background and handed to the configured sink.
+// A batch the sink doesn't take after the retries is spooled to disk, and so is every batch after it until the
+// spool has been replayed, oldest first, on the next flush ticks. Events that don't fit in the bounded queue
+// or in the spool are written to the `token` log as a last resort. When the server stops, the queue and the batch
+// being collected are delivered before the process exits.
+
+#[derive(Default)]
+pub struct DeliveryMetrics {
+    pending: AtomicI64,
+    delivered: AtomicU64,
+    failed_attempts: AtomicU64,
+    spooled: AtomicU64,
+    replayed: AtomicU64,
+    dropped: AtomicU64,
+    last_delivered_at: AtomicI64,
+    last_error: Mutex<Option<String>>,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone)]
+pub struct DeliveryStats {
+    pub sink: String,
+    pub pending: i64,           // events waiting in memory for the next batch
+    pub delivered: u64,         // events the sink took on the first send or a retry
+    pub failed_attempts: u64,   // sends of a batch or a spool file that failed
+    pub spooled: u64,           // events written to the spool
+    pub replayed: u64,          // spooled events delivered later
+    pub dropped: u64,           // events the queue or the spool had no room for, only in the `token` log
+    pub spool_files: u64,
+    pub spool_bytes: u64,
+    pub last_delivered_at: Option<i64>,   // Unix timestamp
+    pub last_error: Option<String>,
+}
+
+pub struct UsagePipeline {
+    sink: Box<dyn UsageSink>,
+    spool: Spool,
+    retry_times: u32,
+    retry_backoff: Duration,
+    metrics: DeliveryMetrics,
+    shutdown: Notify,
+}
+
+static PIPELINE: OnceCell<(Sender<String>, Arc<UsagePipeline>)> = OnceCell::new();
+static PIPELINE_TASK: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+// Start the background delivery of usage events. Until it is started events go to the `token` log directly.
+pub fn start_usage_pipeline(config: &Config) -> Result<(), String> {
+    let pipeline = Arc::new(UsagePipeline::new(
+        build_sink(config)?,
+        Spool::new(&config.usage_spool_dir, config.usage_spool_max_bytes),
+        config.usage_retry_times,
+        Duration::from_millis(config.usage_retry_backoff_ms),
+    ));
+    let (sender, receiver) = channel(config.usage_queue_size.max(1));
+    if PIPELINE.set((sender, pipeline.clone())).is_err() {
+        return Err("Usage pipeline is already started".to_string());
+    }
+    let task = tokio::spawn(pipeline.run(receiver, config.usage_batch_size.max(1), Duration::from_millis(config.usage_flush_interval_ms.max(1))));
+    *PIPELINE_TASK.lock().unwrap() = Some(task);
+    Ok(())
+}
+
+// Deliver the events still in memory, or spool them, and stop the pipeline. Called once the server has stopped;
+// events emitted afterwards go to the `token` log.
+pub async fn stop_usage_pipeline() {
+    let task = PIPELINE_TASK.lock().unwrap().take();
+    if let (Some((_, pipeline)), Some(task)) = (PIPELINE.get(), task) {
+        pipeline.shutdown();
+        if let Err(err) = task.await {
+            error!(target: "error_log", "Usage pipeline failed to stop: {}", err);
+        }
+    }
+}
+
+pub fn emit_usage_event(event: String) {
+    match PIPELINE.get() {
+        Some((sender, pipeline)) => {
+            pipeline.metrics.pending.fetch_add(1, Ordering::Relaxed);
+            match sender.try_send(event) {
+                Ok(()) => {}
+                // The sink is slower than the requests, most likely down: keep the memory bounded
+                Err(TrySendError::Full(event)) => {
+                    pipeline.metrics.pending.fetch_sub(1, Ordering::Relaxed);
+                    pipeline.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    info!(target: "token", "{}", event);
+                }
+                Err(TrySendError::Closed(event)) => {
+                    pipeline.metrics.pending.fetch_sub(1, Ordering::Relaxed);
+                    info!(target: "token", "{}", event);
+                }
+            }
+        }
+        None => info!(target: "token", "{}", event),
+    }
+}
+
+pub async fn delivery_stats() -> Option<DeliveryStats> {
+    match PIPELINE.get() {
+        Some((_, pipeline)) => Some(pipeline.stats().await),
+        None => None,
+    }
+}
+
+impl UsagePipeline {
+    pub fn new(sink: Box<dyn UsageSink>, spool: Spool, retry_times: u32, retry_backoff: Duration) -> Self {
+        UsagePipeline { sink, spool, retry_times, retry_backoff, metrics: DeliveryMetrics::default(), shutdown: Notify::new() }
+    }
+
+    // Ask `run` to deliver what is queued and return
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    pub async fn run(self: Arc<Self>, mut receiver: Receiver<String>, batch_size: usize, flush_interval: Duration) {
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut ticker = tokio::time::interval(flush_interval);
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Some(event) => {
+                        batch.push(event);
+                        if batch.len() >= batch_size {
+                            self.flush(&mut batch).await;
+                        }
+                    }
+                    None => {
+                        self.flush(&mut batch).await;
+                        return;
+                    }
+                },
+                _ = ticker.tick() => {
+                    self.replay().await;
+                    self.flush(&mut batch).await;
+                }
+                _ = self.shutdown.notified() => {
+                    // Senders get `Closed` from now on, what is already queued is still received
+                    receiver.close();
+                    while let Some(event) = receiver.recv().await {
+                        batch.push(event);
+                        if batch.len() >= batch_size {
+                            self.flush(&mut batch).await;
+                        }
+                    }
+                    self.flush(&mut batch).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn flush(&self, batch: &mut Vec<String>) {
+        self.metrics.pending.fetch_sub(batch.len() as i64, Ordering::Relaxed);
+        self.deliver(std::mem::take(batch)).await;
+    }
+
+    // Send a batch, retrying with a doubling backoff, and spool it when the sink is down.
+    // While the spool holds batches the sink is taken as down, the batch goes after them without a send.
+    pub async fn deliver(&self, batch: Vec<String>) {
+        if batch.is_empty() {
+            return;
+        }
+        if !self.spool.files().await.is_empty() {
+            self.spool_batch(&batch).await;
+            return;
+        }
+        for attempt in 0..=self.retry_times {
+            if attempt > 0 {
+                tokio::time::sleep(self.retry_backoff * 2u32.saturating_pow(attempt - 1)).await;
+            }
+            if self.send(&batch).await {
+                self.metrics.delivered.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                return;
+            }
+        }
+        self.spool_batch(&batch).await;
+    }
+
+    // Send the spooled batches, oldest first. Returns whether the spool is empty afterwards.
+    pub async fn replay(&self) -> bool {
+        for path in self.spool.files().await {
+            let events = match Spool::read(&path).await {
+                Ok(events) => events,
+                Err(err) => {
+                    self.failed(err);
+                    return false;
+                }
+            };
+            if !self.send(&events).await {
+                return false;
+            }
+            if let Err(err) = Spool::remove(&path).await {
+                // Sent but still on disk, it would be sent again
+                self.failed(err);
+                return false;
+            }
+            self.metrics.replayed.fetch_add(events.len() as u64, Ordering::Relaxed);
+        }
+        true
+    }
+
+    async fn send(&self, events: &[String]) -> bool {
+        match self.sink.send(events).await {
+            Ok(()) => {
+                self.metrics.last_delivered_at.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+                true
+            }
+            Err(err) => {
+                self.failed(err);
+                false
+            }
+        }
+    }
+
+    async fn spool_batch(&self, batch: &[String]) {
+        match self.spool.push(batch).await {
+            Ok(true) => {
+                self.metrics.spooled.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                return;
+            }
+            Ok(false) => error!(target: "error_log", "Usage spool is full, {} events are only in the token log", batch.len()),
+            Err(err) => error!(target: "error_log", "{}, {} events are only in the token log", err, batch.len()),
+        }
+        self.metrics.dropped.fetch_add(batch.len() as u64, Ordering::Relaxed);
+        for event in batch {
+            info!(target: "token", "{}", event);
+        }
+    }
+
+    fn failed(&self, err: String) {
+        self.metrics.failed_attempts.fetch_add(1, Ordering::Relaxed);
+        error!(target: "error_log", "Failed to deliver usage events to the {} sink: {}", self.sink.name(), err);
+        *self.metrics.last_error.lock().unwrap() = Some(err);
+    }
+
+    pub async fn stats(&self) -> DeliveryStats {
+        let (spool_files, spool_bytes) = self.spool.size().await;
+        let last_delivered_at = self.metrics.last_delivered_at.load(Ordering::Relaxed);
+        DeliveryStats {
+            sink: self.sink.name().to_string(),
+            pending: self.metrics.pending.load(Ordering::Relaxed),
+            delivered: self.metrics.delivered.load(Ordering::Relaxed),
+            failed_attempts: self.metrics.failed_attempts.load(Ordering::Relaxed),
+            spooled: self.metrics.spooled.load(Ordering::Relaxed),
+            replayed: self.metrics.replayed.load(Ordering::Relaxed),
+            dropped: self.metrics.dropped.load(Ordering::Relaxed),
+            spool_files,
+            spool_bytes,
+            last_delivered_at: (last_delivered_at > 0).then_some(last_delivered_at),
+            last_error: self.metrics.last_error.lock().unwrap().clone(),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use log::error;
+use serde::Deserialize;
+use std::error::Error;
+
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::cores::control::models::CatalogError;
+use crate::meta::usage::impls::UsageRecordsImpl;
+use crate::meta::usage::traits::{UsageAggregate, UsageQuery, UsageRecord, UsageRecordsTrait, USAGE_GROUPS};
+
+pub struct UsageRecordsManager {
+    records: Box<dyn UsageRecordsTrait>,
+}
+
+impl Default for UsageRecordsManager {
+    fn default() -> Self {
+        UsageRecordsManager {
+            records: Box::new(UsageRecordsImpl),
+        }
+    }
+}
+
+// Filters and grouping of the usage endpoints, as query parameters
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct UsageParams {
+    pub start: Option<String>,          // Unix timestamp, RFC 3339 time or YYYY-MM-DD date, inclusive
+    pub end: Option<String>,            // exclusive
+    pub account_id: Option<String>,
+    pub api_key: Option<String>,
+    pub project: Option<String>,
+    pub model: Option<String>,
+    pub group_by: Option<String>,       // comma separated: account, key, project, model
+    pub bucket: Option<String>,         // minute, hour, day or none
+    pub utc_offset: Option<i64>,        // minutes east of UTC for dates and day buckets, such as 480 for UTC+8
+}
+
+impl UsageRecordsManager {
+    pub fn _new(records: Box<dyn UsageRecordsTrait>) -> Self {
+        UsageRecordsManager { records }
+    }
+
+    pub async fn add_usage_record(&self, record: &UsageRecord) -> Result<(), Box<dyn Error>> {
+        self.records.add_usage_record(record).await
+    }
+
+    pub async fn aggregate_usage(&self, query: &UsageQuery) -> Result<Vec<UsageAggregate>, Box<dyn Error>> {
+        self.records.aggregate_usage(query).await
+    }
+}
+
+// Write the record of a finished request, in the task that accounts for it after the response
+pub async fn store_usage_record(record: UsageRecord) {
+    if !GLOBAL_CONFIG.usage_records_enabled {
+        return;
+    }
+    if let Err(err) = UsageRecordsManager::default().add_usage_record(&record).await {
+        error!(target: "error_log", "Failed to save usage record of {} on {}: {}", record.account_id, record.model, err);
+    }
+}
+
+pub fn usage_query(params: &UsageParams) -> Result<UsageQuery, CatalogError> {
+    let utc_offset = params.utc_offset.unwrap_or(0);
+    if !(-720..=840).contains(&utc_offset) {
+        return Err(CatalogError::Invalid(format!("utc_offset {} is not between -720 and 840 minutes", utc_offset)));
+    }
+    let utc_offset = utc_offset * 60;
+
+    let mut group_by = Vec::new();
+    for name in params.group_by.as_deref().unwrap_or("").split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let (_, column) = USAGE_GROUPS.iter()
+            .find(|(group, _)| *group == name)
+            .ok_or_else(|| CatalogError::Invalid(format!("Cannot group usage by {}, use account, key, project or model", name)))?;
+        if !group_by.contains(column) {
+            group_by.push(*column);
+        }
+    }
+    let bucket_secs = match params.bucket.as_deref().unwrap_or("none") {
+        "none" | "" => None,
+        "minute" => Some(60),
+        "hour" => Some(3600),
+        "day" => Some(86400),
+        other => return Err(CatalogError::Invalid(format!("Bucket {} is not one of minute, hour, day, none", other))),
+    };
+
+    let time = |value: &Option<String>| value.as_deref().map(|value| parse_time(value, utc_offset)).transpose();
+    let non_empty = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+    Ok(UsageQuery {
+        start: time(&params.start)?,
+        end: time(&params.end)?,
+        account_id: non_empty(&params.account_id),
+        api_key: non_empty(&params.api_key),
+        project: non_empty(&params.project),
+        model: non_empty(&params.model),
+        group_by,
+        bucket_secs,
+        utc_offset,
+    })
+}
+
+// A Unix timestamp, an RFC 3339 time, or the midnight starting a date at the utc offset
+fn parse_time(value: &str, utc_offset: i64) -> Result<i64, CatalogError> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() - utc_offset);
+    }
+    Err(CatalogError::Invalid(format!("Invalid time {}, use a Unix timestamp, an RFC 3339 time or YYYY-MM-DD", value)))
+}
+
+// CSV of the aggregates with the columns the query grouped by, bucket starts as RFC 3339 times at the utc offset
+pub fn usage_csv(aggregates: &[UsageAggregate], query: &UsageQuery) -> String {
+    let offset = FixedOffset::east_opt(query.utc_offset as i32).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    let mut header = Vec::new();
+    if query.bucket_secs.is_some() {
+        header.push("bucket_start");
+    }
+    for (_, column) in USAGE_GROUPS {
+        if query.group_by.contains(&column) {
+            header.push(column);
+        }
+    }
+    header.extend(["requests", "prompt_tokens", "completion_tokens", "total_tokens", "cached_tokens", "cost"]);
+
+    let mut csv = header.join(",");
+    csv.push('\n');
+    for aggregate in aggregates {
+        let mut row = Vec::new();
+        if query.bucket_secs.is_some() {
+            row.push(aggregate.bucket_start
+                .and_then(|start| DateTime::from_timestamp(start, 0))
+                .map(|start| start.with_timezone(&offset).to_rfc3339())
+                .unwrap_or_default());
+        }
+        for (column, value) in [("account_id", &aggregate.account_id), ("api_key", &aggregate.api_key),
+                                ("project", &aggregate.project), ("model", &aggregate.model)] {
+            if query.group_by.contains(&column) {
+                row.push(csv_field(value.as_deref().unwrap_or("")));
+            }
+        }
+        for value in [aggregate.requests, aggregate.prompt_tokens, aggregate.completion_tokens, aggregate.total_tokens, aggregate.cached_tokens] {
+            row.push(value.to_string());
+        }
+        row.push(aggregate.cost.to_string());
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
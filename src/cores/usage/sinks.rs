@@ -0,0 +1,133 @@
+use async_trait::async_trait;
+use log::info;
+use reqwest::Client;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::configs::settings::Config;
+use crate::cores::usage::kafka::KafkaProducer;
+
+pub const SINKS: [&str; 4] = ["log", "file", "webhook", "kafka"];
+
+// Where token usage events are delivered, a batch of JSON lines at a time.
+// A batch is delivered whole or not at all as far as the pipeline knows; a failed batch is retried and spooled.
+#[async_trait]
+pub trait UsageSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, events: &[String]) -> Result<(), String>;
+}
+
+pub fn build_sink(config: &Config) -> Result<Box<dyn UsageSink>, String> {
+    let timeout = Duration::from_secs(config.usage_sink_timeout);
+    match config.usage_sink.as_str() {
+        "log" => Ok(Box::new(LogSink)),
+        "file" => Ok(Box::new(FileSink::new(&config.usage_file_path))),
+        "webhook" => {
+            if config.usage_webhook_url.is_empty() {
+                return Err("usage_webhook_url is required by the webhook usage sink".to_string());
+            }
+            Ok(Box::new(WebhookSink::new(&config.usage_webhook_url, timeout)?))
+        }
+        "kafka" => {
+            if config.usage_kafka_brokers.is_empty() || config.usage_kafka_topic.is_empty() {
+                return Err("usage_kafka_brokers and usage_kafka_topic are required by the kafka usage sink".to_string());
+            }
+            Ok(Box::new(KafkaSink(KafkaProducer::from_config(config, timeout)?)))
+        }
+        other => Err(format!("Usage sink {} is not one of {}", other, SINKS.join(", "))),
+    }
+}
+
+// The `token` log target, tailed by a separate agent as in earlier releases
+pub struct LogSink;
+
+#[async_trait]
+impl UsageSink for LogSink {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn send(&self, events: &[String]) -> Result<(), String> {
+        for event in events {
+            info!(target: "token", "{}", event);
+        }
+        Ok(())
+    }
+}
+
+// One JSON object per line appended to a local file
+pub struct FileSink {
+    path: String,
+}
+
+impl FileSink {
+    pub fn new(path: &str) -> Self {
+        FileSink { path: path.to_string() }
+    }
+}
+
+#[async_trait]
+impl UsageSink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn send(&self, events: &[String]) -> Result<(), String> {
+        let mut content = events.join("\n");
+        content.push('\n');
+        let write = async {
+            let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+            file.write_all(content.as_bytes()).await?;
+            file.sync_data().await
+        };
+        write.await.map_err(|err| format!("Failed to write usage events to {}: {}", self.path, err))
+    }
+}
+
+// POST of the batch as a JSON array; any 2xx status is a delivery
+pub struct WebhookSink {
+    url: String,
+    client: Client,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, String> {
+        let client = Client::builder().timeout(timeout).build()
+            .map_err(|err| format!("Failed to build usage webhook client: {}", err))?;
+        Ok(WebhookSink { url: url.to_string(), client })
+    }
+}
+
+#[async_trait]
+impl UsageSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn send(&self, events: &[String]) -> Result<(), String> {
+        let body = format!("[{}]", events.join(","));
+        let response = self.client.post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| format!("Usage webhook {}: {}", self.url, err))?;
+        if !response.status().is_success() {
+            return Err(format!("Usage webhook {} returned {}", self.url, response.status()));
+        }
+        Ok(())
+    }
+}
+
+pub struct KafkaSink(pub KafkaProducer);
+
+#[async_trait]
+impl UsageSink for KafkaSink {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn send(&self, events: &[String]) -> Result<(), String> {
+        self.0.send(events).await
+    }
+}
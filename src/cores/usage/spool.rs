@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+// Batches a sink couldn't take, one JSONL file each, kept until they are delivered.
+// The spool never grows past `max_bytes`: a batch that doesn't fit is refused and the caller drops it.
+// Files are handled with `tokio::fs`, so that the delivery task doesn't block a worker thread.
+pub struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl Spool {
+    pub fn new(dir: &str, max_bytes: u64) -> Self {
+        Spool { dir: PathBuf::from(dir), max_bytes }
+    }
+
+    // Spooled batch files, oldest first
+    pub async fn files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        if let Ok(mut entries) = fs::read_dir(&self.dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "jsonl") {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    // (files, bytes) on disk
+    pub async fn size(&self) -> (u64, u64) {
+        let files = self.files().await;
+        let mut bytes = 0;
+        for path in &files {
+            if let Ok(meta) = fs::metadata(path).await {
+                bytes += meta.len();
+            }
+        }
+        (files.len() as u64, bytes)
+    }
+
+    // Keep a batch on disk. Returns false when it would take the spool over its size.
+    pub async fn push(&self, events: &[String]) -> Result<bool, String> {
+        let mut content = events.join("\n");
+        content.push('\n');
+        if self.size().await.1 + content.len() as u64 > self.max_bytes {
+            return Ok(false);
+        }
+        fs::create_dir_all(&self.dir).await.map_err(|err| format!("Failed to create spool dir {}: {}", self.dir.display(), err))?;
+        // Names sort in the order batches were spooled; written to a temp file first so a crash never leaves half a batch
+        let name = format!("{:020}-{:010}", chrono::Utc::now().timestamp_micros(), SEQUENCE.fetch_add(1, Ordering::Relaxed));
+        let tmp = self.dir.join(format!("{}.tmp", name));
+        let write = async {
+            let mut file = fs::File::create(&tmp).await?;
+            file.write_all(content.as_bytes()).await?;
+            file.sync_all().await?;
+            fs::rename(&tmp, self.dir.join(format!("{}.jsonl", name))).await
+        };
+        write.await.map_err(|err| format!("Failed to spool usage events to {}: {}", self.dir.display(), err))?;
+        Ok(true)
+    }
+
+    pub async fn read(path: &Path) -> Result<Vec<String>, String> {
+        let content = fs::read_to_string(path).await.map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        Ok(content.lines().filter(|line| !line.is_empty()).map(str::to_string).collect())
+    }
+
+    pub async fn remove(path: &Path) -> Result<(), String> {
+        fs::remove_file(path).await.map_err(|err| format!("Failed to remove {}: {}", path.display(), err))
+    }
+}
@@ -28,6 +28,9 @@ use crate::apis::api_doc::ApiDoc;
 use crate::utils::log::get_log_config;
 use crate::middleware::qos::MultiServerClient;
 use crate::middleware::qos::check_and_remove_unavailable_clients;
+use crate::cores::control::health::probe_services;
+use crate::cores::control::route_cache::refresh_route_table;
+use crate::cores::usage::{start_usage_pipeline, stop_usage_pipeline};
 use lazy_static::lazy_static;
 
 lazy_static! {
@@ -58,11 +61,38 @@ async fn main() -> std::io::Result<()> {
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to parse log config: {}", e)))?;
     init_raw_config(log_config).unwrap();
 
+    // Deliver token usage events in the background
+    start_usage_pipeline(config)
+        .map_err(|e| std::io::Error::other(format!("Usage pipeline setup failed: {}", e)))?;
+
     let db_pool = setup_database().await
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Database setup failed: {}", e)))?;
     meta::connection::setup_database().await
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Database setup failed: {}", e))).unwrap();
 
+    // Load services and key grants into memory, then keep them in sync with other gateway instances
+    refresh_route_table().await
+        .map_err(|e| std::io::Error::other(format!("Route table setup failed: {}", e)))?;
+    let mut interval = time::interval(Duration::from_secs(config.route_cache_ttl.max(1)));
+    tokio::spawn(async move {
+        loop {
+            interval.tick().await;
+            if let Err(err) = refresh_route_table().await {
+                log::error!(target: "error_log", "Failed to refresh route table: {}", err);
+            }
+        }
+    });
+
+    if config.health_check_enabled {
+        let mut interval = time::interval(Duration::from_secs(config.health_check_interval.max(1)));
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                probe_services().await;
+            }
+        });
+    }
+
     generate_and_save_invitation_codes(&db_pool).await.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Database setup failed: {}", e)))?;
 
     // Set the port number
@@ -88,22 +118,28 @@ async fn main() -> std::io::Result<()> {
             //.wrap(ApiKeyCheck::new(Rc::new(db_pool.clone())))
             .wrap(rate_limiter.clone())
             .configure(|cfg| apis::models_api::chat::configure(cfg, auth_model.clone(), qos.clone()))
+            .configure(|cfg| apis::models_api::completions::configure(cfg, auth_model.clone(), qos.clone()))
             // .configure(|cfg| apis::models_api::embeddings::configure(cfg, auth_model.clone()))
             //.configure(apis::models_api::image::configure)
             //.configure(apis::funcs_api::file_chat::configure)
             //.configure(apis::funcs_api::rag::configure)
-            .configure(|cfg| apis::control_api::models::configure(cfg, auth_manage.clone()))
+            .configure(|cfg| apis::control_api::models::configure(cfg, auth_model.clone()))
+            .configure(|cfg| apis::control_api::catalog::configure(cfg, auth_manage.clone()))
+            .configure(|cfg| apis::control_api::traffic_splits::configure(cfg, auth_manage.clone()))
+            .configure(|cfg| apis::control_api::rate_limits::configure(cfg, auth_manage.clone()))
+            .configure(|cfg| apis::control_api::budgets::configure(cfg, auth_manage.clone()))
             .configure(|cfg| apis::control_api::files::configure(cfg, auth_manage.clone()))
             //.configure(apis::control_api::projects::configure)
             //.configure(apis::control_api::invitation_code::configure)
             //.configure(apis::control_api::users::configure)
             .configure(|cfg| apis::control_api::services::configure(cfg, auth_manage.clone(), auth_model.clone()))
             .configure(|cfg| apis::control_api::model_limits::configure(cfg, auth_manage.clone()))
+            .configure(|cfg| apis::control_api::usage::configure(cfg, auth_manage.clone()))
             .service(SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-docs/openapi.json", ApiDoc::openapi()))
     });
 
     // HTTPS or HTTP setup
-    if config.https_enabled {
+    let result = if config.https_enabled {
         // HTTPS setup
         let mut server_cert_file = BufReader::new(File::open(config.server_cert_file.clone()).unwrap());
         let mut chain_cert_file = BufReader::new(File::open(config.chain_cert_file.clone()).unwrap()); // 中间证书链
@@ -118,8 +154,10 @@ async fn main() -> std::io::Result<()> {
         let mut tls_certs = server_certs;
         tls_certs.extend(chain_certs);
         let tls_key = rustls_pemfile::private_key(&mut key_file).unwrap().unwrap();
-        // set up TLS config options
-        let tls_config = rustls::ServerConfig::builder()
+        // set up TLS config options; the kafka client also brings in the ring provider, so name the one to use
+        let tls_config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
             .with_no_client_auth()
             .with_single_cert(tls_certs, tls_key)
             .unwrap();
@@ -128,5 +166,9 @@ async fn main() -> std::io::Result<()> {
     } else {
         // HTTP setup
         server.bind(("0.0.0.0", port))?.run().await
-    }
+    };
+
+    // Usage events still queued in memory are delivered before the process exits
+    stop_usage_pipeline().await;
+    result
 }
\ No newline at end of file
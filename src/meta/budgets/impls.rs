@@ -0,0 +1,39 @@
+use serde_json::json;
+use std::error::Error;
+use async_trait::async_trait;
+
+use crate::meta::budgets::traits::{Budget, BudgetsTrait};
+use crate::meta::connection::DBCrud;
+
+pub struct BudgetsImpl;
+
+#[async_trait]
+impl BudgetsTrait for BudgetsImpl {
+    async fn create_budget(&self, budget: &Budget) -> Result<(), Box<dyn Error>> {
+        DBCrud::create("budgets", budget).await
+    }
+
+    async fn update_budget(&self, budget: &Budget) -> Result<u64, Box<dyn Error>> {
+        let updates = &[
+            ("scope", json!(budget.scope)),
+            ("subject", json!(budget.subject)),
+            ("monthly_limit", json!(budget.monthly_limit)),
+            ("soft_limit", json!(budget.soft_limit)),
+        ];
+        let conditions = &[("id", json!(budget.id))];
+        DBCrud::update("budgets", updates, Some(conditions)).await
+    }
+
+    async fn delete_budget(&self, id: &str) -> Result<u64, Box<dyn Error>> {
+        let conditions = &[("id", json!(id))];
+        DBCrud::delete("budgets", Some(conditions)).await
+    }
+
+    async fn get_budget(&self, id: &str) -> Result<Option<Budget>, Box<dyn Error>> {
+        DBCrud::get("budgets", "id", &json!(id)).await
+    }
+
+    async fn get_all_budgets(&self) -> Result<Vec<Budget>, Box<dyn Error>> {
+        DBCrud::get_all("budgets").await
+    }
+}
@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::error::Error;
+use utoipa::ToSchema;
+
+use async_trait::async_trait;
+
+/*
+A monthly spend budget, in the currency of the catalog prices, checked by the QoS middleware.
+{
+  "id": "per-account-default",
+  "scope": "account",          // "account" or "key"
+  "subject": "*",              // The account id or api key; "*" gives every subject its own budget.
+  "monthly_limit": 100.0,      // Requests are rejected once the month's spend reaches it, 0 for no limit.
+  "soft_limit": 80.0           // The budget webhook is called once the month's spend reaches it, 0 for no warning.
+}
+A budget naming the subject takes precedence over the "*" budget of its scope.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct Budget {
+    pub id: String,
+    pub scope: String,
+    #[serde(default = "default_subject")]
+    pub subject: String,
+    #[serde(default)]
+    pub monthly_limit: f64,
+    #[serde(default)]
+    pub soft_limit: f64,
+}
+
+fn default_subject() -> String {
+    "*".to_string()
+}
+
+pub const BUDGET_SCOPES: [&str; 2] = ["account", "key"];
+
+#[async_trait]
+pub trait BudgetsTrait: Send + Sync {
+    async fn create_budget(&self, budget: &Budget) -> Result<(), Box<dyn Error>>;
+    async fn update_budget(&self, budget: &Budget) -> Result<u64, Box<dyn Error>>;
+    async fn delete_budget(&self, id: &str) -> Result<u64, Box<dyn Error>>;
+    async fn get_budget(&self, id: &str) -> Result<Option<Budget>, Box<dyn Error>>;
+    async fn get_all_budgets(&self) -> Result<Vec<Budget>, Box<dyn Error>>;
+}
@@ -4,7 +4,8 @@ use tokio_postgres::NoTls;
 use crate::configs::settings::GLOBAL_CONFIG;
 use std::{error, fs};
 use tokio_postgres::{Client, Error};
-use crate::meta::models::Model;
+use crate::meta::models::traits::Model;
+use crate::cores::control::model_limits::{day_quotas_unenforced, legacy_limits};
 use chrono::Utc;
 
 pub async fn setup_database() -> Result<Pool<PostgresConnectionManager<NoTls>>, Box<dyn error::Error>> {
@@ -18,10 +19,16 @@ pub async fn setup_database() -> Result<Pool<PostgresConnectionManager<NoTls>>,
     create_invitation_code_table(&client).await?;
     create_project_object_table(&client).await?;
     create_user_object_table(&client).await?;
-    create_models_table(&mut client).await?;
+    // Services come first, the routing columns of models created by earlier releases are moved into them
     create_services_table(&mut client).await?;
     create_models_service_table(&mut client).await?;
+    create_models_table(&mut client).await?;
     create_model_limits_table(&client).await?;
+    create_quota_counters_table(&client).await?;
+    create_traffic_splits_table(&client).await?;
+    create_rate_limits_table(&client).await?;
+    create_usage_records_table(&client).await?;
+    create_budgets_table(&client).await?;
     create_user_key_table(&client).await?;
     create_user_key_models_table(&client).await?;
 
@@ -118,41 +125,90 @@ async fn create_models_table(client: &mut Client) -> Result<(), Error> {
         CREATE TABLE IF NOT EXISTS models (
         id TEXT PRIMARY KEY,
         object TEXT NOT NULL,
-        model_name TEXT NOT NULL,
-        request_url TEXT NOT NULL,
         created BIGINT NOT NULL,
-        owned_by TEXT NOT NULL
+        owned_by TEXT NOT NULL,
+        context_length INTEGER NOT NULL DEFAULT 0,
+        modality TEXT NOT NULL DEFAULT 'text',
+        prompt_price DOUBLE PRECISION NOT NULL DEFAULT 0,
+        completion_price DOUBLE PRECISION NOT NULL DEFAULT 0,
+        cached_price DOUBLE PRECISION NOT NULL DEFAULT 0,
+        fallbacks TEXT NOT NULL DEFAULT ''
     );
     "#;
     client.execute(create_table_query, &[]).await?;
 
+    // Upgrade tables created before the catalog metadata existed. The upstream name and url
+    // of a model are taken from the services serving it, so the old routing columns are moved
+    // into `services` and then dropped.
+    migrate_model_routes(client).await?;
+    client.batch_execute(r#"
+        ALTER TABLE models ADD COLUMN IF NOT EXISTS context_length INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE models ADD COLUMN IF NOT EXISTS modality TEXT NOT NULL DEFAULT 'text';
+        ALTER TABLE models ADD COLUMN IF NOT EXISTS prompt_price DOUBLE PRECISION NOT NULL DEFAULT 0;
+        ALTER TABLE models ADD COLUMN IF NOT EXISTS completion_price DOUBLE PRECISION NOT NULL DEFAULT 0;
+        ALTER TABLE models ADD COLUMN IF NOT EXISTS cached_price DOUBLE PRECISION NOT NULL DEFAULT 0;
+        ALTER TABLE models ADD COLUMN IF NOT EXISTS fallbacks TEXT NOT NULL DEFAULT '';
+    "#).await?;
+
+    // Other names a model can be requested by
+    client.execute(r#"
+        CREATE TABLE IF NOT EXISTS model_aliases (
+            alias TEXT PRIMARY KEY,
+            model TEXT NOT NULL REFERENCES models(id) ON DELETE CASCADE
+        );
+    "#, &[]).await?;
+
     // Initialize the models table
     init_models_table(client).await?;
 
     Ok(())
 }
 
+// Keep the upstream name and url each model of an earlier release was registered with as an inactive
+// service of the model, unless a service already serves it. The service is activated through `/v1/services`
+// once its url is checked, so that placeholder urls are never routed to.
+async fn migrate_model_routes(client: &mut Client) -> Result<(), Error> {
+    let legacy = client.query_opt(
+        "SELECT 1 FROM information_schema.columns WHERE table_name = 'models' AND column_name = 'request_url'",
+        &[],
+    ).await?.is_some();
+    if !legacy {
+        return Ok(());
+    }
+
+    let tx = client.transaction().await?;
+    let rows = tx.query(
+        "SELECT id, model_name, request_url FROM models
+         WHERE request_url <> '' AND NOT EXISTS (SELECT 1 FROM models_service WHERE modelid = models.id)",
+        &[],
+    ).await?;
+    for row in rows {
+        let (model, model_name, url): (String, String, String) = (row.get(0), row.get(1), row.get(2));
+        let service_id = format!("{}-migrated", model);
+        tx.execute(
+            "INSERT INTO services (id, servicetype, status, url, model_name, active_model) VALUES ($1, 'openai', 'inactive', $2, $3, $4) ON CONFLICT (id) DO NOTHING",
+            &[&service_id, &url, &model_name, &model],
+        ).await?;
+        tx.execute(
+            "INSERT INTO models_service (serviceid, modelid) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            &[&service_id, &model],
+        ).await?;
+        println!("Moved the url {} of model {} to the inactive service {}", url, model, service_id);
+    }
+    tx.batch_execute(r#"
+        ALTER TABLE models DROP COLUMN IF EXISTS model_name;
+        ALTER TABLE models DROP COLUMN IF EXISTS request_url;
+    "#).await?;
+    tx.commit().await
+}
+
 async fn init_models_table(client: &mut Client) -> Result<(), Error> {
     println!("Initializing models table");
     let now = Utc::now();
     let timestamp = now.timestamp();
     let default_models = vec![
-        Model {
-            id: "Qwen2.5-14B-Instruct".to_string(),
-            object: "model".to_string(),
-            model_name: "qwen2.5-instruct".to_string(),
-            request_url: "http://x.x.x.x:30007/v1/chat/completions".to_string(),
-            created: timestamp,
-            owned_by: "system".to_string(),
-        },
-        Model {
-            id: "Qwen2.5-7B-Instruct".to_string(),
-            object: "model".to_string(),
-            model_name: "Qwen/Qwen2.5-7B-Instruct".to_string(),
-            request_url: "http://x.x.x.x:8000/v1/chat/completions".to_string(),
-            created: timestamp,
-            owned_by: "system".to_string(),
-        },
+        Model::new("Qwen2.5-14B-Instruct", "system", timestamp),
+        Model::new("Qwen2.5-7B-Instruct", "system", timestamp),
     ]; 
 
     let models_path = "/etc/chatig/models.yaml";
@@ -172,14 +228,19 @@ async fn init_models_table(client: &mut Client) -> Result<(), Error> {
     let tx = client.transaction().await.unwrap();
     for model in &models {
         let _ = tx.execute(
-            "INSERT INTO models (id, object, model_name, request_url, created, owned_by) VALUES ($1, $2, $3, $4, $5, $6);",
+            "INSERT INTO models (id, object, created, owned_by, context_length, modality, prompt_price, completion_price, cached_price, fallbacks)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) ON CONFLICT (id) DO NOTHING;",
             &[
                 &model.id,
                 &model.object,
-                &model.model_name,
-                &model.request_url,
                 &model.created,
                 &model.owned_by,
+                &model.context_length,
+                &model.modality,
+                &model.prompt_price,
+                &model.completion_price,
+                &model.cached_price,
+                &model.fallbacks,
             ],
         )
         .await;
@@ -198,11 +259,23 @@ pub async fn create_services_table(client: &Client) -> Result<(), Error> {
             status TEXT NOT NULL,
             url TEXT NOT NULL,
             model_name TEXT NOT NULL,
-            active_model TEXT NOT NULL
+            active_model TEXT NOT NULL,
+            weight INTEGER NOT NULL DEFAULT 1,
+            allowed_params TEXT NOT NULL DEFAULT '',
+            denied_params TEXT NOT NULL DEFAULT '',
+            api_key TEXT NOT NULL DEFAULT '',
+            probe_path TEXT NOT NULL DEFAULT ''
         );
     "#;
 
     client.execute(create_table_query, &[]).await?;
+
+    // Upgrade tables created before the weight, parameter list, credential and probe columns existed
+    client.execute("ALTER TABLE services ADD COLUMN IF NOT EXISTS weight INTEGER NOT NULL DEFAULT 1;", &[]).await?;
+    client.execute("ALTER TABLE services ADD COLUMN IF NOT EXISTS allowed_params TEXT NOT NULL DEFAULT '';", &[]).await?;
+    client.execute("ALTER TABLE services ADD COLUMN IF NOT EXISTS denied_params TEXT NOT NULL DEFAULT '';", &[]).await?;
+    client.execute("ALTER TABLE services ADD COLUMN IF NOT EXISTS api_key TEXT NOT NULL DEFAULT '';", &[]).await?;
+    client.execute("ALTER TABLE services ADD COLUMN IF NOT EXISTS probe_path TEXT NOT NULL DEFAULT '';", &[]).await?;
     Ok(())
 }
 
@@ -221,13 +294,137 @@ pub async fn create_models_service_table(client: &Client) -> Result<(), Error> {
     Ok(())
 }
 
-// Create the limits for models of each user
+// Create the request and token quotas of models, per api key, account or project.
+// The text limits of earlier releases, one row per model, are kept as `model_limits_legacy` and converted.
 async fn create_model_limits_table(client: &Client) -> Result<(), Error> {
+    let legacy = client.query_opt(
+        "SELECT 1 FROM information_schema.columns WHERE table_name = 'model_limits' AND column_name = 'max_requests'",
+        &[],
+    ).await?.is_some();
+    if legacy {
+        client.execute("ALTER TABLE model_limits RENAME TO model_limits_legacy", &[]).await?;
+    }
+
     let create_table_query = r#"
         CREATE TABLE IF NOT EXISTS model_limits (
-            model_name TEXT PRIMARY KEY,
-            max_requests TEXT NOT NULL,
-            max_tokens TEXT NOT NULL
+            id TEXT PRIMARY KEY,
+            scope TEXT NOT NULL,
+            subject TEXT NOT NULL DEFAULT '*',
+            model_name TEXT NOT NULL,
+            rpm INTEGER NOT NULL DEFAULT 0,
+            tpm INTEGER NOT NULL DEFAULT 0,
+            rpd INTEGER NOT NULL DEFAULT 0,
+            tpd INTEGER NOT NULL DEFAULT 0
+        );
+    "#;
+    client.execute(create_table_query, &[]).await?;
+
+    if legacy {
+        let config = &*GLOBAL_CONFIG;
+        let rows = client.query("SELECT model_name, max_requests, max_tokens FROM model_limits_legacy", &[]).await?;
+        for row in rows {
+            let limits = legacy_limits(row.get(0), row.get(1), row.get(2));
+            if day_quotas_unenforced(&limits, &config.quota_engine) {
+                println!("Model limits of {} have day quotas ({} requests, {} tokens), which are only enforced with quota_engine \"local\"",
+                    limits.model_name, limits.rpd, limits.tpd);
+            }
+            client.execute(
+                "INSERT INTO model_limits (id, scope, subject, model_name, rpm, tpm, rpd, tpd) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (id) DO NOTHING",
+                &[&limits.id, &limits.scope, &limits.subject, &limits.model_name, &limits.rpm, &limits.tpm, &limits.rpd, &limits.tpd],
+            ).await?;
+        }
+    }
+    Ok(())
+}
+
+// Create the counters of the local quota engine, shared by gateway instances when `quota_shared` is on
+async fn create_quota_counters_table(client: &Client) -> Result<(), Error> {
+    let create_table_query = r#"
+        CREATE TABLE IF NOT EXISTS quota_counters (
+            subject TEXT NOT NULL,
+            model TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            window_secs BIGINT NOT NULL,
+            window_start BIGINT NOT NULL,
+            used BIGINT NOT NULL DEFAULT 0,
+            PRIMARY KEY (subject, model, kind, window_secs, window_start)
+        );
+    "#;
+    client.execute(create_table_query, &[]).await?;
+    Ok(())
+}
+
+// Create the traffic split rules for canary and A/B releases
+async fn create_traffic_splits_table(client: &Client) -> Result<(), Error> {
+    let create_table_query = r#"
+        CREATE TABLE IF NOT EXISTS traffic_splits (
+            id TEXT PRIMARY KEY,
+            model TEXT NOT NULL,
+            target_model TEXT NOT NULL DEFAULT '',
+            target_service TEXT NOT NULL DEFAULT '',
+            percent INTEGER NOT NULL DEFAULT 0,
+            userkeys TEXT NOT NULL DEFAULT '',
+            enabled BOOLEAN NOT NULL DEFAULT TRUE
+        );
+    "#;
+    client.execute(create_table_query, &[]).await?;
+    Ok(())
+}
+
+// Create the request rate limits per api key, account, model or client ip
+async fn create_rate_limits_table(client: &Client) -> Result<(), Error> {
+    let create_table_query = r#"
+        CREATE TABLE IF NOT EXISTS rate_limits (
+            id TEXT PRIMARY KEY,
+            scope TEXT NOT NULL,
+            subject TEXT NOT NULL DEFAULT '*',
+            requests_per_minute INTEGER NOT NULL,
+            burst INTEGER NOT NULL DEFAULT 0,
+            UNIQUE (scope, subject)
+        );
+    "#;
+    client.execute(create_table_query, &[]).await?;
+    Ok(())
+}
+
+// Create the tokens of every finished request, summed by the usage endpoints
+async fn create_usage_records_table(client: &Client) -> Result<(), Error> {
+    let create_table_query = r#"
+        CREATE TABLE IF NOT EXISTS usage_records (
+            id BIGSERIAL PRIMARY KEY,
+            account_id TEXT NOT NULL DEFAULT '',
+            api_key TEXT NOT NULL DEFAULT '',
+            project TEXT NOT NULL DEFAULT '',
+            model TEXT NOT NULL,
+            requested_model TEXT NOT NULL DEFAULT '',
+            variant TEXT NOT NULL DEFAULT '',
+            prompt_tokens INTEGER NOT NULL DEFAULT 0,
+            completion_tokens INTEGER NOT NULL DEFAULT 0,
+            total_tokens INTEGER NOT NULL DEFAULT 0,
+            cached_tokens INTEGER NOT NULL DEFAULT 0,
+            cost DOUBLE PRECISION NOT NULL DEFAULT 0,
+            start_time BIGINT NOT NULL,
+            end_time BIGINT NOT NULL
+        );
+    "#;
+    client.execute(create_table_query, &[]).await?;
+    client.execute("ALTER TABLE usage_records ADD COLUMN IF NOT EXISTS cached_tokens INTEGER NOT NULL DEFAULT 0", &[]).await?;
+    client.execute("ALTER TABLE usage_records ADD COLUMN IF NOT EXISTS cost DOUBLE PRECISION NOT NULL DEFAULT 0", &[]).await?;
+    client.execute("CREATE INDEX IF NOT EXISTS usage_records_start_time ON usage_records (start_time)", &[]).await?;
+    client.execute("CREATE INDEX IF NOT EXISTS usage_records_account ON usage_records (account_id, start_time)", &[]).await?;
+    Ok(())
+}
+
+// Create the monthly spend budgets per account or api key
+async fn create_budgets_table(client: &Client) -> Result<(), Error> {
+    let create_table_query = r#"
+        CREATE TABLE IF NOT EXISTS budgets (
+            id TEXT PRIMARY KEY,
+            scope TEXT NOT NULL,
+            subject TEXT NOT NULL DEFAULT '*',
+            monthly_limit DOUBLE PRECISION NOT NULL DEFAULT 0,
+            soft_limit DOUBLE PRECISION NOT NULL DEFAULT 0,
+            UNIQUE (scope, subject)
         );
     "#;
     client.execute(create_table_query, &[]).await?;
@@ -7,3 +7,7 @@ pub mod services;
 pub mod models;
 pub mod middleware;
 pub mod qos;
+pub mod traffic_splits;
+pub mod rate_limits;
+pub mod budgets;
+pub mod usage;
@@ -0,0 +1,99 @@
+use serde_json::json;
+use std::error::Error;
+use async_trait::async_trait;
+
+use crate::meta::models::traits::{Model, ModelAlias, ModelConfig, ModelsTrait};
+use crate::meta::connection::DBCrud;
+
+pub struct ModelsImpl;
+
+#[async_trait]
+impl ModelsTrait for ModelsImpl {
+    /// 将 `ModelConfig` 插入到 `models` 和 `model_aliases` 表中
+    async fn create_model(&self, model: &ModelConfig) -> Result<(), Box<dyn Error>> {
+        DBCrud::create("models", &model.model).await?;
+
+        for alias in &model.aliases {
+            let alias_data = ModelAlias {
+                alias: alias.clone(),
+                model: model.model.id.clone(),
+            };
+            DBCrud::create("model_aliases", &alias_data).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 更新 `models` 表中的记录，并用新的别名列表替换 `model_aliases` 中的记录
+    async fn update_model(&self, model: &ModelConfig) -> Result<u64, Box<dyn Error>> {
+        let updates = &[
+            ("object", json!(model.model.object)),
+            ("owned_by", json!(model.model.owned_by)),
+            ("context_length", json!(model.model.context_length)),
+            ("modality", json!(model.model.modality)),
+            ("prompt_price", json!(model.model.prompt_price)),
+            ("completion_price", json!(model.model.completion_price)),
+            ("cached_price", json!(model.model.cached_price)),
+            ("fallbacks", json!(model.model.fallbacks)),
+        ];
+        let conditions = &[("id", json!(model.model.id))];
+        let rows_updated = DBCrud::update("models", updates, Some(conditions)).await?;
+        if rows_updated == 0 {
+            return Ok(0);
+        }
+
+        let alias_conditions = &[("model", json!(model.model.id))];
+        DBCrud::delete("model_aliases", Some(alias_conditions)).await?;
+        for alias in &model.aliases {
+            let alias_data = ModelAlias {
+                alias: alias.clone(),
+                model: model.model.id.clone(),
+            };
+            DBCrud::create("model_aliases", &alias_data).await?;
+        }
+
+        Ok(rows_updated)
+    }
+
+    /// 删除 `models` 表中的记录，同时删除 `model_aliases` 表中的别名
+    async fn delete_model(&self, model_id: &str) -> Result<u64, Box<dyn Error>> {
+        let alias_conditions = &[("model", json!(model_id))];
+        DBCrud::delete("model_aliases", Some(alias_conditions)).await?;
+
+        let model_conditions = &[("id", json!(model_id))];
+        let delete_num = DBCrud::delete("models", Some(model_conditions)).await?;
+
+        Ok(delete_num)
+    }
+
+    async fn get_model(&self, model_id: &str) -> Result<Option<Model>, Box<dyn Error>> {
+        DBCrud::get("models", "id", &json!(model_id)).await
+    }
+
+    async fn get_all_models(&self) -> Result<Vec<Model>, Box<dyn Error>> {
+        DBCrud::get_all("models").await
+    }
+
+    async fn get_all_aliases(&self) -> Result<Vec<ModelAlias>, Box<dyn Error>> {
+        DBCrud::get_all("model_aliases").await
+    }
+
+    async fn get_alias(&self, alias: &str) -> Result<Option<ModelAlias>, Box<dyn Error>> {
+        DBCrud::get("model_aliases", "alias", &json!(alias)).await
+    }
+
+    /// 新建别名，或将已有别名指向另一个模型（单条 UPDATE，切换是原子的）
+    async fn set_alias(&self, alias: &ModelAlias) -> Result<(), Box<dyn Error>> {
+        let updates = &[("model", json!(alias.model))];
+        let conditions = &[("alias", json!(alias.alias))];
+        if DBCrud::update("model_aliases", updates, Some(conditions)).await? == 0 {
+            DBCrud::create("model_aliases", alias).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_alias(&self, alias: &str) -> Result<u64, Box<dyn Error>> {
+        let conditions = &[("alias", json!(alias))];
+        DBCrud::delete("model_aliases", Some(conditions)).await
+    }
+}
@@ -0,0 +1,2 @@
+pub mod traits;
+pub mod impls;
\ No newline at end of file
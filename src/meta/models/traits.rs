@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::error::Error;
+use utoipa::ToSchema;
+
+use async_trait::async_trait;
+
+
+// ------------------------------------------ Models API ------------------------------------------
+/*
+The model catalog. `id` is the name clients put in the `model` field, it is linked to the services
+whose `active_model` (or `models_service` list) contains it, and every served model has an entry.
+{
+  "id": "Qwen2.5-7B-Instruct",
+  "object": "model",
+  "created": 1686935002, // The Unix timestamp (in seconds) when the model was created.
+  "owned_by": "system",
+  "context_length": 32768, // Max prompt plus completion tokens, 0 if unknown.
+  "modality": "text",      // Comma separated input modalities, such as "text,image".
+  "prompt_price": 0.5,     // Prices per 1M tokens.
+  "completion_price": 1.0,
+  "cached_price": 0.1,
+  "fallbacks": "Qwen2.5-3B-Instruct" // Comma separated models tried in order when this one can't serve.
+}
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct Model {
+    pub id: String,
+    #[serde(default = "default_object")]
+    pub object: String,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub owned_by: String,
+    #[serde(default)]
+    pub context_length: i32,
+    #[serde(default = "default_modality")]
+    pub modality: String,
+    #[serde(default)]
+    pub prompt_price: f64,
+    #[serde(default)]
+    pub completion_price: f64,
+    #[serde(default)]
+    pub cached_price: f64,
+    #[serde(default)]
+    pub fallbacks: String,
+}
+
+fn default_object() -> String {
+    "model".to_string()
+}
+
+fn default_modality() -> String {
+    "text".to_string()
+}
+
+impl Model {
+    // Fallback model names in the order they are tried
+    pub fn fallback_models(&self) -> Vec<String> {
+        self.fallbacks.split(',')
+            .map(|model| model.trim().to_string())
+            .filter(|model| !model.is_empty())
+            .collect()
+    }
+
+    // The price of a request. Cached prompt tokens are charged at `cached_price`, or at `prompt_price` when it is 0.
+    pub fn cost(&self, prompt_tokens: u32, cached_tokens: u32, completion_tokens: u32) -> f64 {
+        let cached_tokens = cached_tokens.min(prompt_tokens);
+        let cached_price = if self.cached_price > 0.0 { self.cached_price } else { self.prompt_price };
+        ((prompt_tokens - cached_tokens) as f64 * self.prompt_price
+            + cached_tokens as f64 * cached_price
+            + completion_tokens as f64 * self.completion_price) / 1_000_000.0
+    }
+
+    // A catalog entry with no metadata yet, for models that services serve before anyone described them
+    pub fn new(id: &str, owned_by: &str, created: i64) -> Self {
+        Model {
+            id: id.to_string(),
+            object: default_object(),
+            created,
+            owned_by: owned_by.to_string(),
+            context_length: 0,
+            modality: default_modality(),
+            prompt_price: 0.0,
+            completion_price: 0.0,
+            cached_price: 0.0,
+            fallbacks: String::new(),
+        }
+    }
+}
+
+// Other names the model can be requested by, such as `chat-default`, `qwen-latest` or `gpt-3.5-turbo`
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct ModelAlias {
+    pub alias: String,
+    pub model: String,
+}
+
+// A catalog entry as the control API shows it, with its aliases and the services serving it
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ModelConfig {
+    #[serde(flatten)]
+    pub model: Model,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub services: Vec<String>,  // read only, services are linked through the services API
+}
+
+#[async_trait]
+pub trait ModelsTrait: Send + Sync {
+    async fn create_model(&self, model: &ModelConfig) -> Result<(), Box<dyn Error>>;
+    async fn update_model(&self, model: &ModelConfig) -> Result<u64, Box<dyn Error>>;
+    async fn delete_model(&self, model_id: &str) -> Result<u64, Box<dyn Error>>;
+    async fn get_model(&self, model_id: &str) -> Result<Option<Model>, Box<dyn Error>>;
+    async fn get_all_models(&self) -> Result<Vec<Model>, Box<dyn Error>>;
+    async fn get_all_aliases(&self) -> Result<Vec<ModelAlias>, Box<dyn Error>>;
+    async fn get_alias(&self, alias: &str) -> Result<Option<ModelAlias>, Box<dyn Error>>;
+    async fn set_alias(&self, alias: &ModelAlias) -> Result<(), Box<dyn Error>>;
+    async fn delete_alias(&self, alias: &str) -> Result<u64, Box<dyn Error>>;
+}
+
+
+// ------------------------------------------ Served models ------------------------------------------
+// The OpenAI `GET /v1/models` list, built from the services that route requests
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ModelCard {
+    pub id: String,         // The name clients put in the `model` field.
+    pub object: String,     // Always "model".
+    pub created: i64,       // The Unix timestamp (in seconds) when the model was created.
+    pub owned_by: String,   // The organization that owns the model.
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ModelList {
+    pub object: String,     // Always "list".
+    pub data: Vec<ModelCard>,
+}
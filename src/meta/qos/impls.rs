@@ -2,8 +2,8 @@ use serde_json::json;
 use std::error::Error;
 use async_trait::async_trait;
 
-use crate::meta::qos::traits::{Limits, LimitsTrait};
-use crate::meta::connection::DBCrud;
+use crate::meta::qos::traits::{Limits, LimitsTrait, QuotaCountersTrait, QuotaWindow};
+use crate::meta::connection::{get_db_connection, DBCrud, DbConnection};
 
 pub struct LimitsImpl;
 
@@ -11,37 +11,46 @@ pub struct LimitsImpl;
 impl LimitsTrait for LimitsImpl {
     async fn add_limits_object(&self, limits: Limits) -> Result<(), Box<dyn Error>>{
         let model_limits_object = json!({
+            "id": limits.id,
+            "scope": limits.scope,
+            "subject": limits.subject,
             "model_name": limits.model_name,
-            "max_requests": limits.max_requests,
-            "max_tokens": limits.max_tokens,
+            "rpm": limits.rpm,
+            "tpm": limits.tpm,
+            "rpd": limits.rpd,
+            "tpd": limits.tpd,
         });
         DBCrud::create("model_limits", &model_limits_object).await?;
 
         Ok(())
     }
 
-    async fn delete_limits_object(&self, model_name: &str) -> Result<(), Box<dyn Error>>{
-        let limits_conditions = &[("model_name", json!(model_name))];
-        DBCrud::delete("model_limits", Some(limits_conditions)).await?;
+    async fn delete_limits_object(&self, id: &str) -> Result<u64, Box<dyn Error>>{
+        let limits_conditions = &[("id", json!(id))];
+        let delete_num = DBCrud::delete("model_limits", Some(limits_conditions)).await?;
 
-        Ok(())
+        Ok(delete_num)
     }
 
     async fn update_limits_object(&self, limits: Limits) -> Result<u64, Box<dyn Error>>{
         let updates = &[
+            ("scope", json!(limits.scope)),
+            ("subject", json!(limits.subject)),
             ("model_name", json!(limits.model_name)),
-            ("max_requests", json!(limits.max_requests)),
-            ("max_tokens", json!(limits.max_tokens)),
+            ("rpm", json!(limits.rpm)),
+            ("tpm", json!(limits.tpm)),
+            ("rpd", json!(limits.rpd)),
+            ("tpd", json!(limits.tpd)),
         ];
 
-        let conditions = &[("model_name", json!(limits.model_name))];
+        let conditions = &[("id", json!(limits.id))];
         let rows_updated = DBCrud::update("model_limits", updates, Some(conditions)).await?;
 
         Ok(rows_updated)
     }
 
-    async fn get_limits_object(&self, model_name: &str) -> Result<Option<Limits>, Box<dyn Error>>{
-        let limits: Option<Limits> = DBCrud::get("model_limits", "model_name", &json!(model_name)).await?;
+    async fn get_limits_object(&self, id: &str) -> Result<Option<Limits>, Box<dyn Error>>{
+        let limits: Option<Limits> = DBCrud::get("model_limits", "id", &json!(id)).await?;
 
         Ok(limits)
     }
@@ -51,4 +60,65 @@ impl LimitsTrait for LimitsImpl {
 
         Ok(model_limits)
     }
-}
\ No newline at end of file
+}
+
+pub struct QuotaCountersImpl;
+
+#[async_trait]
+impl QuotaCountersTrait for QuotaCountersImpl {
+    async fn get_usage(&self, subject: &str, model: &str, kind: &str, window: QuotaWindow) -> Result<i64, Box<dyn Error>> {
+        let conn = get_db_connection().await?;
+        let used: Option<i64> = match conn {
+            DbConnection::Postgres(mut pg_conn) => {
+                sqlx::query_scalar("SELECT used FROM quota_counters WHERE subject = $1 AND model = $2 AND kind = $3 AND window_secs = $4 AND window_start = $5")
+                    .bind(subject).bind(model).bind(kind).bind(window.secs).bind(window.start)
+                    .fetch_optional(&mut *pg_conn).await?
+            }
+            DbConnection::MySql(mut mysql_conn) => {
+                sqlx::query_scalar("SELECT used FROM quota_counters WHERE subject = ? AND model = ? AND kind = ? AND window_secs = ? AND window_start = ?")
+                    .bind(subject).bind(model).bind(kind).bind(window.secs).bind(window.start)
+                    .fetch_optional(&mut *mysql_conn).await?
+            }
+        };
+        Ok(used.unwrap_or(0))
+    }
+
+    /// 原子地累加计数，返回累加后的值
+    async fn add_usage(&self, subject: &str, model: &str, kind: &str, window: QuotaWindow, amount: i64) -> Result<i64, Box<dyn Error>> {
+        let conn = get_db_connection().await?;
+        let used = match conn {
+            DbConnection::Postgres(mut pg_conn) => {
+                sqlx::query_scalar(
+                    "INSERT INTO quota_counters (subject, model, kind, window_secs, window_start, used) VALUES ($1, $2, $3, $4, $5, $6)
+                     ON CONFLICT (subject, model, kind, window_secs, window_start) DO UPDATE SET used = quota_counters.used + EXCLUDED.used
+                     RETURNING used")
+                    .bind(subject).bind(model).bind(kind).bind(window.secs).bind(window.start).bind(amount)
+                    .fetch_one(&mut *pg_conn).await?
+            }
+            DbConnection::MySql(mut mysql_conn) => {
+                sqlx::query(
+                    "INSERT INTO quota_counters (subject, model, kind, window_secs, window_start, used) VALUES (?, ?, ?, ?, ?, ?)
+                     ON DUPLICATE KEY UPDATE used = used + VALUES(used)")
+                    .bind(subject).bind(model).bind(kind).bind(window.secs).bind(window.start).bind(amount)
+                    .execute(&mut *mysql_conn).await?;
+                self.get_usage(subject, model, kind, window).await?
+            }
+        };
+        Ok(used)
+    }
+
+    async fn delete_expired(&self, now: i64) -> Result<u64, Box<dyn Error>> {
+        let conn = get_db_connection().await?;
+        let result = match conn {
+            DbConnection::Postgres(mut pg_conn) => {
+                sqlx::query("DELETE FROM quota_counters WHERE window_start + window_secs < $1")
+                    .bind(now).execute(&mut *pg_conn).await?.rows_affected()
+            }
+            DbConnection::MySql(mut mysql_conn) => {
+                sqlx::query("DELETE FROM quota_counters WHERE window_start + window_secs < ?")
+                    .bind(now).execute(&mut *mysql_conn).await?.rows_affected()
+            }
+        };
+        Ok(result)
+    }
+}
@@ -1,21 +1,69 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use std::error::Error;
+use utoipa::ToSchema;
 
 use async_trait::async_trait;
 
-#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+/*
+Request and token quotas of a model, enforced by the QoS middleware for each subject.
+{
+  "id": "qwen-per-key",
+  "scope": "key",                       // "key", "account" or "project"
+  "subject": "*",                       // The api key, account id or project id (`OpenAI-Project` header);
+                                        // "*" gives every subject its own quota.
+  "model_name": "Qwen2.5-7B-Instruct",
+  "rpm": 60,                            // Requests per minute
+  "tpm": 100000,                        // Tokens per minute
+  "rpd": 0,                             // Requests per day
+  "tpd": 0                              // Tokens per day; 0 leaves a window unlimited
+}
+In each scope a row naming the subject takes precedence over the "*" row, and the rows of all scopes apply together.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
 pub struct Limits {
+    pub id: String,
+    pub scope: String,
+    #[serde(default = "default_subject")]
+    pub subject: String,
     pub model_name: String,
-    pub max_requests: String,
-    pub max_tokens: String,
+    #[serde(default)]
+    pub rpm: i32,
+    #[serde(default)]
+    pub tpm: i32,
+    #[serde(default)]
+    pub rpd: i32,
+    #[serde(default)]
+    pub tpd: i32,
+}
+
+fn default_subject() -> String {
+    "*".to_string()
 }
 
+pub const LIMIT_SCOPES: [&str; 3] = ["key", "account", "project"];
+
 #[async_trait]
 pub trait LimitsTrait: Send + Sync {
     async fn add_limits_object(&self, limits: Limits) -> Result<(), Box<dyn Error>>;
-    async fn delete_limits_object(&self, model_name: &str) -> Result<(), Box<dyn Error>>;
+    async fn delete_limits_object(&self, id: &str) -> Result<u64, Box<dyn Error>>;
     async fn update_limits_object(&self, limits: Limits) -> Result<u64, Box<dyn Error>>;
-    async fn get_limits_object(&self, model_name: &str) -> Result<Option<Limits>, Box<dyn Error>>;
+    async fn get_limits_object(&self, id: &str) -> Result<Option<Limits>, Box<dyn Error>>;
     async fn get_all_limits_objects(&self) -> Result<Vec<Limits>, Box<dyn Error>>;
+}
+
+// A fixed window of the local quota engine: its length and start, in seconds since the epoch
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QuotaWindow {
+    pub secs: i64,
+    pub start: i64,
+}
+
+// Counters of the local quota engine kept in the database, so that gateway instances share them.
+// `kind` is "requests" or "tokens".
+#[async_trait]
+pub trait QuotaCountersTrait: Send + Sync {
+    async fn get_usage(&self, subject: &str, model: &str, kind: &str, window: QuotaWindow) -> Result<i64, Box<dyn Error>>;
+    async fn add_usage(&self, subject: &str, model: &str, kind: &str, window: QuotaWindow, amount: i64) -> Result<i64, Box<dyn Error>>;
+    async fn delete_expired(&self, now: i64) -> Result<u64, Box<dyn Error>>;
 }
\ No newline at end of file
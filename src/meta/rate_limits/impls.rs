@@ -0,0 +1,39 @@
+use serde_json::json;
+use std::error::Error;
+use async_trait::async_trait;
+
+use crate::meta::rate_limits::traits::{RateLimit, RateLimitsTrait};
+use crate::meta::connection::DBCrud;
+
+pub struct RateLimitsImpl;
+
+#[async_trait]
+impl RateLimitsTrait for RateLimitsImpl {
+    async fn create_rate_limit(&self, limit: &RateLimit) -> Result<(), Box<dyn Error>> {
+        DBCrud::create("rate_limits", limit).await
+    }
+
+    async fn update_rate_limit(&self, limit: &RateLimit) -> Result<u64, Box<dyn Error>> {
+        let updates = &[
+            ("scope", json!(limit.scope)),
+            ("subject", json!(limit.subject)),
+            ("requests_per_minute", json!(limit.requests_per_minute)),
+            ("burst", json!(limit.burst)),
+        ];
+        let conditions = &[("id", json!(limit.id))];
+        DBCrud::update("rate_limits", updates, Some(conditions)).await
+    }
+
+    async fn delete_rate_limit(&self, id: &str) -> Result<u64, Box<dyn Error>> {
+        let conditions = &[("id", json!(id))];
+        DBCrud::delete("rate_limits", Some(conditions)).await
+    }
+
+    async fn get_rate_limit(&self, id: &str) -> Result<Option<RateLimit>, Box<dyn Error>> {
+        DBCrud::get("rate_limits", "id", &json!(id)).await
+    }
+
+    async fn get_all_rate_limits(&self) -> Result<Vec<RateLimit>, Box<dyn Error>> {
+        DBCrud::get_all("rate_limits").await
+    }
+}
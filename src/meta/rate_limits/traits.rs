@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::error::Error;
+use utoipa::ToSchema;
+
+use async_trait::async_trait;
+
+
+/*
+A request rate limit, enforced by `RateLimitMiddleware` with one token bucket per subject.
+{
+  "id": "per-key-default",
+  "scope": "key",              // "key", "account", "model" or "ip"
+  "subject": "*",              // The api key, account id, model or ip; "*" gives every subject its own bucket.
+  "requests_per_minute": 60,
+  "burst": 10                  // Bucket capacity, 0 uses requests_per_minute.
+}
+A rule naming the subject takes precedence over the "*" rule of its scope.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct RateLimit {
+    pub id: String,
+    pub scope: String,
+    #[serde(default = "default_subject")]
+    pub subject: String,
+    pub requests_per_minute: i32,
+    #[serde(default)]
+    pub burst: i32,
+}
+
+fn default_subject() -> String {
+    "*".to_string()
+}
+
+pub const RATE_LIMIT_SCOPES: [&str; 4] = ["key", "account", "model", "ip"];
+
+#[async_trait]
+pub trait RateLimitsTrait: Send + Sync {
+    async fn create_rate_limit(&self, limit: &RateLimit) -> Result<(), Box<dyn Error>>;
+    async fn update_rate_limit(&self, limit: &RateLimit) -> Result<u64, Box<dyn Error>>;
+    async fn delete_rate_limit(&self, id: &str) -> Result<u64, Box<dyn Error>>;
+    async fn get_rate_limit(&self, id: &str) -> Result<Option<RateLimit>, Box<dyn Error>>;
+    async fn get_all_rate_limits(&self) -> Result<Vec<RateLimit>, Box<dyn Error>>;
+}
@@ -3,13 +3,38 @@ use serde_json::json;
 use std::fs;
 use std::error::Error;
 use async_trait::async_trait;
-use rand::Rng;
 
 use crate::meta::services::traits::{Services, ModelsService, ServiceConfig, ServicesTrait};
 use crate::meta::connection::DBCrud;
 
 pub struct ServicesImpl;
 
+// 将 `services` 表中的记录和模型列表组装成 `ServiceConfig`
+fn service_config(service: Services, models: Vec<String>) -> ServiceConfig {
+    ServiceConfig {
+        id: service.id,
+        servicetype: service.servicetype,
+        status: service.status,
+        url: service.url,
+        model_name: service.model_name,
+        active_model: service.active_model,
+        weight: service.weight,
+        allowed_params: split_params(&service.allowed_params),
+        denied_params: split_params(&service.denied_params),
+        api_key: service.api_key,
+        probe_path: service.probe_path,
+        models,
+    }
+}
+
+fn split_params(params: &str) -> Vec<String> {
+    params.split(',')
+        .map(|param| param.trim())
+        .filter(|param| !param.is_empty())
+        .map(String::from)
+        .collect()
+}
+
 #[async_trait]
 impl ServicesTrait for ServicesImpl {
     /// 加载/etc/chatig/services.yaml文件到 `services` 和 `models_service` 表中
@@ -34,6 +59,11 @@ impl ServicesTrait for ServicesImpl {
                 "url": service.url,
                 "model_name": service.model_name,
                 "active_model": service.active_model,
+                "weight": service.weight,
+                "allowed_params": service.allowed_params.join(","),
+                "denied_params": service.denied_params.join(","),
+                "api_key": service.api_key,
+                "probe_path": service.probe_path,
             });
     
             if let Err(err) = DBCrud::create("services", &service_data).await {
@@ -68,6 +98,11 @@ impl ServicesTrait for ServicesImpl {
             "url": service.url,
             "model_name": service.model_name,
             "active_model": service.active_model,
+            "weight": service.weight,
+            "allowed_params": service.allowed_params.join(","),
+            "denied_params": service.denied_params.join(","),
+            "api_key": service.api_key,
+            "probe_path": service.probe_path,
         });
         DBCrud::create("services", &service_data).await?;
 
@@ -104,6 +139,11 @@ impl ServicesTrait for ServicesImpl {
             ("url", json!(service.url)),
             ("model_name", json!(service.model_name)),
             ("active_model", json!(service.active_model)),
+            ("weight", json!(service.weight)),
+            ("allowed_params", json!(service.allowed_params.join(","))),
+            ("denied_params", json!(service.denied_params.join(","))),
+            ("api_key", json!(service.api_key)),
+            ("probe_path", json!(service.probe_path)),
         ];
         let conditions = &[("id", json!(service.id))];
         let rows_updated = DBCrud::update("services", updates, Some(conditions)).await?;
@@ -123,19 +163,12 @@ impl ServicesTrait for ServicesImpl {
             // 提取模型 ID 列表
             let model_ids = models
                 .iter()
-                .filter_map(|record| Some(record.modelid.clone()))
+                .filter(|record| record.serviceid == service.id)
+                .map(|record| record.modelid.clone())
                 .collect::<Vec<String>>();
     
             // 组装成完整的 `ServiceConfig`
-            let service_config = ServiceConfig {
-                id: service.id,
-                servicetype: service.servicetype,
-                status: service.status,
-                url: service.url,
-                model_name: service.model_name,
-                active_model: service.active_model,
-                models: model_ids,
-            };
+            let service_config = service_config(service, model_ids);
     
             Ok(Some(service_config))
         } else {
@@ -143,34 +176,18 @@ impl ServicesTrait for ServicesImpl {
         }
     }
 
-    /// 根据模型名称查询 `ServiceConfig`
-    async fn get_service_by_model(&self, active_model: &str) -> Result<Option<ServiceConfig>, Box<dyn Error>>{
+    /// 根据模型名称查询所有副本的 `ServiceConfig`
+    async fn get_services_by_model(&self, active_model: &str) -> Result<Vec<ServiceConfig>, Box<dyn Error>>{
         // 查询 `services` 表中的记录
         let services: Vec<Services> = DBCrud::get_multis("services", "active_model", &json!(active_model)).await?;
-        let service_num = services.len();
-
-        if service_num > 0 {
-            let service = if services.len() > 1 {
-                get_random_service(&services).unwrap_or_else(|| services[0].clone())
-            } else {
-                services[0].clone()
-            };
-            
-            // 组装成完整的 `ServiceConfig`
-            let service_config = ServiceConfig {
-                id: service.id,
-                servicetype: service.servicetype,
-                status: service.status,
-                url: service.url,
-                model_name: service.model_name,
-                active_model: service.active_model,
-                models: vec![String::from("")],
-            };
-    
-            Ok(Some(service_config))
-        } else {
-            Ok(None)
-        }
+
+        // 组装成完整的 `ServiceConfig`
+        let service_configs = services
+            .into_iter()
+            .map(|service| service_config(service, vec![String::from("")]))
+            .collect();
+
+        Ok(service_configs)
     }
 
     /// 查询所有 `ServiceConfig`
@@ -192,15 +209,7 @@ impl ServicesTrait for ServicesImpl {
                 .collect::<Vec<String>>();
     
             // 组装成 `ServiceConfig`
-            let service_config = ServiceConfig {
-                id: service.id,
-                servicetype: service.servicetype,
-                status: service.status,
-                url: service.url,
-                model_name: service.model_name,
-                active_model: service.active_model,
-                models: model_ids,
-            };
+            let service_config = service_config(service, model_ids);
     
             service_configs.push(service_config);
         }
@@ -209,13 +218,3 @@ impl ServicesTrait for ServicesImpl {
     }
     
 }
-
-fn get_random_service(services: &Vec<Services>) -> Option<Services> {
-    let n = services.len();
-
-    // 随机生成一个索引
-    let index = rand::thread_rng().gen_range(0..n);
-
-    // 返回随机服务
-    services.get(index).cloned()
-} 
\ No newline at end of file
@@ -12,6 +12,11 @@ pub struct Services {
     pub url: String,
     pub model_name: String,
     pub active_model: String,
+    pub weight: i32,    // relative share of traffic for weighted round-robin
+    pub allowed_params: String, // comma separated request parameters forwarded to the service, empty for all
+    pub denied_params: String,  // comma separated request parameters never forwarded to the service
+    pub api_key: String,        // sent as a bearer token to hosted services, empty for none
+    pub probe_path: String,     // health probe url relative to `url`, empty for the `models` endpoint, "none" to not probe
 }
 
 // 记录多模型集群里，集群支持的模型类型
@@ -29,13 +34,27 @@ pub struct ServiceConfig {
     pub url: String,
     pub model_name: String,
     pub active_model: String,
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+    #[serde(default)]
+    pub allowed_params: Vec<String>,
+    #[serde(default)]
+    pub denied_params: Vec<String>,
+    #[serde(default, skip_serializing)]
+    pub api_key: String,
+    #[serde(default)]
+    pub probe_path: String,
     pub models: Vec<String>,
 }
 
+fn default_weight() -> i32 {
+    1
+}
+
 #[derive(Deserialize)]
 pub struct InvalidateCacheRequest {
     pub key: String,
-    pub cache_type: String, // 指定是清除哪一类缓存, 可以是 "manage" 或 "model"
+    pub cache_type: String, // 指定是清除哪一类缓存, 可以是 "manage"、"model" 或 "route"
 }
 
 #[async_trait]
@@ -45,6 +64,6 @@ pub trait ServicesTrait: Send + Sync {
     async fn delete_service(&self, service_id: &str) -> Result<u64, Box<dyn Error>>;
     async fn update_service(&self, service: &ServiceConfig) -> Result<u64, Box<dyn Error>>;
     async fn get_service(&self, service_id: &str) -> Result<Option<ServiceConfig>, Box<dyn Error>>;
-    async fn get_service_by_model(&self, active_model: &str) -> Result<Option<ServiceConfig>, Box<dyn Error>>;
+    async fn get_services_by_model(&self, active_model: &str) -> Result<Vec<ServiceConfig>, Box<dyn Error>>;
     async fn get_all_services(&self) -> Result<Vec<ServiceConfig>, Box<dyn Error>>;
 }
\ No newline at end of file
@@ -0,0 +1,41 @@
+use serde_json::json;
+use std::error::Error;
+use async_trait::async_trait;
+
+use crate::meta::traffic_splits::traits::{TrafficSplit, TrafficSplitsTrait};
+use crate::meta::connection::DBCrud;
+
+pub struct TrafficSplitsImpl;
+
+#[async_trait]
+impl TrafficSplitsTrait for TrafficSplitsImpl {
+    async fn create_split(&self, split: &TrafficSplit) -> Result<(), Box<dyn Error>> {
+        DBCrud::create("traffic_splits", split).await
+    }
+
+    async fn update_split(&self, split: &TrafficSplit) -> Result<u64, Box<dyn Error>> {
+        let updates = &[
+            ("model", json!(split.model)),
+            ("target_model", json!(split.target_model)),
+            ("target_service", json!(split.target_service)),
+            ("percent", json!(split.percent)),
+            ("userkeys", json!(split.userkeys)),
+            ("enabled", json!(split.enabled)),
+        ];
+        let conditions = &[("id", json!(split.id))];
+        DBCrud::update("traffic_splits", updates, Some(conditions)).await
+    }
+
+    async fn delete_split(&self, id: &str) -> Result<u64, Box<dyn Error>> {
+        let conditions = &[("id", json!(id))];
+        DBCrud::delete("traffic_splits", Some(conditions)).await
+    }
+
+    async fn get_split(&self, id: &str) -> Result<Option<TrafficSplit>, Box<dyn Error>> {
+        DBCrud::get("traffic_splits", "id", &json!(id)).await
+    }
+
+    async fn get_all_splits(&self) -> Result<Vec<TrafficSplit>, Box<dyn Error>> {
+        DBCrud::get_all("traffic_splits").await
+    }
+}
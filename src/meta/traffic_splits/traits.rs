@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::error::Error;
+use utoipa::ToSchema;
+
+use async_trait::async_trait;
+
+
+/*
+A traffic split rule on a model, used for canary releases and A/B tests. Requests for `model` from the
+listed `userkeys`, and `percent` of the other requests, go to the variant: another model, or one
+service of the model. The assignment is sticky per user.
+{
+  "id": "qwen-ft-canary",              // The variant name, recorded in the token log.
+  "model": "Qwen2.5-7B-Instruct",
+  "target_model": "Qwen2.5-7B-ft-v2",  // Either a model ...
+  "target_service": "",                // ... or a service id serving `model`.
+  "percent": 10,                       // 0 - 100
+  "userkeys": "sk-tester1,sk-tester2", // Comma separated keys always sent to the variant.
+  "enabled": true
+}
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct TrafficSplit {
+    pub id: String,
+    pub model: String,
+    #[serde(default)]
+    pub target_model: String,
+    #[serde(default)]
+    pub target_service: String,
+    #[serde(default)]
+    pub percent: i32,
+    #[serde(default)]
+    pub userkeys: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl TrafficSplit {
+    pub fn has_userkey(&self, userkey: &str) -> bool {
+        !userkey.is_empty() && self.userkeys.split(',').any(|key| key.trim() == userkey)
+    }
+}
+
+#[async_trait]
+pub trait TrafficSplitsTrait: Send + Sync {
+    async fn create_split(&self, split: &TrafficSplit) -> Result<(), Box<dyn Error>>;
+    async fn update_split(&self, split: &TrafficSplit) -> Result<u64, Box<dyn Error>>;
+    async fn delete_split(&self, id: &str) -> Result<u64, Box<dyn Error>>;
+    async fn get_split(&self, id: &str) -> Result<Option<TrafficSplit>, Box<dyn Error>>;
+    async fn get_all_splits(&self) -> Result<Vec<TrafficSplit>, Box<dyn Error>>;
+}
@@ -0,0 +1,152 @@
+use std::error::Error;
+use async_trait::async_trait;
+
+use crate::meta::usage::traits::{UsageAggregate, UsageQuery, UsageRecord, UsageRecordsTrait, USAGE_GROUPS};
+use crate::meta::connection::{get_db_connection, DbConnection};
+
+pub struct UsageRecordsImpl;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UsageBind {
+    Text(String),
+    Int(i64),
+}
+
+// The SELECT summing the records of a query. Column names and bucket sizes come from the code,
+// only the filter values are bound.
+pub fn aggregate_sql(query: &UsageQuery, mysql: bool) -> (String, Vec<UsageBind>) {
+    let (text, bigint) = if mysql { ("CHAR", "SIGNED") } else { ("TEXT", "BIGINT") };
+    let mut columns = Vec::new();
+    let mut groups = Vec::new();
+
+    match query.bucket_secs {
+        Some(secs) => {
+            let shifted = format!("(start_time + {})", query.utc_offset);
+            columns.push(format!("({} - MOD({}, {}) - {}) AS bucket_start", shifted, shifted, secs, query.utc_offset));
+            groups.push("bucket_start".to_string());
+        }
+        None => columns.push(format!("CAST(NULL AS {}) AS bucket_start", bigint)),
+    }
+    for (_, column) in USAGE_GROUPS {
+        if query.group_by.contains(&column) {
+            columns.push(column.to_string());
+            groups.push(column.to_string());
+        } else {
+            columns.push(format!("CAST(NULL AS {}) AS {}", text, column));
+        }
+    }
+    columns.push("COUNT(*) AS requests".to_string());
+    for column in ["prompt_tokens", "completion_tokens", "total_tokens", "cached_tokens"] {
+        columns.push(format!("CAST(COALESCE(SUM({}), 0) AS {}) AS {}", column, bigint, column));
+    }
+    columns.push(format!("CAST(COALESCE(SUM(cost), 0) AS {}) AS cost", if mysql { "DOUBLE" } else { "DOUBLE PRECISION" }));
+
+    let mut conditions = Vec::new();
+    let mut binds = Vec::new();
+    let mut condition = |sql: &str, bind: UsageBind| {
+        binds.push(bind);
+        let placeholder = if mysql { "?".to_string() } else { format!("${}", binds.len()) };
+        conditions.push(format!("{} {}", sql, placeholder));
+    };
+    if let Some(start) = query.start {
+        condition("start_time >=", UsageBind::Int(start));
+    }
+    if let Some(end) = query.end {
+        condition("start_time <", UsageBind::Int(end));
+    }
+    for (column, value) in [("account_id", &query.account_id), ("api_key", &query.api_key), ("project", &query.project), ("model", &query.model)] {
+        if let Some(value) = value {
+            condition(&format!("{} =", column), UsageBind::Text(value.clone()));
+        }
+    }
+
+    let mut sql = format!("SELECT {} FROM usage_records", columns.join(", "));
+    if !conditions.is_empty() {
+        sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    if !groups.is_empty() {
+        sql.push_str(&format!(" GROUP BY {} ORDER BY {}", groups.join(", "), groups.join(", ")));
+    }
+    (sql, binds)
+}
+
+#[async_trait]
+impl UsageRecordsTrait for UsageRecordsImpl {
+    async fn add_usage_record(&self, record: &UsageRecord) -> Result<(), Box<dyn Error>> {
+        let conn = get_db_connection().await?;
+        match conn {
+            DbConnection::Postgres(mut pg_conn) => {
+                sqlx::query(
+                    "INSERT INTO usage_records (account_id, api_key, project, model, requested_model, variant, prompt_tokens, completion_tokens, total_tokens, cached_tokens, cost, start_time, end_time)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)")
+                    .bind(&record.account_id).bind(&record.api_key).bind(&record.project).bind(&record.model)
+                    .bind(&record.requested_model).bind(&record.variant)
+                    .bind(record.prompt_tokens).bind(record.completion_tokens).bind(record.total_tokens)
+                    .bind(record.cached_tokens).bind(record.cost)
+                    .bind(record.start_time).bind(record.end_time)
+                    .execute(&mut *pg_conn).await?;
+            }
+            DbConnection::MySql(mut mysql_conn) => {
+                sqlx::query(
+                    "INSERT INTO usage_records (account_id, api_key, project, model, requested_model, variant, prompt_tokens, completion_tokens, total_tokens, cached_tokens, cost, start_time, end_time)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+                    .bind(&record.account_id).bind(&record.api_key).bind(&record.project).bind(&record.model)
+                    .bind(&record.requested_model).bind(&record.variant)
+                    .bind(record.prompt_tokens).bind(record.completion_tokens).bind(record.total_tokens)
+                    .bind(record.cached_tokens).bind(record.cost)
+                    .bind(record.start_time).bind(record.end_time)
+                    .execute(&mut *mysql_conn).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn aggregate_usage(&self, query: &UsageQuery) -> Result<Vec<UsageAggregate>, Box<dyn Error>> {
+        let conn = get_db_connection().await?;
+        let aggregates = match conn {
+            DbConnection::Postgres(mut pg_conn) => {
+                let (sql, binds) = aggregate_sql(query, false);
+                let mut select = sqlx::query_as::<_, UsageAggregate>(&sql);
+                for bind in binds {
+                    select = match bind {
+                        UsageBind::Text(value) => select.bind(value),
+                        UsageBind::Int(value) => select.bind(value),
+                    };
+                }
+                select.fetch_all(&mut *pg_conn).await?
+            }
+            DbConnection::MySql(mut mysql_conn) => {
+                let (sql, binds) = aggregate_sql(query, true);
+                let mut select = sqlx::query_as::<_, UsageAggregate>(&sql);
+                for bind in binds {
+                    select = match bind {
+                        UsageBind::Text(value) => select.bind(value),
+                        UsageBind::Int(value) => select.bind(value),
+                    };
+                }
+                select.fetch_all(&mut *mysql_conn).await?
+            }
+        };
+        Ok(aggregates)
+    }
+
+    async fn get_spend(&self, column: &str, subject: &str, since: i64) -> Result<f64, Box<dyn Error>> {
+        if !["account_id", "api_key"].contains(&column) {
+            return Err(format!("Cannot sum spend by {}", column).into());
+        }
+        let conn = get_db_connection().await?;
+        let spend: Option<f64> = match conn {
+            DbConnection::Postgres(mut pg_conn) => {
+                sqlx::query_scalar(&format!("SELECT CAST(SUM(cost) AS DOUBLE PRECISION) FROM usage_records WHERE {} = $1 AND start_time >= $2", column))
+                    .bind(subject).bind(since)
+                    .fetch_one(&mut *pg_conn).await?
+            }
+            DbConnection::MySql(mut mysql_conn) => {
+                sqlx::query_scalar(&format!("SELECT CAST(SUM(cost) AS DOUBLE) FROM usage_records WHERE {} = ? AND start_time >= ?", column))
+                    .bind(subject).bind(since)
+                    .fetch_one(&mut *mysql_conn).await?
+            }
+        };
+        Ok(spend.unwrap_or(0.0))
+    }
+}
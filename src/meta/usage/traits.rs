@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::error::Error;
+use utoipa::ToSchema;
+
+use async_trait::async_trait;
+
+/*
+The tokens of one finished request, written after the response has been sent.
+{
+  "id": 1024,
+  "account_id": "111111",
+  "api_key": "sk-...",
+  "project": "",                            // `OpenAI-Project` header, empty when not sent
+  "model": "Qwen2.5-7B-Instruct",           // the catalog model that served the request
+  "requested_model": "qwen",                // the name the client asked for, possibly an alias
+  "variant": "",                            // the traffic split rule that picked the route
+  "prompt_tokens": 120,
+  "completion_tokens": 300,
+  "total_tokens": 420,
+  "cached_tokens": 100,                     // prompt tokens served from the upstream prefix cache
+  "cost": 0.00036,                          // priced by the catalog model, prices are per 1M tokens
+  "start_time": 1704067200,                 // Unix timestamps
+  "end_time": 1704067203
+}
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct UsageRecord {
+    #[serde(default)]
+    pub id: i64,
+    pub account_id: String,
+    pub api_key: String,
+    pub project: String,
+    pub model: String,
+    pub requested_model: String,
+    pub variant: String,
+    pub prompt_tokens: i32,
+    pub completion_tokens: i32,
+    pub total_tokens: i32,
+    pub cached_tokens: i32,
+    pub cost: f64,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+// Columns the records can be grouped by, with their names in the query
+pub const USAGE_GROUPS: [(&str, &str); 4] = [("account", "account_id"), ("key", "api_key"), ("project", "project"), ("model", "model")];
+
+// Which records to sum and how to group them. Columns not grouped by are None in the results.
+#[derive(Debug, Clone, Default)]
+pub struct UsageQuery {
+    pub start: Option<i64>,             // start_time >= start
+    pub end: Option<i64>,               // start_time < end
+    pub account_id: Option<String>,
+    pub api_key: Option<String>,
+    pub project: Option<String>,
+    pub model: Option<String>,
+    pub group_by: Vec<&'static str>,    // columns of USAGE_GROUPS
+    pub bucket_secs: Option<i64>,       // time buckets of this size, aligned to UTC shifted by utc_offset
+    pub utc_offset: i64,                // seconds
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, FromRow, ToSchema)]
+pub struct UsageAggregate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket_start: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+    pub cached_tokens: i64,
+    pub cost: f64,
+}
+
+#[async_trait]
+pub trait UsageRecordsTrait: Send + Sync {
+    async fn add_usage_record(&self, record: &UsageRecord) -> Result<(), Box<dyn Error>>;
+    async fn aggregate_usage(&self, query: &UsageQuery) -> Result<Vec<UsageAggregate>, Box<dyn Error>>;
+    // Cost of the requests whose `column` ("account_id" or "api_key") is `subject`, started at `since` or later
+    async fn get_spend(&self, column: &str, subject: &str, since: i64) -> Result<f64, Box<dyn Error>>;
+}
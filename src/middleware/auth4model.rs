@@ -6,7 +6,7 @@ use std::time::Duration;
 
 use crate::configs::settings::GLOBAL_CONFIG;
 use crate::meta::middleware::traits::UserKeysTrait;
-use crate::meta::middleware::impls::UserKeysImpl;
+use crate::cores::control::route_cache::CachedUserKeysImpl;
 use crate::middleware::auth_cache::AuthCache;
 use log::{info, error};
 
@@ -32,7 +32,7 @@ pub struct Auth4ModelMiddleware {
 
 impl Auth4ModelMiddleware {
     pub fn new() -> Self {
-        let userkeys = Arc::new(UserKeysImpl);
+        let userkeys = Arc::new(CachedUserKeysImpl);
         let cache = Arc::new(Mutex::new(AuthCache::new()));
         Self { userkeys, cache }
     }
@@ -87,6 +87,8 @@ where
             .map(|auth_str| auth_str.replace("Bearer ", ""))
             .map(|s| s.to_string());
 
+        let cache: Arc<Mutex<AuthCache>> = self.cache.clone();
+
         let payload = req.take_payload();
         let body = BytesMut::new();
 
@@ -107,7 +109,6 @@ where
             read_payload(payload, body).await
         };
 
-        let cache: Arc<Mutex<AuthCache>> = self.cache.clone();
         Box::pin(async move {
             let (chat_request, body_clone) = read_payload_fut.await?;
             let model = Some(chat_request.model);
@@ -173,57 +174,63 @@ where
             // 如果启用了远程鉴权
              if config.auth_remote_enabled {
                 
-                // 构造缓存的key
-                let cache_key = format!("{}:{}", api_key.clone(), model_name.clone());
+                let user_id = remote_check(&cache, &api_key, &model_name).await?;
+                req.extensions_mut().insert(user_id);
+                return service.call(req).await;
+            }
 
-                // 检查缓存
-                let cache_result = cache.lock().unwrap().check_cache_model(&cache_key);
+            Err(ErrorForbidden("Authentication failed"))
+        })
+    }
+}
 
-                if let Some(user_id) = cache_result {
-                    // 缓存命中，返回成功
-                    info!(target: "access_log", "Cache hit for user_id: {:?}", user_id);
-                    req.extensions_mut().insert(user_id);
-                    return service.call(req).await;
-                }
+// Ask the remote auth server whether the key may use the model, returns the account id.
+// Successful checks are cached for `auth_cache_time` seconds.
+pub async fn remote_check(cache: &Mutex<AuthCache>, api_key: &str, model_name: &str) -> Result<String, Error> {
+    let config = &*GLOBAL_CONFIG;
 
-                let url = format!("{}/v1/apiInfo/check", config.auth_remote_server);
-                let client = reqwest::Client::new();
-                let response = client.post(&url)
-                    .json(&serde_json::json!({
-                        "apiKey": api_key.clone(),
-                        "modelName": model_name.clone(),
-                        "cloudRegionId": config.cloud_region_id
-                    }))
-                    .timeout(Duration::from_secs(5))
-                    .send()
-                    .await;
-
-                // info!(target: "access_log", "Model remote auth response: {:?}", response);
-                match response {
-                    Ok(resp) if resp.status().is_success() => {
-                        if let Ok(json) = resp.json::<serde_json::Value>().await {
-                            let account_id = json.get("accountId").and_then(|u| u.as_str()).map(|u| u.to_string());
-                            // let user_id = json.get("userId").and_then(|u| u.as_str()).map(|u| u.to_string());
-                            let is_valid = json.get("isValid").and_then(|v| v.as_bool());
-            
-                            if let (Some(user_id), Some(true)) = (account_id.clone(), is_valid) {
-                                // 获取远程校验通过后的用户ID，缓存它
-                                req.extensions_mut().insert(user_id.clone());
-                                cache.lock().unwrap().set_cache_model(&cache_key, user_id, Duration::from_secs(config.auth_cache_time)); // 设置缓存时间
-                                return service.call(req).await;
-                            }
-                            // info!(target: "access_log", "Model remote auth: accountId: {:?}, isValid: {:?}, user_id{:?}", account_id, is_valid, user_id);
-                        }
-                        // 如果 accountId 为空或 isValid 为 false，返回错误
-                        return Err(ErrorForbidden("Remote validation failed: accountId is empty or isValid is false"));
-                    }
-                    _ => {
-                        return Err(ErrorForbidden("Remote validation failed"));
-                    }
+    // 构造缓存的key
+    let cache_key = format!("{}:{}", api_key, model_name);
+
+    // 检查缓存
+    let cache_result = cache.lock().unwrap().check_cache_model(&cache_key);
+
+    if let Some(user_id) = cache_result {
+        // 缓存命中，返回成功
+        info!(target: "access_log", "Cache hit for user_id: {:?}", user_id);
+        return Ok(user_id);
+    }
+
+    let url = format!("{}/v1/apiInfo/check", config.auth_remote_server);
+    let client = reqwest::Client::new();
+    let response = client.post(&url)
+        .json(&serde_json::json!({
+            "apiKey": api_key,
+            "modelName": model_name,
+            "cloudRegionId": config.cloud_region_id
+        }))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await;
+
+    // info!(target: "access_log", "Model remote auth response: {:?}", response);
+    match response {
+        Ok(resp) if resp.status().is_success() => {
+            if let Ok(json) = resp.json::<serde_json::Value>().await {
+                let account_id = json.get("accountId").and_then(|u| u.as_str()).map(|u| u.to_string());
+                // let user_id = json.get("userId").and_then(|u| u.as_str()).map(|u| u.to_string());
+                let is_valid = json.get("isValid").and_then(|v| v.as_bool());
+
+                if let (Some(user_id), Some(true)) = (account_id.clone(), is_valid) {
+                    // 获取远程校验通过后的用户ID，缓存它
+                    cache.lock().unwrap().set_cache_model(&cache_key, user_id.clone(), Duration::from_secs(config.auth_cache_time)); // 设置缓存时间
+                    return Ok(user_id);
                 }
+                // info!(target: "access_log", "Model remote auth: accountId: {:?}, isValid: {:?}, user_id{:?}", account_id, is_valid, user_id);
             }
-
-            Err(ErrorForbidden("Authentication failed"))
-        })
+            // 如果 accountId 为空或 isValid 为 false，返回错误
+            Err(ErrorForbidden("Remote validation failed: accountId is empty or isValid is false"))
+        }
+        _ => Err(ErrorForbidden("Remote validation failed")),
     }
 }
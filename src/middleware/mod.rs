@@ -2,5 +2,6 @@ pub mod api_key;
 pub mod rate_limit;
 pub mod auth4manage;
 pub mod auth4model;
+pub mod model_list_auth;
 pub mod auth_cache;
 pub mod qos;
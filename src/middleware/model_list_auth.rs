@@ -0,0 +1,119 @@
+use actix_web::{dev::{Service, ServiceRequest, ServiceResponse, Transform}, Error, HttpMessage};
+use actix_web::error::ErrorUnauthorized;
+use futures::future::{join_all, ok, LocalBoxFuture, Ready};
+use std::{sync::{Arc, Mutex}, task::{Context, Poll}};
+
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::cores::control::route_cache::{route_table, CachedUserKeysImpl};
+use crate::meta::middleware::traits::UserKeysTrait;
+use crate::middleware::auth4model::remote_check;
+use crate::middleware::auth_cache::AuthCache;
+
+// Models the caller's api key may use, for `GET /v1/models`.
+// Absent when authentication is disabled, every model may be used then.
+#[derive(Clone, Debug)]
+pub struct ModelGrants(pub Vec<String>);
+
+// Authentication of the `/v1/models` scope. Listing requests carry no model, the key is checked
+// against every served model instead. Shares the remote check cache of `Auth4ModelMiddleware`.
+#[derive(Clone)]
+pub struct ModelListAuthMiddleware {
+    userkeys: Arc<dyn UserKeysTrait>,
+    cache: Arc<Mutex<AuthCache>>,
+}
+
+impl ModelListAuthMiddleware {
+    pub fn new(cache: Arc<Mutex<AuthCache>>) -> Self {
+        Self { userkeys: Arc::new(CachedUserKeysImpl), cache }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ModelListAuthMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = ModelListAuthService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ModelListAuthService {
+            service: Arc::new(service),
+            userkeys: self.userkeys.clone(),
+            cache: self.cache.clone(),
+        })
+    }
+}
+
+pub struct ModelListAuthService<S> {
+    service: Arc<S>,
+    userkeys: Arc<dyn UserKeysTrait>,
+    cache: Arc<Mutex<AuthCache>>,
+}
+
+impl<S, B> Service<ServiceRequest> for ModelListAuthService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let config = &*GLOBAL_CONFIG;
+        let userkeys = self.userkeys.clone();
+        let cache = self.cache.clone();
+        let user_key_header = req.headers()
+            .get("Authorization")
+            .and_then(|hv| hv.to_str().ok())
+            .map(|auth_str| auth_str.replace("Bearer ", ""));
+
+        Box::pin(async move {
+            if !config.auth_local_enabled && !config.auth_remote_enabled {
+                return service.call(req).await;
+            }
+            let api_key = match user_key_header {
+                Some(s) => s,
+                None => return Err(ErrorUnauthorized("Missing api_key header")),
+            };
+            // An unknown key is rejected like on the other endpoints, not answered with an empty list
+            let known_locally = config.auth_local_enabled && matches!(userkeys.check_userkey(&api_key).await, Ok(true));
+            if !known_locally && !config.auth_remote_enabled {
+                return Err(ErrorUnauthorized("Invalid api_key"));
+            }
+            let grants = model_grants(userkeys, cache, &api_key, known_locally).await;
+            if !known_locally && grants.0.is_empty() && !route_table().active_models().is_empty() {
+                return Err(ErrorUnauthorized("Invalid api_key"));
+            }
+            req.extensions_mut().insert(grants);
+            service.call(req).await
+        })
+    }
+}
+
+// Check the key against every model of the route table, locally first when the key is a local one,
+// then the remaining models remotely, all at once
+async fn model_grants(userkeys: Arc<dyn UserKeysTrait>, cache: Arc<Mutex<AuthCache>>, api_key: &str, known_locally: bool) -> ModelGrants {
+    let config = &*GLOBAL_CONFIG;
+    let mut granted = Vec::new();
+    let mut remote = Vec::new();
+    for model in route_table().active_models() {
+        if known_locally && matches!(userkeys.check_userkey_model(api_key, &model).await, Ok(true)) {
+            granted.push(model);
+        } else if config.auth_remote_enabled {
+            remote.push(model);
+        }
+    }
+    let checks = join_all(remote.iter().map(|model| remote_check(&cache, api_key, model))).await;
+    granted.extend(remote.into_iter().zip(checks).filter(|(_, check)| check.is_ok()).map(|(model, _)| model));
+    ModelGrants(granted)
+}
@@ -4,7 +4,8 @@ use actix_web::{
     Error,
     HttpMessage,
 };
-use actix_web::error::ErrorBadRequest;
+use actix_web::error::{ErrorBadRequest, InternalError};
+use actix_web::HttpResponse;
 use futures::future::{ok, LocalBoxFuture, Ready};
 use serde::Deserialize;
 use serde::Serialize;
@@ -17,21 +18,61 @@ use futures_core::Stream;
 use tokio::join;
 use log::error;
 use log::info;
+use chrono::Utc;
 
 use actix_web::error::PayloadError;
 // 引入 BoxedPayloadStream 定义
 pub type BoxedPayloadStream = std::pin::Pin<Box<dyn futures_core::Stream<Item = Result<Bytes, PayloadError>>>>;
 
-use crate::{configs::settings::GLOBAL_CONFIG, cores::control::model_limits::LimitsManager};
+use crate::configs::settings::GLOBAL_CONFIG;
+use crate::cores::control::model_limits::QuotaSubjects;
+use crate::cores::control::quota::{request_quotas, token_quotas};
+use crate::meta::qos::traits::Limits;
+use crate::utils::tokenizer::prepare_tokenizer;
+use crate::cores::chat_models::chat_controller::{ChatCompletionRequest as ChatRequest, TextCompletionRequest};
+use crate::cores::chat_models::chat_utils::{chat_prompt_tokens, text_prompt_tokens};
 use crate::GLOBAL_MULTI_SERVER_CLIENT;
+use crate::cores::control::route_cache::route_table;
+use crate::cores::control::quota::QuotaEngine;
+use crate::cores::control::budgets::check_budgets;
 
 // 假设的 ChatCompletionRequest 结构体
 #[derive(Deserialize)]
-struct ChatCompletionRequest {
-    model: String,
-    // 可以添加其他字段
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub max_tokens: Option<u32>,
+    pub max_completion_tokens: Option<u32>,
 }
 
+impl ChatCompletionRequest {
+    // Tokens the request may use, checked against the token quotas before it is sent:
+    // the prompt, plus the completion it asks for
+    pub fn estimated_tokens(&self, prompt_tokens: u32) -> i64 {
+        let completion = self.max_completion_tokens.or(self.max_tokens).unwrap_or(0);
+        prompt_tokens as i64 + completion as i64
+    }
+}
+
+// The prompt tokens of a chat or text completion body, counted the way the handlers count them.
+// Bodies the handlers reject count nothing.
+pub fn prompt_tokens(model: &str, body: &[u8]) -> u32 {
+    if let Ok(request) = serde_json::from_slice::<ChatRequest>(body) {
+        return chat_prompt_tokens(model, &request);
+    }
+    serde_json::from_slice::<TextCompletionRequest>(body)
+        .map(|request| text_prompt_tokens(model, &request))
+        .unwrap_or(0)
+}
+
+// The prompt tokens of the request, counted once with the tokenizer of the requested catalog model
+#[derive(Clone, Copy, Debug)]
+pub struct PromptTokens(pub u32);
+
+// Tokens the request may use, checked against the token quotas of each model the router tries.
+// Only set when the model quotas are enabled.
+#[derive(Clone, Copy, Debug)]
+pub struct QuotaTokens(pub i64);
+
 // middleware structure
 pub struct Qos {
 }
@@ -110,11 +151,20 @@ where
         let service = self.service.clone(); // Arc 实现了 Clone 特性
 
         let config = &*GLOBAL_CONFIG;
-        let coil_enabled = config.coil_enabled;
+        let quota_enabled = config.quota_enabled();
 
         let fut = async move {
             let (chat_request, body_clone) = read_payload_fut.await?;
-            let model = chat_request.model;
+            // Token limits belong to the catalog model, also when it is requested by an alias
+            let limits_model = route_table().canonical_model(&chat_request.model).to_string();
+
+            // The prompt is counted for the token quotas only, the handlers count it when they are off
+            let prompt = if quota_enabled {
+                prepare_tokenizer(&limits_model).await;
+                prompt_tokens(&limits_model, &body_clone)
+            } else {
+                0
+            };
 
             // 将请求体重新放回 ServiceRequest
             let body_bytes = Bytes::from(body_clone);
@@ -123,32 +173,48 @@ where
             let payload = actix_web::dev::Payload::from(boxed_stream);
             req.set_payload(payload);
 
-            if coil_enabled {
-                let userid = req.extensions().get::<String>().cloned().unwrap_or_else(|| "".to_string());
-              
-                let userid_clone = userid.clone();
-                let model_clone = model.clone();
-                let (valid_tokens, valid) = join!(
-                    throttled(userid_clone, model_clone),
-                    query_and_consume(userid, model)
-                );
-                let valid_tokens = valid_tokens?;
-                let valid = valid?;
-
-                if valid && valid_tokens {
-                    service.call(req).await
-                } else {
-                    Err(actix_web::error::ErrorTooManyRequests("Throttle for request"))
-                }       
-            } else {
-                    service.call(req).await
+            let userid = req.extensions().get::<String>().cloned().unwrap_or_else(|| "".to_string());
+            let subjects = QuotaSubjects::new(req.headers(), &userid);
+
+            // Monthly budgets of the account and the key, whichever quota engine is used
+            let budgets = route_table().budgets(&subjects);
+            if !budgets.is_empty() {
+                match check_budgets(&budgets).await {
+                    Ok(Some(message)) => return Err(budget_exceeded(&message)),
+                    Ok(None) => {}
+                    Err(err) => if !quota_unavailable(err)? {
+                        return Err(budget_exceeded("Your budget could not be checked, please try again later."));
+                    },
+                }
+            }
+
+            // The model quotas are checked and counted by the router, for each model of the fallback chain it tries
+            if quota_enabled {
+                req.extensions_mut().insert(PromptTokens(prompt));
+                req.extensions_mut().insert(QuotaTokens(chat_request.estimated_tokens(prompt)));
             }
+            service.call(req).await
         };
 
         Box::pin(fut)
     }
 }
 
+// Check the request and token quotas of the model for the request, and count the request when both have room.
+// Both are checked before the request is counted, so that models passed over on the way down the fallback chain
+// are not charged for it.
+pub async fn take_model_quota(subjects: &QuotaSubjects, model: &str, estimated: i64) -> Result<bool, Error> {
+    let limits = route_table().model_limits(model, subjects);
+    let (valid_tokens, valid) = join!(
+        throttled(&limits, model, estimated),
+        requests_available(&limits, model)
+    );
+    if !valid_tokens? || !valid? {
+        return Ok(false);
+    }
+    query_and_consume(&limits, model).await
+}
+
 #[derive(Deserialize)]
 struct ResponseData {
     throttled: bool,
@@ -164,79 +230,40 @@ struct RequestBody {
     limit: String,
 }
 
-async fn query_and_consume(apikey: String, model: String) -> Result<bool, Error> {
-    // let client: reqwest::Client = reqwest::Client::new();
-    let (client, base_url) = {
-        let global_client = GLOBAL_MULTI_SERVER_CLIENT.lock().unwrap(); // 直接加锁
-        let (client, base_url) = global_client.get_client_and_base_url(&apikey, &model);
-        // 克隆必要的数据，避免持有 MutexGuard
-        (client.clone(), base_url.to_string())
-    };
-
-    let limit_manager = LimitsManager::default();
-    let limits = limit_manager.get_limits_object(&model)
-        .await
-        .map_err(|e| ErrorBadRequest(format!("Failed to get model limits: {}", e)))?;
-    let limits = match limits {
-        Some(limits) => limits,
-        None => return Err(ErrorBadRequest(format!("Qos: {} is not in model_limits table", model))),
-    };
-
-    // 修改请求路径
-    // let url = format!("http://{}/query_and_consume", ip);
-    let url = format!("{}/query_and_consume", base_url);
-
-    // 构建请求体
-    let request_body = RequestBody {
-        user: apikey,
-        item: model,
-        request_amount: "1".to_string(),
-        limit: limits.max_requests,
-    };
-
-    // 发送 POST 请求并带上请求体
-    let response = match client.post(url)
-       .json(&request_body)
-       .send()
-       .await {
-        Ok(resp) => resp,
-        Err(err) => {
-            // 记录错误日志
-            error!(target: "error_log", "{}", err);
-            return Ok(true);
-        }
-    };
+// Whether the request quotas have room for the request, without counting it
+async fn requests_available(limits: &[(String, Limits)], model: &str) -> Result<bool, Error> {
+    if GLOBAL_CONFIG.quota_engine == "local" {
+        return QuotaEngine::default().requests_available(model, &request_quotas(limits), Utc::now().timestamp())
+            .await
+            .or_else(quota_unavailable);
+    }
 
-    let body_text = match response.text().await {
-        Ok(text) => text,
-        Err(err) => {
-            return Err(actix_web::error::ErrorInternalServerError(format!(
-                "Failed to read response body: {}",
-                err
-            )))
+    for (subject, limits) in limits.iter().filter(|(_, limits)| limits.rpm > 0) {
+        if !coil_throttled(subject.clone(), "", model.to_string(), 1, limits.rpm.to_string()).await? {
+            return Ok(false);
         }
-    };
-
-    if body_text.trim().is_empty() {
-        return Ok(true);
     }
+    Ok(true)
+}
 
-    if body_text.trim() == "{}" {
-        // 空对象表示未限流
-        return Ok(true);
+// Count the request against the request quotas, per minute and per day with the local engine.
+// The coil servers only keep the minute windows. Their subjects were all checked by `requests_available`,
+// so the request is counted for each of them without checking again and a later subject can't fail
+// after an earlier one was charged.
+async fn query_and_consume(limits: &[(String, Limits)], model: &str) -> Result<bool, Error> {
+    if GLOBAL_CONFIG.quota_engine == "local" {
+        return QuotaEngine::default().take_request(model, &request_quotas(limits), Utc::now().timestamp())
+            .await
+            .or_else(quota_unavailable);
     }
 
-    let body = match serde_json::from_str::<ResponseData>(&body_text) {
-        Ok(body) => body,
-        Err(err) => {
-            return Err(actix_web::error::ErrorInternalServerError(format!(
-                "Failed to parse response: {}",
-                err
-            )))
+    for (subject, _) in limits.iter().filter(|(_, limits)| limits.rpm > 0) {
+        let status = coil_consume(subject.clone(), "", model.to_string(), 1).await?;
+        if status != "success" {
+            error!(target: "error_log", "Failed to count the request of {} for {}: {}", subject, model, status);
         }
-    };
-
-    Ok(!body.throttled)
+    }
+    Ok(true)
 }
 
 // 请求消耗的token
@@ -252,7 +279,28 @@ struct ResponseConsumeData {
     status: String,
 }
 
-pub async fn consume(apikey: String, model: String, tokens: u32) -> Result<String, Error> {
+// Add the tokens of a finished request to the token quotas that applied to it.
+// The response has already been served, so tokens the local engine can't count are only logged.
+pub async fn consume(subjects: &QuotaSubjects, model: &str, tokens: u32) -> Result<String, Error> {
+    let limits = route_table().model_limits(model, subjects);
+    if GLOBAL_CONFIG.quota_engine == "local" {
+        if let Err(err) = QuotaEngine::default().consume_tokens(model, &token_quotas(&limits), tokens as i64, Utc::now().timestamp()).await {
+            error!(target: "error_log", "Failed to consume tokens: {}", err);
+        }
+        return Ok("success".to_string());
+    }
+
+    for (subject, _) in limits.iter().filter(|(_, limits)| limits.tpm > 0) {
+        let status = coil_consume(subject.clone(), "tokens", model.to_string(), tokens).await?;
+        if status != "success" {
+            return Ok(status);
+        }
+    }
+    Ok("success".to_string())
+}
+
+// Add `amount` to a coil counter, "" for the request counter and "tokens" for the token counter
+async fn coil_consume(apikey: String, counter: &str, model: String, amount: u32) -> Result<String, Error> {
     // let client: reqwest::Client = reqwest::Client::new();
     let (client, base_url) = {
         let global_client = GLOBAL_MULTI_SERVER_CLIENT.lock().unwrap(); // 直接加锁
@@ -264,13 +312,13 @@ pub async fn consume(apikey: String, model: String, tokens: u32) -> Result<Strin
     let url = format!("{}/consume", base_url);
 
     // 用户和rpm的不一样
-    let tokens_apikey = format!("tokens{}", apikey);
+    let user = format!("{}{}", counter, apikey);
 
     // 构建请求体
     let request_body = RequestConsumeBody {
-        user: tokens_apikey,
+        user,
         item: model,
-        request_amount: tokens.to_string(),
+        request_amount: amount.to_string(),
     };
 
     // 发送 POST 请求并带上请求体
@@ -309,8 +357,26 @@ pub async fn consume(apikey: String, model: String, tokens: u32) -> Result<Strin
     Ok(body.status)
 }
 
-// 测试token是否达到阈值
-pub async fn throttled(apikey: String, model: String) -> Result<bool, Error> {
+// 测试token是否达到阈值: whether the token quotas have room for the estimated tokens of the request
+pub async fn throttled(limits: &[(String, Limits)], model: &str, estimated: i64) -> Result<bool, Error> {
+    if GLOBAL_CONFIG.quota_engine == "local" {
+        return QuotaEngine::default().tokens_available(model, &token_quotas(limits), estimated, Utc::now().timestamp())
+            .await
+            .or_else(quota_unavailable);
+    }
+
+    for (subject, limits) in limits.iter().filter(|(_, limits)| limits.tpm > 0) {
+        let request_amount = estimated.clamp(1, limits.tpm as i64);
+        if !coil_throttled(subject.clone(), "tokens", model.to_string(), request_amount, limits.tpm.to_string()).await? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Whether a coil counter has room for `request_amount`, without consuming it.
+// `counter` is "" for the request counter and "tokens" for the token counter, as in `coil_consume`.
+async fn coil_throttled(apikey: String, counter: &str, model: String, request_amount: i64, limit: String) -> Result<bool, Error> {
     // let client: reqwest::Client = reqwest::Client::new();
     let (client, base_url) = {
         let global_client = GLOBAL_MULTI_SERVER_CLIENT.lock().unwrap(); // 直接加锁
@@ -323,24 +389,15 @@ pub async fn throttled(apikey: String, model: String) -> Result<bool, Error> {
     // let url = format!("http://{}/throttled", ip);
     let url = format!("{}/throttled", base_url);
 
-    let limit_manager = LimitsManager::default();
-    let limits = limit_manager.get_limits_object(&model)
-        .await
-        .map_err(|e| ErrorBadRequest(format!("Failed to get model limits: {}", e)))?;
-    let limits = match limits {
-        Some(limits) => limits,
-        None => return Err(ErrorBadRequest(format!("Qos: {} is not in model_limits table", model))),
-    };
-
     // 用户和rpm的不一样
-    let tokens_apikey = format!("tokens{}", apikey);
+    let user = format!("{}{}", counter, apikey);
 
     // 构建请求体
     let request_body = RequestBody {
-        user: tokens_apikey,
+        user,
         item: model,
-        request_amount: "100".to_string(),
-        limit: limits.max_tokens,
+        request_amount: request_amount.to_string(),
+        limit,
     };
 
     // 发送 POST 请求并带上请求体
@@ -352,7 +409,7 @@ pub async fn throttled(apikey: String, model: String) -> Result<bool, Error> {
          Err(err) => {
             // 记录错误日志
             error!(target: "error_log", "{}", err);
-            return Ok(true);
+            return Ok(!GLOBAL_CONFIG.quota_fail_closed);
         }
      };
  
@@ -389,6 +446,26 @@ pub async fn throttled(apikey: String, model: String) -> Result<bool, Error> {
      Ok(!body.throttled)
 }
 
+// Rejection of a request whose account or key has used up its monthly budget, in the OpenAI format
+fn budget_exceeded(message: &str) -> Error {
+    let response = HttpResponse::TooManyRequests().json(serde_json::json!({
+        "error": {
+            "message": message,
+            "type": "insufficient_quota",
+            "param": null,
+            "code": "insufficient_quota"
+        }
+    }));
+    InternalError::from_response("Budget exceeded", response).into()
+}
+
+// When the limits, budgets or the shared counters of the local engine can't be read, or the coil servers
+// can't be reached, `quota_fail_closed` decides whether the request goes through
+fn quota_unavailable(err: Box<dyn std::error::Error>) -> Result<bool, Error> {
+    error!(target: "error_log", "Quota check failed: {}", err);
+    Ok(!GLOBAL_CONFIG.quota_fail_closed)
+}
+
 use std::hash::{Hash, Hasher};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::collections::BTreeMap;
@@ -1,16 +1,38 @@
-use actix_web::{dev::{Service, ServiceRequest, ServiceResponse, Transform}, Error};
-use leaky_bucket::RateLimiter;
-use std::{task::{Context, Poll}, time::Duration};
-use std::sync::Arc;
+use actix_web::{dev::{Service, ServiceRequest, ServiceResponse, Transform}, Error, HttpMessage, HttpResponse};
+use actix_web::error::{InternalError, PayloadError};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use bytes::{Bytes, BytesMut};
 use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::stream::once;
+use futures::StreamExt;
+use leaky_bucket::RateLimiter;
+use serde::Deserialize;
+use serde_json::json;
+use lru::LruCache;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::{task::{Context, Poll}, time::{Duration, Instant}};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex as StdMutex};
 use tokio::sync::Mutex;
 
 use crate::configs::settings::GLOBAL_CONFIG;
+use crate::cores::control::route_cache::route_table;
+use crate::meta::rate_limits::traits::RateLimit;
+use crate::middleware::qos::BoxedPayloadStream;
+
+// Paths that are never limited: probes and API docs must stay reachable when clients are throttled
+const EXEMPT_PATHS: [&str; 3] = ["/swagger-ui", "/api-docs", "/v1/chat/health"];
+
+// How often buckets that are full again are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
 
 // 定义限流中间件
 #[derive(Clone)]
 pub struct RateLimitMiddleware {
-    limiter: Arc<Mutex<RateLimiter>>, // 使用共享的令牌桶
+    limiter: Arc<Mutex<RateLimiter>>, // 使用共享的令牌桶，限制整个网关的请求速率
+    keyed: Arc<StdMutex<KeyedLimiter>>, // 按 api key、账号、模型和客户端 ip 的令牌桶
+    accounts: Arc<StdMutex<LruCache<String, String>>>,  // api key -> account id set by the auth middleware
 }
 
 impl RateLimitMiddleware {
@@ -25,8 +47,148 @@ impl RateLimitMiddleware {
 
         Self {
             limiter: Arc::new(Mutex::new(limiter)),
+            keyed: Arc::new(StdMutex::new(KeyedLimiter::default())),
+            accounts: Arc::new(StdMutex::new(LruCache::new(NonZeroUsize::new(GLOBAL_CONFIG.auth_cache_capacity).unwrap_or(NonZeroUsize::MIN)))),
+        }
+    }
+}
+
+// The outcome of a keyed check, for the most constrained bucket of the request
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,             // bucket capacity
+    pub remaining: u32,         // requests left after this one
+    pub reset: Duration,        // until the bucket is full again
+    pub retry_after: Duration,  // until the next request is allowed, zero when allowed
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant,   // a full bucket is the same as none
+}
+
+// Token buckets keyed by rule and subject. Every bucket of a request must have a token left,
+// otherwise the request is rejected and none of them is charged.
+#[derive(Default)]
+pub struct KeyedLimiter {
+    buckets: HashMap<String, Bucket>,
+    last_sweep: Option<Instant>,
+}
+
+impl KeyedLimiter {
+    pub fn check(&mut self, limits: &[(RateLimit, String)], now: Instant) -> Option<RateLimitDecision> {
+        self.sweep(now);
+
+        let mut decisions = Vec::with_capacity(limits.len());
+        for (limit, subject) in limits {
+            let capacity = capacity(limit);
+            let rate = limit.requests_per_minute.max(1) as f64 / 60.0;
+            let bucket = self.buckets.entry(bucket_key(limit, subject)).or_insert(Bucket { tokens: capacity, updated: now, full_at: now });
+            bucket.tokens = (bucket.tokens + now.saturating_duration_since(bucket.updated).as_secs_f64() * rate).min(capacity);
+            bucket.updated = now;
+            decisions.push((bucket_key(limit, subject), capacity, rate, bucket.tokens));
+        }
+
+        let allowed = decisions.iter().all(|(_, _, _, tokens)| *tokens >= 1.0);
+        let mut result: Option<RateLimitDecision> = None;
+        for (key, capacity, rate, mut tokens) in decisions {
+            if allowed {
+                tokens -= 1.0;
+                if let Some(bucket) = self.buckets.get_mut(&key) {
+                    bucket.tokens = tokens;
+                    bucket.full_at = now + Duration::from_secs_f64((capacity - tokens).max(0.0) / rate);
+                }
+            }
+            let decision = RateLimitDecision {
+                allowed,
+                limit: capacity as u32,
+                remaining: tokens.max(0.0).floor() as u32,
+                reset: Duration::from_secs_f64((capacity - tokens).max(0.0) / rate),
+                retry_after: if tokens >= 1.0 || allowed { Duration::ZERO } else { Duration::from_secs_f64((1.0 - tokens) / rate) },
+            };
+            let tighter = match &result {
+                Some(current) => (decision.retry_after, current.remaining) > (current.retry_after, decision.remaining),
+                None => true,
+            };
+            if tighter {
+                result = Some(decision);
+            }
+        }
+        result
+    }
+
+    // The number of buckets kept
+    #[cfg(test)]
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    // Drop the buckets idle long enough to be refilled
+    fn sweep(&mut self, now: Instant) {
+        if self.last_sweep.map(|last| now.saturating_duration_since(last) < SWEEP_INTERVAL).unwrap_or(false) {
+            return;
+        }
+        self.last_sweep = Some(now);
+        self.buckets.retain(|_, bucket| bucket.full_at > now);
+    }
+}
+
+fn capacity(limit: &RateLimit) -> f64 {
+    if limit.burst > 0 { limit.burst as f64 } else { limit.requests_per_minute.max(1) as f64 }
+}
+
+fn bucket_key(limit: &RateLimit, subject: &str) -> String {
+    format!("{}:{}", limit.id, subject)
+}
+
+// OpenAI style durations: `20ms`, `1s`, `6m0s`
+pub fn format_reset(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        return format!("{}ms", duration.as_millis());
+    }
+    let secs = duration.as_secs_f64().ceil() as u64;
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m{}s", secs / 60, secs % 60)
+    }
+}
+
+fn set_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    let values = [
+        ("x-ratelimit-limit-requests", decision.limit.to_string()),
+        ("x-ratelimit-remaining-requests", decision.remaining.to_string()),
+        ("x-ratelimit-reset-requests", format_reset(decision.reset)),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
         }
     }
+    if !decision.allowed {
+        let retry_after = decision.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+}
+
+fn rate_limit_exceeded(decision: &RateLimitDecision) -> Error {
+    let mut response = HttpResponse::TooManyRequests().json(json!({
+        "error": {
+            "message": format!("Rate limit reached for requests, please try again in {}.", format_reset(decision.retry_after)),
+            "type": "requests",
+            "param": null,
+            "code": "rate_limit_exceeded"
+        }
+    }));
+    set_rate_limit_headers(response.headers_mut(), decision);
+    InternalError::from_response("Rate limit exceeded", response).into()
+}
+
+#[derive(Deserialize)]
+struct ModelField {
+    model: Option<String>,
 }
 
 impl<S, B> Transform<S, ServiceRequest> for RateLimitMiddleware
@@ -42,15 +204,19 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ok(RateLimitMiddlewareService {
-            service,
+            service: Rc::new(service),
             limiter: self.limiter.clone(),
+            keyed: self.keyed.clone(),
+            accounts: self.accounts.clone(),
         })
     }
 }
 
 pub struct RateLimitMiddlewareService<S> {
-    service: S,
+    service: Rc<S>,
     limiter: Arc<Mutex<RateLimiter>>,
+    keyed: Arc<StdMutex<KeyedLimiter>>,
+    accounts: Arc<StdMutex<LruCache<String, String>>>,
 }
 
 impl<S, B> Service<ServiceRequest> for RateLimitMiddlewareService<S>
@@ -66,25 +232,95 @@ where
         self.service.poll_ready(ctx)
     }
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
         let config = &*GLOBAL_CONFIG;
-        let fut = self.service.call(req);
+        let service = self.service.clone();
         let limiter = self.limiter.clone();
+        let keyed = self.keyed.clone();
+        let accounts = self.accounts.clone();
 
         Box::pin(async move {
-            if !config.rate_limit_enbled {
-                return fut.await;
+            if EXEMPT_PATHS.iter().any(|path| req.path().starts_with(path)) {
+                return service.call(req).await;
+            }
+            if config.rate_limit_enbled {
+                let allowed = {
+                    let limiter = limiter.lock().await;
+                    limiter.try_acquire(1)
+                };
+                if !allowed {
+                    return Err(actix_web::error::ErrorTooManyRequests("Rate limit exceeded"));
+                }
             }
-            let allowed = {
-                let limiter = limiter.lock().await;
-                limiter.try_acquire(1)
-            };
 
-            if allowed {
-                fut.await
-            } else {
-                Err(actix_web::error::ErrorTooManyRequests("Rate limit exceeded"))
+            // The subjects of the request, each limited by its own rule or the "*" rule of its scope
+            let table = route_table();
+            let api_key = req.headers().get("Authorization")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.replace("Bearer ", ""))
+                .unwrap_or_default();
+            let ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+            let account = accounts.lock().unwrap().get(&api_key).cloned().unwrap_or_default();
+            let model = if table.has_rate_limits("model") { read_model(&mut req).await? } else { String::new() };
+
+            let mut limits = Vec::new();
+            for (scope, subject) in [("key", api_key.clone()), ("account", account), ("model", table.canonical_model(&model).to_string()), ("ip", ip)] {
+                if subject.is_empty() {
+                    continue;
+                }
+                if let Some(limit) = table.rate_limit(scope, &subject) {
+                    limits.push((limit.clone(), subject));
+                }
+            }
+            if limits.is_empty() {
+                return service.call(req).await;
+            }
+
+            let decision = keyed.lock().unwrap().check(&limits, Instant::now());
+            match decision {
+                Some(decision) if !decision.allowed => Err(rate_limit_exceeded(&decision)),
+                decision => {
+                    let mut res = service.call(req).await?;
+                    // Later requests of the key also count against the account the auth middleware found
+                    if let Some(account) = res.request().extensions().get::<String>().filter(|account| !account.is_empty()) {
+                        if !api_key.is_empty() {
+                            accounts.lock().unwrap().put(api_key, account.clone());
+                        }
+                    }
+                    if let Some(decision) = decision {
+                        set_rate_limit_headers(res.headers_mut(), &decision);
+                    }
+                    Ok(res)
+                }
             }
         })
     }
 }
+
+// The `model` of a JSON request body; the body is put back for the handlers
+async fn read_model(req: &mut ServiceRequest) -> Result<String, Error> {
+    let is_json = req.headers().get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("application/json"))
+        .unwrap_or(false);
+    if !is_json {
+        return Ok(String::new());
+    }
+
+    let mut payload = req.take_payload();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| {
+            actix_web::error::ErrorInternalServerError(format!("Failed to read payload: {}", e))
+        })?;
+        body.extend_from_slice(&chunk);
+    }
+    let model = serde_json::from_slice::<ModelField>(&body).ok().and_then(|field| field.model).unwrap_or_default();
+
+    // 将请求体重新放回 ServiceRequest
+    let body_bytes = Bytes::from(body);
+    let stream = once(async { Ok::<_, PayloadError>(body_bytes) });
+    let boxed_stream: BoxedPayloadStream = Box::pin(stream);
+    req.set_payload(actix_web::dev::Payload::from(boxed_stream));
+    Ok(model)
+}
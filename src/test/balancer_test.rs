@@ -0,0 +1,53 @@
+#[cfg(test)]
+pub mod tests {
+    use crate::configs::settings::Config;
+    use crate::cores::control::balancer::{begin_request, order_with_strategy, pick_weighted, Strategy};
+    use crate::meta::services::traits::ServiceConfig;
+
+    fn service(id: &str, weight: i32) -> ServiceConfig {
+        ServiceConfig {
+            id: id.to_string(),
+            servicetype: "vllm".to_string(),
+            status: "active".to_string(),
+            url: format!("http://{}:8000/v1/chat/completions", id),
+            model_name: "Qwen/Qwen2.5-7B-Instruct".to_string(),
+            active_model: "Qwen2.5-7B-Instruct".to_string(),
+            weight,
+            allowed_params: vec![],
+            denied_params: vec![],
+            api_key: String::new(),
+            probe_path: String::new(),
+            models: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!(Strategy::parse("least_requests"), Ok(Strategy::LeastRequests));
+        assert_eq!(Strategy::parse("random"), Ok(Strategy::Random));
+        assert!(Strategy::parse("round_robin").is_err());
+
+        let mut config = Config::default();
+        assert!(config.check().is_ok());
+        config.load_balance_models.insert("qwen".to_string(), "latancy".to_string());
+        assert!(config.check().unwrap_err().contains("load_balance_models of qwen"));
+    }
+
+    #[test]
+    fn test_weighted_round_robin_distribution() {
+        let services = vec![service("wrr-a", 3), service("wrr-b", 1), service("wrr-c", 0)];
+        let picks: Vec<&str> = (0..8).map(|_| services[pick_weighted("wrr-test", &services)].id.as_str()).collect();
+        // Smooth: the heavy replica never gets more than its share in a row, a zero weight gets nothing
+        assert_eq!(picks, vec!["wrr-a", "wrr-a", "wrr-b", "wrr-a", "wrr-a", "wrr-a", "wrr-b", "wrr-a"]);
+    }
+
+    #[test]
+    fn test_least_requests_ordering() {
+        let services = vec![service("lr-a", 1), service("lr-b", 1), service("lr-c", 1)];
+        let _a = [begin_request("lr-a"), begin_request("lr-a")];
+        let _c = begin_request("lr-c");
+        let ordered = order_with_strategy("lr-test", services, Strategy::LeastRequests);
+        let ids: Vec<&str> = ordered.iter().map(|service| service.id.as_str()).collect();
+        assert_eq!(ids, vec!["lr-b", "lr-c", "lr-a"]);
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+pub mod tests {
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+
+    use crate::cores::control::budgets::{matching_budgets, month_start, BudgetTracker};
+    use crate::cores::control::model_limits::QuotaSubjects;
+    use crate::meta::budgets::traits::Budget;
+    use crate::meta::models::traits::Model;
+    use crate::meta::usage::traits::{UsageAggregate, UsageQuery, UsageRecord, UsageRecordsTrait};
+
+    // 2024-03-15 12:00:00 UTC
+    const NOW: i64 = 1710504000;
+
+    // Usage records holding a fixed spend per "column:subject", counting the lookups
+    #[derive(Clone, Default)]
+    struct FakeRecords {
+        spend: Arc<Mutex<HashMap<String, f64>>>,
+        lookups: Arc<Mutex<Vec<(String, i64)>>>,
+    }
+
+    #[async_trait]
+    impl UsageRecordsTrait for FakeRecords {
+        async fn add_usage_record(&self, _record: &UsageRecord) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn aggregate_usage(&self, _query: &UsageQuery) -> Result<Vec<UsageAggregate>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_spend(&self, column: &str, subject: &str, since: i64) -> Result<f64, Box<dyn Error>> {
+            let key = format!("{}:{}", column, subject);
+            self.lookups.lock().unwrap().push((key.clone(), since));
+            Ok(self.spend.lock().unwrap().get(&key).copied().unwrap_or(0.0))
+        }
+    }
+
+    fn budget(id: &str, scope: &str, subject: &str, monthly_limit: f64, soft_limit: f64) -> Budget {
+        Budget { id: id.to_string(), scope: scope.to_string(), subject: subject.to_string(), monthly_limit, soft_limit }
+    }
+
+    #[test]
+    fn test_model_cost() {
+        let mut model = Model::new("qwen", "owner", 0);
+        model.prompt_price = 2.0;
+        model.completion_price = 8.0;
+        // 1000 prompt tokens at 2.0 and 500 completion tokens at 8.0 per 1M tokens
+        assert!((model.cost(1000, 0, 500) - 0.006).abs() < 1e-12);
+        // cached tokens at the prompt price until a cached price is set
+        assert!((model.cost(1000, 400, 500) - 0.006).abs() < 1e-12);
+        model.cached_price = 0.5;
+        assert!((model.cost(1000, 400, 500) - 0.0054).abs() < 1e-12);
+        // no more cached tokens than prompt tokens
+        assert!((model.cost(100, 400, 0) - 0.00005).abs() < 1e-12);
+        assert_eq!(Model::new("free", "owner", 0).cost(1000, 0, 1000), 0.0);
+    }
+
+    #[test]
+    fn test_matching_budgets() {
+        let budgets = vec![
+            budget("accounts", "account", "*", 100.0, 80.0),
+            budget("vip", "account", "111111", 1000.0, 0.0),
+            budget("key", "key", "sk-1", 10.0, 5.0),
+        ];
+        let subjects = |key: &str, account: &str| QuotaSubjects { key: key.to_string(), account: account.to_string(), ..Default::default() };
+
+        let matched = matching_budgets(&budgets, &subjects("sk-1", "111111"));
+        let ids: Vec<(&str, &str)> = matched.iter().map(|(subject, budget)| (subject.as_str(), budget.id.as_str())).collect();
+        assert_eq!(ids, vec![("account:111111", "vip"), ("key:sk-1", "key")]);
+
+        let matched = matching_budgets(&budgets, &subjects("sk-2", "222222"));
+        let ids: Vec<(&str, &str)> = matched.iter().map(|(subject, budget)| (subject.as_str(), budget.id.as_str())).collect();
+        assert_eq!(ids, vec![("account:222222", "accounts")]);
+
+        assert!(matching_budgets(&budgets, &QuotaSubjects::default()).is_empty());
+    }
+
+    #[test]
+    fn test_month_start() {
+        // 2024-03-01 00:00:00 at UTC+8 is 2024-02-29 16:00:00 UTC
+        assert_eq!(month_start(NOW, chrono_tz::Asia::Shanghai), 1709222400);
+        assert_eq!(month_start(NOW, chrono_tz::UTC), 1709251200);
+        // 2024-02-29 17:00:00 UTC is already March in Shanghai
+        assert_eq!(month_start(1709226000, chrono_tz::Asia::Shanghai), 1709222400);
+        assert_eq!(month_start(1709226000, chrono_tz::UTC), 1706745600);
+    }
+
+    #[actix_web::test]
+    async fn test_tracker_rejects_spent_budgets() {
+        let records = FakeRecords::default();
+        records.spend.lock().unwrap().insert("account_id:111111".to_string(), 99.5);
+        let tracker = BudgetTracker::new(Box::new(records.clone()), Some(60), chrono_tz::UTC);
+        let budgets = vec![
+            ("account:111111".to_string(), budget("accounts", "account", "*", 100.0, 0.0)),
+            ("key:sk-1".to_string(), budget("keys", "key", "*", 0.0, 0.0)),
+        ];
+
+        assert!(tracker.exceeded(&budgets, NOW).await.unwrap().is_none());
+        // budgets without a monthly limit are not looked up
+        assert_eq!(*records.lookups.lock().unwrap(), vec![("account_id:111111".to_string(), 1709251200)]);
+
+        tracker.add_spend(&budgets, 0.5, NOW + 1).await.unwrap();
+        let (subject, exceeded, spent) = tracker.exceeded(&budgets, NOW + 2).await.unwrap().unwrap();
+        assert_eq!((subject.as_str(), exceeded.id.as_str(), spent), ("account:111111", "accounts", 100.0));
+        assert_eq!(records.lookups.lock().unwrap().len(), 1);
+
+        // loaded again after the refresh interval, picking up the spend of other instances
+        records.spend.lock().unwrap().insert("account_id:111111".to_string(), 20.0);
+        assert!(tracker.exceeded(&budgets, NOW + 60).await.unwrap().is_none());
+        assert_eq!(records.lookups.lock().unwrap().len(), 2);
+
+        // a new month starts from the records again
+        records.spend.lock().unwrap().insert("account_id:111111".to_string(), 0.0);
+        assert_eq!(tracker.spent("account:111111", 1711929600).await.unwrap(), 0.0);
+        assert_eq!(records.lookups.lock().unwrap().last().unwrap().1, 1711929600);
+    }
+
+    #[actix_web::test]
+    async fn test_tracker_soft_limit_crossed_once() {
+        let tracker = BudgetTracker::new(Box::new(FakeRecords::default()), None, chrono_tz::UTC);
+        let budgets = vec![("key:sk-1".to_string(), budget("keys", "key", "*", 10.0, 5.0))];
+
+        assert!(tracker.add_spend(&budgets, 3.0, NOW).await.unwrap().is_empty());
+        let crossed = tracker.add_spend(&budgets, 3.0, NOW).await.unwrap();
+        assert_eq!(crossed.len(), 1);
+        assert_eq!((crossed[0].0.as_str(), crossed[0].2), ("key:sk-1", 6.0));
+        assert!(tracker.add_spend(&budgets, 3.0, NOW).await.unwrap().is_empty());
+        // counted in memory only without usage records
+        assert_eq!(tracker.spent("key:sk-1", NOW + 3600).await.unwrap(), 9.0);
+        assert!(tracker.exceeded(&budgets, NOW).await.unwrap().is_none());
+        tracker.add_spend(&budgets, 1.0, NOW).await.unwrap();
+        assert!(tracker.exceeded(&budgets, NOW).await.unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_tracker_soft_limit_from_records() {
+        let records = FakeRecords::default();
+        records.spend.lock().unwrap().insert("api_key:sk-1".to_string(), 4.0);
+        let tracker = BudgetTracker::new(Box::new(records.clone()), Some(60), chrono_tz::UTC);
+        // a budget with only a soft limit
+        let budgets = vec![("key:sk-1".to_string(), budget("keys", "key", "*", 0.0, 5.0))];
+
+        // the spend of earlier requests is loaded before the soft limit is checked
+        let crossed = tracker.add_spend(&budgets, 1.5, NOW).await.unwrap();
+        assert_eq!(crossed.len(), 1);
+        assert_eq!((crossed[0].0.as_str(), crossed[0].2), ("key:sk-1", 5.5));
+        assert_eq!(*records.lookups.lock().unwrap(), vec![("api_key:sk-1".to_string(), 1709251200)]);
+        assert!(tracker.add_spend(&budgets, 1.0, NOW + 1).await.unwrap().is_empty());
+        assert_eq!(records.lookups.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,291 @@
+#[cfg(test)]
+pub mod tests {
+    use serde_json::json;
+
+    use crate::configs::settings::MultimodalLimit;
+    use crate::cores::chat_models::chat_controller::{ChatCompletionRequest, ContentPart, MessageContent};
+    use crate::cores::chat_models::chat_utils::{chat_prompt_tokens, check_multimodal_limits, filter_request_params, get_request_body, stream_chunks, text_prompt_tokens};
+    use crate::cores::chat_models::chat_controller::{CompletionsResponse, CompletionsStreamResponse, TextCompletionRequest, TextCompletionsResponse};
+    use crate::utils::tokenizer::count_tokens;
+    use crate::cores::chat_models::support_models::openai::text_completions_url;
+    use crate::meta::services::traits::ServiceConfig;
+
+    fn qwen_vl_request(images: &[&str]) -> ChatCompletionRequest {
+        let mut content = vec![json!({"type": "text", "text": "这两张图有什么不同？"})];
+        for url in images {
+            content.push(json!({"type": "image_url", "image_url": {"url": url}}));
+        }
+        serde_json::from_value(json!({
+            "model": "Qwen2-VL-7B-Instruct",
+            "messages": [
+                {"role": "system", "content": "You are a helpful assistant."},
+                {"role": "user", "content": content}
+            ]
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_content_string_or_parts() {
+        let request = qwen_vl_request(&["https://example.com/a.png"]);
+        assert!(matches!(request.messages[0].content, Some(MessageContent::Text(_))));
+        match &request.messages[1].content {
+            Some(MessageContent::Parts(parts)) => {
+                assert_eq!(parts.len(), 2);
+                assert!(matches!(&parts[1], ContentPart::ImageUrl { image_url } if image_url.url == "https://example.com/a.png"));
+            }
+            other => panic!("unexpected content: {:?}", other),
+        }
+        assert_eq!(request.messages[1].text(), "这两张图有什么不同？");
+
+        // Parts are forwarded unchanged
+        let forwarded = serde_json::to_value(&request.messages[1]).unwrap();
+        assert_eq!(forwarded["content"][1], json!({"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}));
+    }
+
+    #[test]
+    fn test_input_audio_part() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "Qwen2-Audio-7B-Instruct",
+            "messages": [{"role": "user", "content": [{"type": "input_audio", "input_audio": {"data": "UklGRg==", "format": "wav"}}]}]
+        })).unwrap();
+        let forwarded = serde_json::to_value(&request.messages[0]).unwrap();
+        assert_eq!(forwarded["content"][0]["input_audio"]["format"], "wav");
+    }
+
+    #[test]
+    fn test_other_parts_forwarded_unchanged() {
+        let content = json!([
+            {"type": "video", "video": ["https://example.com/1.jpg", "https://example.com/2.jpg"]},
+            {"type": "video_url", "video_url": {"url": "https://example.com/a.mp4"}},
+            {"type": "image_url", "image_url": {"url": "https://example.com/a.png", "min_pixels": 3136, "max_pixels": 12845056}},
+            {"type": "text", "text": "描述这段视频"}
+        ]);
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "Qwen2-VL-7B-Instruct",
+            "messages": [{"role": "user", "content": content}]
+        })).unwrap();
+        match &request.messages[0].content {
+            Some(MessageContent::Parts(parts)) => assert!(matches!(&parts[0], ContentPart::Other(_))),
+            other => panic!("unexpected content: {:?}", other),
+        }
+        assert_eq!(request.messages[0].text(), "描述这段视频");
+        let forwarded = serde_json::to_value(&request.messages[0]).unwrap();
+        assert_eq!(forwarded["content"], content);
+    }
+
+    #[test]
+    fn test_multimodal_image_count_limit() {
+        let limit = MultimodalLimit { max_images: Some(1), max_image_bytes: None };
+        assert!(check_multimodal_limits(&qwen_vl_request(&["https://example.com/a.png"]), &limit).is_ok());
+        let request = qwen_vl_request(&["https://example.com/a.png", "https://example.com/b.png"]);
+        assert!(check_multimodal_limits(&request, &limit).is_err());
+    }
+
+    #[test]
+    fn test_multimodal_image_size_limit() {
+        // 12 base64 characters with one padding byte decode to 8 bytes
+        let limit = MultimodalLimit { max_images: None, max_image_bytes: Some(8) };
+        assert!(check_multimodal_limits(&qwen_vl_request(&["data:image/png;base64,iVBORw0KGgo="]), &limit).is_ok());
+        assert!(check_multimodal_limits(&qwen_vl_request(&["data:image/png;base64,iVBORw0KGgoAAAA"]), &limit).is_err());
+        // The size of remote images is unknown to the gateway
+        assert!(check_multimodal_limits(&qwen_vl_request(&["https://example.com/large.png"]), &limit).is_ok());
+    }
+
+    fn service(allowed_params: &[&str], denied_params: &[&str]) -> ServiceConfig {
+        ServiceConfig {
+            id: "test01".to_string(),
+            servicetype: "vllm".to_string(),
+            status: "active".to_string(),
+            url: "http://127.0.0.1:8000/v1/chat/completions".to_string(),
+            model_name: "Qwen/Qwen2.5-7B-Instruct".to_string(),
+            active_model: "Qwen2.5-7B-Instruct".to_string(),
+            weight: 1,
+            allowed_params: allowed_params.iter().map(|param| param.to_string()).collect(),
+            denied_params: denied_params.iter().map(|param| param.to_string()).collect(),
+            api_key: String::new(),
+            probe_path: String::new(),
+            models: vec![],
+        }
+    }
+
+    #[test]
+    fn test_unknown_params_forwarded() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "Qwen2.5-7B-Instruct",
+            "messages": [{"role": "user", "content": "hi"}],
+            "top_p": 0.8,
+            "presence_penalty": 0.5,
+            "logit_bias": {"50256": -100},
+            "seed": 42,
+            "logprobs": true,
+            "top_logprobs": 2,
+            "repetition_penalty": 1.05,
+            "extra_body": {"guided_json": {"type": "object"}}
+        })).unwrap();
+
+        let (body, is_stream) = get_request_body("Qwen/Qwen2.5-7B-Instruct".to_string(), &request);
+        assert!(!is_stream);
+        assert_eq!(body["model"], "Qwen/Qwen2.5-7B-Instruct");
+        assert_eq!(body["top_p"], json!(0.8f32));
+        assert_eq!(body["logit_bias"]["50256"], json!(-100.0));
+        assert_eq!(body["seed"], 42);
+        assert_eq!(body["top_logprobs"], 2);
+        assert_eq!(body["repetition_penalty"], 1.05);
+        assert_eq!(body["guided_json"], json!({"type": "object"}));
+        assert!(body.get("extra_body").is_none());
+        // Options the client did not send are not sent upstream either
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[test]
+    fn test_service_param_lists() {
+        let body = json!({"model": "m", "messages": [], "stream": true, "seed": 1, "guided_json": {}, "top_p": 0.5});
+
+        let mut denied = body.clone();
+        filter_request_params(&mut denied, &service(&[], &["guided_json"]));
+        assert!(denied.get("guided_json").is_none());
+        assert_eq!(denied["seed"], 1);
+
+        let mut allowed = body.clone();
+        filter_request_params(&mut allowed, &service(&["top_p"], &[]));
+        assert_eq!(allowed, json!({"model": "m", "messages": [], "stream": true, "top_p": 0.5}));
+    }
+
+    #[test]
+    fn test_text_completions_url() {
+        assert_eq!(text_completions_url("http://127.0.0.1:8000/v1/chat/completions"), "http://127.0.0.1:8000/v1/completions");
+        assert_eq!(text_completions_url("http://127.0.0.1:1025/v1/chat/completions/"), "http://127.0.0.1:1025/v1/completions");
+        assert_eq!(text_completions_url("http://127.0.0.1:8000/v1/completions"), "http://127.0.0.1:8000/v1/completions");
+    }
+
+    #[test]
+    fn test_text_completions_chunks() {
+        // vLLM `/v1/completions` stream chunks
+        let chunk: TextCompletionsResponse = serde_json::from_value(json!({
+            "id": "cmpl-3a1f", "object": "text_completion", "created": 1735000300, "model": "Qwen/Qwen2.5-Coder-7B",
+            "choices": [{"index": 0, "text": "def", "logprobs": null, "finish_reason": null, "stop_reason": null}],
+            "usage": null
+        })).unwrap();
+        assert_eq!(chunk.choices[0].text, "def");
+        assert!(chunk.usage.is_none());
+
+        let usage_chunk: TextCompletionsResponse = serde_json::from_value(json!({
+            "id": "cmpl-3a1f", "object": "text_completion", "created": 1735000300, "model": "Qwen/Qwen2.5-Coder-7B",
+            "choices": [], "usage": {"prompt_tokens": 5, "total_tokens": 21, "completion_tokens": 16}
+        })).unwrap();
+        assert_eq!(usage_chunk.usage.unwrap().completion_tokens, 16);
+    }
+
+    #[test]
+    fn test_stream_chunks_with_continuous_usage() {
+        // vLLM with `continuous_usage_stats`: every chunk carries the usage so far
+        let chunk: CompletionsStreamResponse = serde_json::from_value(json!({
+            "id": "chat-7c2e", "object": "chat.completion.chunk", "created": 1735000400, "model": "Qwen/Qwen2.5-7B-Instruct",
+            "choices": [{"index": 0, "delta": {"content": "你好"}, "logprobs": null, "finish_reason": null}],
+            "usage": {"prompt_tokens": 20, "total_tokens": 21, "completion_tokens": 1}
+        })).unwrap();
+        let forwarded = stream_chunks(&chunk, "qwen", false);
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0]["model"], "qwen");
+        assert_eq!(forwarded[0]["choices"][0]["delta"]["content"], "你好");
+        assert!(forwarded[0].get("usage").is_none());
+        let forwarded = stream_chunks(&chunk, "qwen", true);
+        assert_eq!(forwarded[0]["usage"]["total_tokens"], 21);
+
+        let usage_chunk: CompletionsStreamResponse = serde_json::from_value(json!({
+            "id": "chat-7c2e", "object": "chat.completion.chunk", "created": 1735000400, "model": "Qwen/Qwen2.5-7B-Instruct",
+            "choices": [], "usage": {"prompt_tokens": 20, "total_tokens": 23, "completion_tokens": 3}
+        })).unwrap();
+        assert!(stream_chunks(&usage_chunk, "qwen", false).is_empty());
+        let forwarded = stream_chunks(&usage_chunk, "qwen", true);
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0]["choices"], json!([]));
+        assert_eq!(forwarded[0]["usage"]["completion_tokens"], 3);
+    }
+
+    #[test]
+    fn test_stream_chunks_forward_every_delta_field() {
+        // Legacy function calling and DeepSeek-R1 reasoning
+        let chunk: CompletionsStreamResponse = serde_json::from_value(json!({
+            "id": "chat-9d1a", "object": "chat.completion.chunk", "created": 1735000500, "model": "deepseek-reasoner",
+            "choices": [
+                {"index": 0, "delta": {"function_call": {"arguments": "{\"city\": "}}, "finish_reason": null},
+                {"index": 1, "delta": {"role": "assistant", "content": null, "reasoning_content": "先想想"}, "finish_reason": null},
+                {"index": 2, "delta": {"refusal": "I can't help with that."}, "finish_reason": "stop"}
+            ]
+        })).unwrap();
+        let forwarded = stream_chunks(&chunk, "deepseek-r1", false);
+        let choices = &forwarded[0]["choices"];
+        assert_eq!(choices[0]["delta"]["function_call"]["arguments"], "{\"city\": ");
+        assert_eq!(choices[1]["delta"]["role"], "assistant");
+        assert_eq!(choices[1]["delta"]["reasoning_content"], "先想想");
+        assert_eq!(choices[2]["delta"]["refusal"], "I can't help with that.");
+        assert_eq!(choices[2]["delta"]["content"], "");
+        assert_eq!(choices[2]["finish_reason"], "stop");
+    }
+
+    #[test]
+    fn test_streams_always_ask_for_usage() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "m", "messages": [{"role": "user", "content": "hi"}], "stream": true
+        })).unwrap();
+        let (body, is_stream) = get_request_body("m".to_string(), &request);
+        assert!(is_stream);
+        assert_eq!(body["stream_options"], json!({"include_usage": true}));
+
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "m", "messages": [], "stream": true, "stream_options": {"include_usage": false}
+        })).unwrap();
+        assert_eq!(get_request_body("m".to_string(), &request).0["stream_options"]["include_usage"], true);
+
+        let request: ChatCompletionRequest = serde_json::from_value(json!({"model": "m", "messages": []})).unwrap();
+        assert!(get_request_body("m".to_string(), &request).0.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_stream_options_forwarded() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "m", "messages": [], "stream": true, "stream_options": {"continuous_usage_stats": true}
+        })).unwrap();
+        let options = request.stream_options.as_ref().unwrap();
+        assert!(!options.include_usage);
+        let (body, _) = get_request_body("m".to_string(), &request);
+        assert_eq!(body["stream_options"], json!({"include_usage": true, "continuous_usage_stats": true}));
+    }
+
+    #[test]
+    fn test_logprobs_responses() {
+        // vLLM answers `logprobs: true, top_logprobs: 1` with logprobs objects
+        let logprobs = json!({"content": [{"token": "你好", "logprob": -0.01, "bytes": [228, 189, 160, 229, 165, 189],
+                                           "top_logprobs": [{"token": "你好", "logprob": -0.01, "bytes": [228, 189, 160, 229, 165, 189]}]}]});
+        let response: CompletionsResponse = serde_json::from_value(json!({
+            "id": "chat-7c1e", "object": "chat.completion", "created": 1735000400, "model": "Qwen/Qwen2.5-7B-Instruct",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "你好", "tool_calls": []},
+                         "logprobs": logprobs, "finish_reason": "stop", "stop_reason": null}],
+            "usage": {"prompt_tokens": 9, "total_tokens": 10, "completion_tokens": 1, "prompt_tokens_details": null},
+            "prompt_logprobs": [null, {"8948": {"logprob": -1.2, "rank": 1, "decoded_token": "system"}}]
+        })).unwrap();
+        assert_eq!(response.choices[0].logprobs.as_ref(), Some(&logprobs));
+        assert_eq!(response.prompt_logprobs.unwrap()[1]["8948"]["rank"], 1);
+
+        let chunk: CompletionsStreamResponse = serde_json::from_value(json!({
+            "id": "chat-7c1e", "object": "chat.completion.chunk", "created": 1735000400, "model": "Qwen/Qwen2.5-7B-Instruct",
+            "choices": [{"index": 0, "delta": {"content": "你好"}, "logprobs": logprobs, "finish_reason": null}]
+        })).unwrap();
+        assert_eq!(chunk.choices[0].logprobs.as_ref().unwrap()["content"][0]["token"], "你好");
+    }
+
+    #[test]
+    fn test_prompt_tokens_without_usage() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("hello world"), 4);
+        assert_eq!(count_tokens("你好，世界"), 5);
+        assert_eq!(count_tokens("a, b."), 4);
+
+        let request = qwen_vl_request(&["https://example.com/a.png"]);
+        assert_eq!(chat_prompt_tokens("Qwen2-VL-7B-Instruct", &request), count_tokens("You are a helpful assistant.") + count_tokens("这两张图有什么不同？"));
+
+        let request: TextCompletionRequest = serde_json::from_value(json!({"model": "m", "prompt": ["def add", [1, 2, 3]]})).unwrap();
+        assert_eq!(text_prompt_tokens("m", &request), count_tokens("def add") + 3);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,106 @@
+#[cfg(test)]
+pub mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::cores::control::health::{probe_url, BreakerState, ServiceHealth};
+    use crate::meta::services::traits::ServiceConfig;
+
+    const OPEN_FOR: Duration = Duration::from_secs(30);
+
+    fn service(url: &str, probe_path: &str) -> ServiceConfig {
+        ServiceConfig {
+            id: "probe01".to_string(),
+            servicetype: "bailian".to_string(),
+            status: "active".to_string(),
+            url: url.to_string(),
+            model_name: "qwen-plus".to_string(),
+            active_model: "qwen-plus".to_string(),
+            weight: 1,
+            allowed_params: vec![],
+            denied_params: vec![],
+            api_key: "sk-upstream".to_string(),
+            probe_path: probe_path.to_string(),
+            models: vec![],
+        }
+    }
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let mut health = ServiceHealth::default();
+        let now = Instant::now();
+        assert!(!health.record_failure("502".to_string(), 3, now));
+        assert!(!health.record_failure("502".to_string(), 3, now));
+        assert!(health.record_failure("502".to_string(), 3, now));
+        assert_eq!(health.state, BreakerState::Open);
+        assert!(!health.admit(now + Duration::from_secs(29), OPEN_FOR));
+    }
+
+    #[test]
+    fn test_half_open_lets_one_trial_through() {
+        let mut health = ServiceHealth::default();
+        let now = Instant::now();
+        for _ in 0..3 {
+            health.record_failure("502".to_string(), 3, now);
+        }
+
+        let cooled_down = now + OPEN_FOR;
+        // Filtering the candidates doesn't take the trial
+        assert!(health.available(cooled_down, OPEN_FOR));
+        assert!(health.available(cooled_down, OPEN_FOR));
+        assert!(health.admit(cooled_down, OPEN_FOR));
+        assert_eq!(health.state, BreakerState::HalfOpen);
+        // The other requests go to the remaining services while the trial is in flight
+        assert!(!health.available(cooled_down, OPEN_FOR));
+        assert!(!health.admit(cooled_down, OPEN_FOR));
+        assert!(!health.admit(cooled_down + Duration::from_secs(1), OPEN_FOR));
+
+        // A failed trial opens the breaker again
+        assert!(health.record_failure("502".to_string(), 3, cooled_down + Duration::from_secs(1)));
+        assert!(!health.admit(cooled_down + Duration::from_secs(2), OPEN_FOR));
+
+        // A successful trial closes it for everyone
+        let cooled_down = cooled_down + Duration::from_secs(1) + OPEN_FOR;
+        assert!(health.admit(cooled_down, OPEN_FOR));
+        assert!(health.record_success());
+        assert!(health.admit(cooled_down, OPEN_FOR));
+        assert!(health.admit(cooled_down, OPEN_FOR));
+    }
+
+    #[test]
+    fn test_abandoned_trial_is_retried() {
+        let mut health = ServiceHealth::default();
+        health.state = BreakerState::HalfOpen;
+        let now = Instant::now();
+        assert!(health.admit(now, OPEN_FOR));
+        assert!(!health.admit(now + Duration::from_secs(29), OPEN_FOR));
+        // The trial request was never sent or its outcome never recorded
+        assert!(health.admit(now + OPEN_FOR, OPEN_FOR));
+    }
+
+    #[test]
+    fn test_probe_url() {
+        let url = |service: ServiceConfig| probe_url(&service).unwrap().map(|url| url.to_string());
+        let bailian = "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions";
+        assert_eq!(url(service(bailian, "")).as_deref(), Some("https://dashscope.aliyuncs.com/compatible-mode/v1/models"));
+        assert_eq!(url(service("https://api.deepseek.com/chat/completions", "")).as_deref(), Some("https://api.deepseek.com/models"));
+        assert_eq!(url(service("http://10.0.0.1:8000/v1/chat/completions", "/health")).as_deref(), Some("http://10.0.0.1:8000/health"));
+        assert_eq!(url(service(bailian, "none")), None);
+    }
+
+    #[test]
+    fn test_probe_success_keeps_request_failures() {
+        let mut health = ServiceHealth::default();
+        let now = Instant::now();
+        health.record_failure("502".to_string(), 3, now);
+        health.record_failure("502".to_string(), 3, now);
+        assert!(!health.record_probe_success());
+        assert_eq!(health.consecutive_failures, 2);
+        assert!(health.record_failure("502".to_string(), 3, now));
+
+        // An open breaker lets a trial request decide
+        assert!(health.record_probe_success());
+        assert_eq!(health.state, BreakerState::HalfOpen);
+        assert!(health.admit(now, OPEN_FOR));
+        assert!(!health.admit(now, OPEN_FOR));
+    }
+}
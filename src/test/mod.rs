@@ -1 +1,12 @@
-pub mod servers_test;
\ No newline at end of file
+pub mod servers_test;
+pub mod sse_test;
+pub mod chat_test;
+pub mod traffic_split_test;
+pub mod rate_limit_test;
+pub mod quota_test;
+pub mod tokenizer_test;
+pub mod usage_sink_test;
+pub mod usage_records_test;
+pub mod budgets_test;
+pub mod health_test;
+pub mod balancer_test;
\ No newline at end of file
@@ -0,0 +1,169 @@
+#[cfg(test)]
+pub mod tests {
+    use crate::cores::control::model_limits::{day_quotas_unenforced, legacy_limits, matching_limits, QuotaSubjects};
+    use crate::cores::control::quota::{parse_quotas, request_quotas, token_quotas, Quota, QuotaEngine};
+    use crate::meta::qos::traits::Limits;
+    use crate::middleware::qos::{prompt_tokens, ChatCompletionRequest};
+    use crate::utils::tokenizer::count_tokens;
+
+    // 2024-01-01 00:00:00 UTC
+    const NOW: i64 = 1704067200;
+
+    fn limits(id: &str, scope: &str, subject: &str, rpm: i32, tpm: i32, rpd: i32, tpd: i32) -> Limits {
+        Limits {
+            id: id.to_string(),
+            scope: scope.to_string(),
+            subject: subject.to_string(),
+            model_name: "m".to_string(),
+            rpm,
+            tpm,
+            rpd,
+            tpd,
+        }
+    }
+
+    fn for_key(key: &str, limits: Limits) -> Vec<(String, Limits)> {
+        vec![(format!("key:{}", key), limits)]
+    }
+
+    #[test]
+    fn test_parse_quotas() {
+        assert_eq!(parse_quotas("100").unwrap(), vec![Quota { amount: 100, secs: 60 }]);
+        assert_eq!(
+            parse_quotas("60/min, 10000/day").unwrap(),
+            vec![Quota { amount: 60, secs: 60 }, Quota { amount: 10000, secs: 86400 }]
+        );
+        assert_eq!(parse_quotas("5000/hour").unwrap(), vec![Quota { amount: 5000, secs: 3600 }]);
+        assert!(parse_quotas("").unwrap().is_empty());
+        assert!(parse_quotas("many").is_err());
+        assert!(parse_quotas("10/week").is_err());
+    }
+
+    #[test]
+    fn test_legacy_limits() {
+        let converted = legacy_limits("m", "60/min,1000/day", "100000");
+        assert_eq!((converted.scope.as_str(), converted.subject.as_str()), ("account", "*"));
+        assert_eq!((converted.rpm, converted.tpm, converted.rpd, converted.tpd), (60, 100000, 1000, 0));
+        let unparsed = legacy_limits("m", "lots", "");
+        assert_eq!((unparsed.rpm, unparsed.tpm, unparsed.rpd, unparsed.tpd), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_day_quotas_need_local_engine() {
+        let daily = legacy_limits("m", "60/min,1000/day", "");
+        assert!(day_quotas_unenforced(&daily, "coil"));
+        assert!(!day_quotas_unenforced(&daily, "local"));
+        let minute = legacy_limits("m", "60/min", "100000");
+        assert!(!day_quotas_unenforced(&minute, "coil"));
+    }
+
+    #[test]
+    fn test_windows_are_aligned() {
+        let quota = Quota { amount: 1, secs: 60 };
+        assert_eq!(quota.window(NOW + 59).start, NOW);
+        assert_eq!(quota.window(NOW + 60).start, NOW + 60);
+    }
+
+    #[test]
+    fn test_subject_rows_win_over_star() {
+        let rows = vec![
+            limits("every-key", "key", "*", 10, 0, 0, 0),
+            limits("vip-key", "key", "sk-vip", 100, 0, 0, 0),
+            limits("every-project", "project", "*", 50, 0, 0, 0),
+        ];
+        let subjects = QuotaSubjects { key: "sk-vip".to_string(), account: "acct".to_string(), project: "".to_string() };
+        let matched = matching_limits(&rows, &subjects);
+        assert_eq!(matched.len(), 1);
+        assert_eq!((matched[0].0.as_str(), matched[0].1.id.as_str()), ("key:sk-vip", "vip-key"));
+
+        let subjects = QuotaSubjects { key: "sk-1".to_string(), account: "".to_string(), project: "proj".to_string() };
+        let ids: Vec<_> = matching_limits(&rows, &subjects).into_iter().map(|(subject, limits)| (subject, limits.id)).collect();
+        assert_eq!(ids, vec![
+            ("key:sk-1".to_string(), "every-key".to_string()),
+            ("project:proj".to_string(), "every-project".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_zero_windows_are_unlimited() {
+        let matched = for_key("k", limits("l", "key", "*", 10, 0, 0, 5000));
+        assert_eq!(request_quotas(&matched), vec![("key:k".to_string(), Quota { amount: 10, secs: 60 })]);
+        assert_eq!(token_quotas(&matched), vec![("key:k".to_string(), Quota { amount: 5000, secs: 86400 })]);
+    }
+
+    #[test]
+    fn test_estimated_tokens() {
+        let body = r#"{"model": "m", "messages": [{"role": "user", "content": "hello"}, {"role": "user", "content": [{"type": "text", "text": "你好"}]}], "max_tokens": 100}"#;
+        let request: ChatCompletionRequest = serde_json::from_str(body).unwrap();
+        let prompt = prompt_tokens("m", body.as_bytes());
+        assert_eq!(prompt, count_tokens("hello") + count_tokens("你好"));
+        assert_eq!(request.estimated_tokens(prompt), prompt as i64 + 100);
+
+        let body = r#"{"model": "m", "prompt": ["abcdefgh", [1, 2, 3]], "max_completion_tokens": 8}"#;
+        let request: ChatCompletionRequest = serde_json::from_str(body).unwrap();
+        assert_eq!(prompt_tokens("m", body.as_bytes()), 2 + 3);
+        assert_eq!(request.estimated_tokens(5), 5 + 8);
+
+        // a body the handlers would reject
+        assert_eq!(prompt_tokens("m", br#"{"model": "m", "input": "hello"}"#), 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_requests_reset_with_the_window() {
+        let engine = QuotaEngine::local();
+        let quotas = request_quotas(&for_key("window", limits("l", "key", "*", 2, 0, 0, 0)));
+        assert!(engine.take_request("m", &quotas, NOW).await.unwrap());
+        assert!(engine.take_request("m", &quotas, NOW + 1).await.unwrap());
+        assert!(!engine.take_request("m", &quotas, NOW + 2).await.unwrap());
+        assert!(engine.take_request("m", &quotas, NOW + 60).await.unwrap());
+        // Other keys and models have their own counters
+        let other = request_quotas(&for_key("other", limits("l", "key", "*", 2, 0, 0, 0)));
+        assert!(engine.take_request("m", &other, NOW + 2).await.unwrap());
+        assert!(engine.take_request("n", &quotas, NOW + 2).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_requests_available_does_not_count() {
+        let engine = QuotaEngine::local();
+        let quotas = request_quotas(&for_key("available", limits("l", "key", "*", 1, 0, 0, 0)));
+        assert!(engine.requests_available("m", &quotas, NOW).await.unwrap());
+        assert!(engine.requests_available("m", &quotas, NOW).await.unwrap());
+        assert!(engine.take_request("m", &quotas, NOW).await.unwrap());
+        assert!(!engine.requests_available("m", &quotas, NOW + 1).await.unwrap());
+        assert!(engine.requests_available("m", &quotas, NOW + 60).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_day_window_spans_minutes() {
+        let engine = QuotaEngine::local();
+        let quotas = request_quotas(&for_key("day", limits("l", "key", "*", 2, 0, 3, 0)));
+        assert!(engine.take_request("m", &quotas, NOW).await.unwrap());
+        assert!(engine.take_request("m", &quotas, NOW + 1).await.unwrap());
+        assert!(engine.take_request("m", &quotas, NOW + 60).await.unwrap());
+        assert!(!engine.take_request("m", &quotas, NOW + 120).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_every_scope_must_have_room() {
+        let engine = QuotaEngine::local();
+        let mut matched = for_key("scoped", limits("per-key", "key", "*", 5, 0, 0, 0));
+        matched.push(("account:scoped".to_string(), limits("per-account", "account", "*", 1, 0, 0, 0)));
+        let quotas = request_quotas(&matched);
+        assert!(engine.take_request("m", &quotas, NOW).await.unwrap());
+        assert!(!engine.take_request("m", &quotas, NOW + 1).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_tokens_use_the_estimate() {
+        let engine = QuotaEngine::local();
+        let quotas = token_quotas(&for_key("tokens", limits("l", "key", "*", 0, 1000, 0, 0)));
+        assert!(engine.tokens_available("m", &quotas, 400, NOW).await.unwrap());
+        engine.consume_tokens("m", &quotas, 600, NOW).await.unwrap();
+        assert!(engine.tokens_available("m", &quotas, 400, NOW + 1).await.unwrap());
+        assert!(!engine.tokens_available("m", &quotas, 401, NOW + 1).await.unwrap());
+        // A request larger than the whole window needs an unused window
+        assert!(engine.tokens_available("m", &quotas, 5000, NOW + 60).await.unwrap());
+        engine.consume_tokens("m", &quotas, 1, NOW + 60).await.unwrap();
+        assert!(!engine.tokens_available("m", &quotas, 5000, NOW + 61).await.unwrap());
+    }
+}
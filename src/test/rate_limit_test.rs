@@ -0,0 +1,99 @@
+#[cfg(test)]
+pub mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::meta::rate_limits::traits::RateLimit;
+    use crate::middleware::rate_limit::{format_reset, KeyedLimiter};
+
+    fn limit(id: &str, scope: &str, requests_per_minute: i32, burst: i32) -> RateLimit {
+        RateLimit {
+            id: id.to_string(),
+            scope: scope.to_string(),
+            subject: "*".to_string(),
+            requests_per_minute,
+            burst,
+        }
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let mut limiter = KeyedLimiter::default();
+        let limits = vec![(limit("per-key", "key", 60, 2), "sk-1".to_string())];
+        let start = Instant::now();
+
+        let first = limiter.check(&limits, start).unwrap();
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining), (2, 1));
+        assert!(limiter.check(&limits, start).unwrap().allowed);
+
+        let rejected = limiter.check(&limits, start).unwrap();
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.remaining, 0);
+        assert_eq!(rejected.retry_after, Duration::from_secs(1));
+
+        // 60 requests per minute refill one token per second
+        assert!(limiter.check(&limits, start + Duration::from_secs(1)).unwrap().allowed);
+    }
+
+    #[test]
+    fn test_subjects_have_their_own_buckets() {
+        let mut limiter = KeyedLimiter::default();
+        let rule = limit("per-ip", "ip", 60, 1);
+        let now = Instant::now();
+        assert!(limiter.check(&[(rule.clone(), "10.0.0.1".to_string())], now).unwrap().allowed);
+        assert!(!limiter.check(&[(rule.clone(), "10.0.0.1".to_string())], now).unwrap().allowed);
+        assert!(limiter.check(&[(rule, "10.0.0.2".to_string())], now).unwrap().allowed);
+    }
+
+    #[test]
+    fn test_rejection_charges_no_bucket() {
+        let mut limiter = KeyedLimiter::default();
+        let key = (limit("per-key", "key", 60, 5), "sk-1".to_string());
+        let model = (limit("per-model", "model", 60, 1), "Qwen2.5-7B-Instruct".to_string());
+        let now = Instant::now();
+
+        assert!(limiter.check(&[key.clone(), model.clone()], now).unwrap().allowed);
+        let rejected = limiter.check(&[key.clone(), model.clone()], now).unwrap();
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.limit, 1);
+
+        // The key bucket was charged once only
+        assert_eq!(limiter.check(&[key], now).unwrap().remaining, 3);
+    }
+
+    #[test]
+    fn test_refilled_buckets_are_dropped() {
+        let mut limiter = KeyedLimiter::default();
+        let rule = limit("per-ip", "ip", 60, 10);
+        let start = Instant::now();
+        for i in 0..5 {
+            assert!(limiter.check(&[(rule.clone(), format!("10.0.0.{}", i))], start).unwrap().allowed);
+        }
+        // 10.0.0.0 drains its bucket, it takes 10s to refill
+        for _ in 0..9 {
+            limiter.check(&[(rule.clone(), "10.0.0.0".to_string())], start);
+        }
+        assert_eq!(limiter.bucket_count(), 5);
+
+        // All of them are full again after 10s at most and dropped at the next sweep
+        limiter.check(&[(rule.clone(), "10.0.0.9".to_string())], start + Duration::from_secs(60));
+        assert_eq!(limiter.bucket_count(), 1);
+
+        let mut limiter = KeyedLimiter::default();
+        limiter.check(&[(rule.clone(), "10.0.0.0".to_string())], start);
+        for _ in 0..9 {
+            limiter.check(&[(rule.clone(), "10.0.0.0".to_string())], start + Duration::from_secs(55));
+        }
+        // Still refilling at the next sweep, its tokens are kept
+        let decision = limiter.check(&[(rule, "10.0.0.1".to_string())], start + Duration::from_secs(60)).unwrap();
+        assert!(decision.allowed);
+        assert_eq!(limiter.bucket_count(), 2);
+    }
+
+    #[test]
+    fn test_format_reset() {
+        assert_eq!(format_reset(Duration::from_millis(20)), "20ms");
+        assert_eq!(format_reset(Duration::from_millis(1500)), "2s");
+        assert_eq!(format_reset(Duration::from_secs(360)), "6m0s");
+    }
+}
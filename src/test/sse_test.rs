@@ -0,0 +1,159 @@
+#[cfg(test)]
+pub mod tests {
+    use crate::cores::chat_models::chat_controller::CompletionsStreamResponse;
+    use crate::utils::sse::{SseDecoder, SseEvent};
+
+    // vLLM 0.6 `/v1/chat/completions` with `stream_options.include_usage`
+    const VLLM_STREAM: &str = concat!(
+        "data: {\"id\":\"chat-5f3e9a\",\"object\":\"chat.completion.chunk\",\"created\":1735000000,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"},\"logprobs\":null,\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chat-5f3e9a\",\"object\":\"chat.completion.chunk\",\"created\":1735000000,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"你好\"},\"logprobs\":null,\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chat-5f3e9a\",\"object\":\"chat.completion.chunk\",\"created\":1735000000,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"！\"},\"logprobs\":null,\"finish_reason\":\"stop\",\"stop_reason\":null}]}\n\n",
+        "data: {\"id\":\"chat-5f3e9a\",\"object\":\"chat.completion.chunk\",\"created\":1735000000,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[],\"usage\":{\"prompt_tokens\":20,\"total_tokens\":23,\"completion_tokens\":3}}\n\n",
+        "data: [DONE]\n\n",
+    );
+
+    // MindIE OpenAI-compatible endpoint, served with CRLF line endings
+    const MINDIE_STREAM: &str = concat!(
+        "data: {\"id\":\"endpoint_common_12\",\"object\":\"chat.completion.chunk\",\"created\":1735000100,\"model\":\"Qwen-7B-Chat\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"昇腾\"},\"finish_reason\":null}]}\r\n\r\n",
+        "data: {\"id\":\"endpoint_common_12\",\"object\":\"chat.completion.chunk\",\"created\":1735000100,\"model\":\"Qwen-7B-Chat\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"910B\"},\"finish_reason\":\"stop\"}]}\r\n\r\n",
+        "data: [DONE]\r\n\r\n",
+    );
+
+    // DashScope native SSE: `id`/`event` fields, a status comment and no space after the colon
+    const DASHSCOPE_STREAM: &str = concat!(
+        "id:1\n",
+        "event:result\n",
+        ":HTTP_STATUS/200\n",
+        "data:{\"output\":{\"choices\":[{\"message\":{\"content\":\"你好\",\"role\":\"assistant\"},\"finish_reason\":\"null\"}]},\"usage\":{\"total_tokens\":12,\"input_tokens\":10,\"output_tokens\":2},\"request_id\":\"5c1b7f0e\"}\n",
+        "\n",
+        "id:2\n",
+        "event:result\n",
+        ":HTTP_STATUS/200\n",
+        "data:{\"output\":{\"choices\":[{\"message\":{\"content\":\"你好！\",\"role\":\"assistant\"},\"finish_reason\":\"stop\"}]},\"usage\":{\"total_tokens\":13,\"input_tokens\":10,\"output_tokens\":3},\"request_id\":\"5c1b7f0e\"}\n",
+        "\n",
+    );
+
+    // vLLM with `--enable-auto-tool-choice`, the arguments string is streamed in fragments
+    const VLLM_TOOL_CALL_STREAM: &str = concat!(
+        "data: {\"id\":\"chat-81c2\",\"object\":\"chat.completion.chunk\",\"created\":1735000200,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":null},\"logprobs\":null,\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chat-81c2\",\"object\":\"chat.completion.chunk\",\"created\":1735000200,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"id\":\"chatcmpl-tool-4d1f\",\"type\":\"function\",\"index\":0,\"function\":{\"name\":\"get_weather\"}}]},\"logprobs\":null,\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chat-81c2\",\"object\":\"chat.completion.chunk\",\"created\":1735000200,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"city\\\": \"}}]},\"logprobs\":null,\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chat-81c2\",\"object\":\"chat.completion.chunk\",\"created\":1735000200,\"model\":\"Qwen/Qwen2.5-7B-Instruct\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"杭州\\\"}\"}}]},\"logprobs\":null,\"finish_reason\":\"tool_calls\"}]}\n\n",
+        "data: [DONE]\n\n",
+    );
+
+    fn decode_in_chunks(body: &str, chunk_size: usize) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        for chunk in body.as_bytes().chunks(chunk_size) {
+            events.extend(decoder.push(chunk));
+        }
+        events.extend(decoder.finish());
+        events
+    }
+
+    #[test]
+    fn test_vllm_stream_in_one_read() {
+        let events = decode_in_chunks(VLLM_STREAM, VLLM_STREAM.len());
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[4].data, "[DONE]");
+        for event in &events[..4] {
+            assert_eq!(event.event, None);
+            serde_json::from_str::<CompletionsStreamResponse>(&event.data).expect("event is not a stream chunk");
+        }
+        let usage_chunk: CompletionsStreamResponse = serde_json::from_str(&events[3].data).unwrap();
+        assert_eq!(usage_chunk.usage.unwrap().total_tokens, 23);
+    }
+
+    #[test]
+    fn test_vllm_stream_split_across_reads() {
+        let expected = decode_in_chunks(VLLM_STREAM, VLLM_STREAM.len());
+        // Byte-sized reads also split the multi-byte characters of "你好"
+        for chunk_size in [1, 2, 3, 7, 64, 500] {
+            assert_eq!(decode_in_chunks(VLLM_STREAM, chunk_size), expected, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_vllm_tool_call_stream() {
+        let events = decode_in_chunks(VLLM_TOOL_CALL_STREAM, 3);
+        assert_eq!(events.len(), 5);
+        let mut name = String::new();
+        let mut arguments = String::new();
+        for event in &events[..4] {
+            let chunk: CompletionsStreamResponse = serde_json::from_str(&event.data).expect("event is not a stream chunk");
+            for tool_call in chunk.choices[0].delta.tool_calls.iter().flatten() {
+                assert_eq!(tool_call.index, 0);
+                if let Some(function) = &tool_call.function {
+                    name.push_str(function.name.as_deref().unwrap_or_default());
+                    arguments.push_str(function.arguments.as_deref().unwrap_or_default());
+                }
+            }
+        }
+        assert_eq!(name, "get_weather");
+        assert_eq!(arguments, "{\"city\": \"杭州\"}");
+    }
+
+    #[test]
+    fn test_mindie_stream_with_crlf() {
+        let expected: Vec<String> = MINDIE_STREAM.split("\r\n\r\n")
+            .filter(|event| !event.is_empty())
+            .map(|event| event.trim_start_matches("data: ").to_string())
+            .collect();
+        for chunk_size in [1, 2, 5, MINDIE_STREAM.len()] {
+            let events = decode_in_chunks(MINDIE_STREAM, chunk_size);
+            let data: Vec<String> = events.into_iter().map(|event| event.data).collect();
+            assert_eq!(data, expected, "chunk size {}", chunk_size);
+        }
+        let first: CompletionsStreamResponse = serde_json::from_str(&expected[0]).unwrap();
+        assert_eq!(first.choices[0].delta.content.as_deref(), Some("昇腾"));
+    }
+
+    #[test]
+    fn test_dashscope_stream_fields_and_comments() {
+        for chunk_size in [1, 4, DASHSCOPE_STREAM.len()] {
+            let events = decode_in_chunks(DASHSCOPE_STREAM, chunk_size);
+            assert_eq!(events.len(), 2);
+            assert_eq!(events[0].event.as_deref(), Some("result"));
+            assert_eq!(events[0].id.as_deref(), Some("1"));
+            assert_eq!(events[1].id.as_deref(), Some("2"));
+            let data: serde_json::Value = serde_json::from_str(&events[1].data).unwrap();
+            assert_eq!(data["output"]["choices"][0]["finish_reason"], "stop");
+        }
+    }
+
+    #[test]
+    fn test_several_events_in_one_read() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b"data: {\"a\":1}\n\ndata: {\"a\":2}\n\ndata: {\"a\"");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].data, "{\"a\":2}");
+        let events = decoder.push(b":3}\n\n");
+        assert_eq!(events[0].data, "{\"a\":3}");
+    }
+
+    #[test]
+    fn test_crlf_split_between_reads() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: x\r").is_empty());
+        assert!(decoder.push(b"\n\r").is_empty());
+        let events = decoder.push(b"\n");
+        assert_eq!(events, vec![SseEvent { event: None, id: None, data: "x".to_string() }]);
+    }
+
+    #[test]
+    fn test_multiline_data_comments_and_empty_events() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b": ping\n\nevent: ping\n\ndata: line1\ndata:line2\ndata\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "line1\nline2\n");
+    }
+
+    #[test]
+    fn test_finish_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: [DONE]").is_empty());
+        assert_eq!(decoder.finish().map(|event| event.data), Some("[DONE]".to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+}
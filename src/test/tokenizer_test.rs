@@ -0,0 +1,46 @@
+#[cfg(test)]
+pub mod tests {
+    use std::fs;
+
+    use crate::cores::chat_models::chat_utils::check_context_window;
+    use crate::utils::tokenizer::{count_tokens, load_tokenizer};
+
+    // A word level vocabulary in the HuggingFace `tokenizer.json` format
+    const TOKENIZER_JSON: &str = r#"{
+        "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+        "normalizer": null, "pre_tokenizer": {"type": "Whitespace"}, "post_processor": null, "decoder": null,
+        "model": {"type": "WordLevel", "vocab": {"hello": 0, "world": 1, "[UNK]": 2}, "unk_token": "[UNK]"}
+    }"#;
+
+    #[test]
+    fn test_load_tokenizer_per_model() {
+        let dir = std::env::temp_dir().join(format!("chatig-tokenizers-{}", std::process::id()));
+        fs::create_dir_all(dir.join("Qwen2.5-7B-Instruct")).unwrap();
+        fs::write(dir.join("Qwen2.5-7B-Instruct").join("tokenizer.json"), TOKENIZER_JSON).unwrap();
+        let dir = dir.to_str().unwrap();
+
+        let tokenizer = load_tokenizer(dir, "Qwen2.5-7B-Instruct").unwrap();
+        assert_eq!(tokenizer.encode("hello big world", false).unwrap().len(), 3);
+        // `Series/Name` falls back to the directory of `Name`
+        assert!(load_tokenizer(dir, "Qwen/Qwen2.5-7B-Instruct").is_some());
+        assert!(load_tokenizer(dir, "Llama-3-8B").is_none());
+        assert!(load_tokenizer("", "Qwen2.5-7B-Instruct").is_none());
+    }
+
+    #[test]
+    fn test_approximate_counts() {
+        assert_eq!(count_tokens("internationalization"), 5);
+        assert_eq!(count_tokens("日本語のテキスト"), 8);
+        assert_eq!(count_tokens("  \n "), 0);
+    }
+
+    #[test]
+    fn test_context_window() {
+        assert!(check_context_window(0, 1_000_000, None).is_ok());
+        assert!(check_context_window(4096, 3000, Some(1096)).is_ok());
+        let err = check_context_window(4096, 3000, Some(2000)).unwrap_err();
+        assert_eq!(err, "This model's maximum context length is 4096 tokens. However, you requested 5000 tokens (3000 in the messages, 2000 in the completion). Please reduce the length of the messages or completion.");
+        let err = check_context_window(4096, 5000, None).unwrap_err();
+        assert!(err.contains("your messages resulted in 5000 tokens"));
+    }
+}
@@ -0,0 +1,81 @@
+#[cfg(test)]
+pub mod tests {
+    use crate::cores::chat_models::chat_router::RouteContext;
+    use crate::cores::control::traffic_splits::{assign_variant, check_percent_total, split_bucket};
+    use crate::meta::traffic_splits::traits::TrafficSplit;
+
+    fn split(id: &str, percent: i32, userkeys: &str) -> TrafficSplit {
+        TrafficSplit {
+            id: id.to_string(),
+            model: "Qwen2.5-7B-Instruct".to_string(),
+            target_model: format!("Qwen2.5-7B-{}", id),
+            target_service: "".to_string(),
+            percent,
+            userkeys: userkeys.to_string(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_listed_keys_get_the_variant() {
+        let splits = vec![split("canary", 0, "sk-tester1, sk-tester2")];
+        assert_eq!(assign_variant(&splits, "sk-tester2", "user-1").map(|split| split.id.as_str()), Some("canary"));
+        assert!(assign_variant(&splits, "sk-other", "user-1").is_none());
+        assert!(assign_variant(&splits, "", "user-1").is_none());
+    }
+
+    #[test]
+    fn test_assignment_is_sticky_and_follows_percent() {
+        let splits = vec![split("a", 20, ""), split("b", 30, "")];
+        let mut counts = [0; 3];
+        for user in 0..10000 {
+            let sticky_id = format!("user-{}", user);
+            let variant = assign_variant(&splits, "", &sticky_id).map(|split| split.id.clone());
+            assert_eq!(variant, assign_variant(&splits, "", &sticky_id).map(|split| split.id.clone()));
+            match variant.as_deref() {
+                Some("a") => counts[0] += 1,
+                Some("b") => counts[1] += 1,
+                _ => counts[2] += 1,
+            }
+        }
+        assert!((1700..2300).contains(&counts[0]), "{:?}", counts);
+        assert!((2700..3300).contains(&counts[1]), "{:?}", counts);
+        assert!((4700..5300).contains(&counts[2]), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_buckets_are_pinned() {
+        // Changing these moves users between variants
+        assert_eq!(split_bucket("Qwen2.5-7B-Instruct", "user-1"), 82);
+        assert_eq!(split_bucket("Qwen2.5-7B-Instruct", "alice"), 53);
+        assert_eq!(split_bucket("Qwen2.5-7B-Instruct", "sk-1"), 23);
+    }
+
+    #[test]
+    fn test_percent_total_is_at_most_100() {
+        let splits = vec![split("a", 70, ""), split("b", 20, "")];
+        assert!(check_percent_total(&split("c", 10, ""), &splits).is_ok());
+        assert!(check_percent_total(&split("c", 11, ""), &splits).is_err());
+        // An update replaces the rule's own percentage
+        assert!(check_percent_total(&split("a", 80, ""), &splits).is_ok());
+        let mut disabled = split("c", 60, "");
+        disabled.enabled = false;
+        assert!(check_percent_total(&disabled, &splits).is_ok());
+        assert!(check_percent_total(&split("c", 60, ""), &[disabled]).is_ok());
+    }
+
+    #[test]
+    fn test_disabled_rules_are_skipped() {
+        let mut canary = split("canary", 100, "sk-tester1");
+        canary.enabled = false;
+        assert!(assign_variant(&[canary.clone()], "sk-tester1", "user-1").is_none());
+        assert!(split_bucket(&canary.model, "user-1") < 100);
+    }
+
+    #[test]
+    fn test_sticky_id_order() {
+        assert_eq!(RouteContext::new("sk-1".to_string(), "account-1", Some("alice")).sticky_id, "alice");
+        assert_eq!(RouteContext::new("sk-1".to_string(), "account-1", Some("")).sticky_id, "sk-1");
+        assert_eq!(RouteContext::new("".to_string(), "account-1", None).sticky_id, "account-1");
+    }
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+pub mod tests {
+    use crate::cores::usage::records::{usage_csv, usage_query, UsageParams};
+    use crate::meta::usage::impls::{aggregate_sql, UsageBind};
+    use crate::meta::usage::traits::{UsageAggregate, UsageQuery};
+
+    fn params(group_by: &str, bucket: &str) -> UsageParams {
+        UsageParams {
+            group_by: Some(group_by.to_string()),
+            bucket: Some(bucket.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_usage_query_from_params() {
+        let query = usage_query(&UsageParams {
+            start: Some("2024-01-01".to_string()),
+            end: Some("2024-01-02T00:00:00+08:00".to_string()),
+            account_id: Some("111111".to_string()),
+            api_key: Some("".to_string()),
+            group_by: Some("model, account,model".to_string()),
+            bucket: Some("hour".to_string()),
+            utc_offset: Some(480),
+            ..Default::default()
+        }).unwrap();
+        // midnight of the date at UTC+8, 2023-12-31 16:00:00 UTC
+        assert_eq!(query.start, Some(1704038400));
+        assert_eq!(query.end, Some(1704124800));
+        assert_eq!(query.account_id.as_deref(), Some("111111"));
+        assert_eq!(query.api_key, None);
+        assert_eq!(query.group_by, vec!["model", "account_id"]);
+        assert_eq!((query.bucket_secs, query.utc_offset), (Some(3600), 28800));
+
+        assert_eq!(usage_query(&UsageParams { start: Some("1704067200".to_string()), ..Default::default() }).unwrap().start, Some(1704067200));
+        assert!(usage_query(&params("user", "none")).is_err());
+        assert!(usage_query(&params("", "week")).is_err());
+        assert!(usage_query(&UsageParams { start: Some("yesterday".to_string()), ..Default::default() }).is_err());
+        assert!(usage_query(&UsageParams { utc_offset: Some(900), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_sql() {
+        let mut query = usage_query(&params("account,model", "day")).unwrap();
+        query.start = Some(100);
+        query.model = Some("qwen".to_string());
+
+        let (sql, binds) = aggregate_sql(&query, false);
+        assert!(sql.starts_with("SELECT ((start_time + 0) - MOD((start_time + 0), 86400) - 0) AS bucket_start, account_id, CAST(NULL AS TEXT) AS api_key, CAST(NULL AS TEXT) AS project, model, COUNT(*) AS requests, "), "{}", sql);
+        assert!(sql.ends_with(" FROM usage_records WHERE start_time >= $1 AND model = $2 GROUP BY bucket_start, account_id, model ORDER BY bucket_start, account_id, model"), "{}", sql);
+        assert_eq!(binds, vec![UsageBind::Int(100), UsageBind::Text("qwen".to_string())]);
+
+        let (sql, _) = aggregate_sql(&query, true);
+        assert!(sql.contains("CAST(NULL AS CHAR) AS api_key") && sql.contains("CAST(COALESCE(SUM(total_tokens), 0) AS SIGNED) AS total_tokens"), "{}", sql);
+        assert!(sql.contains("WHERE start_time >= ? AND model = ?"), "{}", sql);
+
+        // Everything summed into one row
+        let (sql, binds) = aggregate_sql(&UsageQuery::default(), false);
+        assert!(sql.starts_with("SELECT CAST(NULL AS BIGINT) AS bucket_start,"), "{}", sql);
+        assert!(sql.ends_with("FROM usage_records"), "{}", sql);
+        assert!(binds.is_empty());
+    }
+
+    #[test]
+    fn test_usage_csv() {
+        let query = usage_query(&UsageParams { utc_offset: Some(480), ..params("model", "day") }).unwrap();
+        let aggregates = vec![UsageAggregate {
+            bucket_start: Some(1704038400),
+            model: Some("qwen,\"chat\"".to_string()),
+            requests: 2,
+            prompt_tokens: 10,
+            completion_tokens: 20,
+            total_tokens: 30,
+            cached_tokens: 4,
+            cost: 0.25,
+            ..Default::default()
+        }];
+        assert_eq!(usage_csv(&aggregates, &query),
+            "bucket_start,model,requests,prompt_tokens,completion_tokens,total_tokens,cached_tokens,cost\n\
+             2024-01-01T00:00:00+08:00,\"qwen,\"\"chat\"\"\",2,10,20,30,4,0.25\n");
+        assert_eq!(usage_csv(&[], &UsageQuery::default()), "requests,prompt_tokens,completion_tokens,total_tokens,cached_tokens,cost\n");
+    }
+}
@@ -0,0 +1,166 @@
+#[cfg(test)]
+pub mod tests {
+    use async_trait::async_trait;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use rskafka::client::SaslConfig;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc::channel;
+
+    use crate::cores::usage::kafka::{sasl_config, tls_config, KafkaProducer};
+    use crate::cores::usage::sinks::{FileSink, UsageSink};
+    use crate::cores::usage::spool::Spool;
+    use crate::cores::usage::UsagePipeline;
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("chatig-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn events(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("{{\"totalTokens\":{}}}", i)).collect()
+    }
+
+    // A sink that fails while `down` is set and records what it took
+    #[derive(Clone, Default)]
+    struct FlakySink {
+        down: Arc<Mutex<bool>>,
+        taken: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl UsageSink for FlakySink {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        async fn send(&self, events: &[String]) -> Result<(), String> {
+            if *self.down.lock().unwrap() {
+                return Err("broker down".to_string());
+            }
+            self.taken.lock().unwrap().extend_from_slice(events);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_kafka_sasl_config() {
+        assert!(sasl_config("", "", "").unwrap().is_none());
+        assert!(matches!(sasl_config("plain", "usage", "secret").unwrap(), Some(SaslConfig::Plain(credentials)) if credentials.username == "usage"));
+        assert!(matches!(sasl_config("SCRAM-SHA-512", "usage", "secret").unwrap(), Some(SaslConfig::ScramSha512(_))));
+        assert!(sasl_config("GSSAPI", "usage", "secret").is_err());
+    }
+
+    #[test]
+    fn test_kafka_tls_ca_file() {
+        assert!(tls_config("/nonexistent/ca.pem").unwrap_err().contains("/nonexistent/ca.pem"));
+        let dir = temp_dir("kafka-ca");
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/ca.pem", dir);
+        fs::write(&path, "not a certificate\n").unwrap();
+        assert!(tls_config(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_kafka_producer_broker_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let producer = KafkaProducer::new(vec![address], "usage", None, None, Duration::from_secs(2));
+        assert!(producer.send(&events(0..1)).await.is_err());
+        // Nothing to send needs no broker
+        assert!(producer.send(&[]).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_spool_is_bounded() {
+        let dir = temp_dir("spool-bounded");
+        let spool = Spool::new(&dir, 100);
+        assert_eq!(spool.size().await, (0, 0));
+        assert!(spool.push(&events(0..3)).await.unwrap());        // 3 * 17 bytes and newlines: 54
+        assert!(!spool.push(&events(3..6)).await.unwrap());       // would be 108
+        assert!(spool.push(&events(6..7)).await.unwrap());
+        assert_eq!(spool.size().await, (2, 72));
+
+        let files = spool.files().await;
+        assert_eq!(Spool::read(&files[0]).await.unwrap(), events(0..3));
+        assert_eq!(Spool::read(&files[1]).await.unwrap(), events(6..7));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_pipeline_spools_and_replays_in_order() {
+        let dir = temp_dir("spool-replay");
+        let sink = FlakySink::default();
+        let pipeline = UsagePipeline::new(Box::new(sink.clone()), Spool::new(&dir, 1 << 20), 2, Duration::from_millis(1));
+
+        *sink.down.lock().unwrap() = true;
+        pipeline.deliver(events(0..2)).await;
+        pipeline.deliver(events(2..3)).await;
+        let stats = pipeline.stats().await;
+        // three tries of the first batch, the second goes after it without a send
+        assert_eq!((stats.delivered, stats.spooled, stats.failed_attempts, stats.spool_files), (0, 3, 3, 2));
+        assert_eq!(stats.last_error.as_deref(), Some("broker down"));
+        assert!(!pipeline.replay().await);
+        assert_eq!(pipeline.stats().await.failed_attempts, 4);
+
+        // Batches wait behind the spool until a replay empties it
+        *sink.down.lock().unwrap() = false;
+        pipeline.deliver(events(3..4)).await;
+        assert!(sink.taken.lock().unwrap().is_empty());
+        assert!(pipeline.replay().await);
+        assert_eq!(*sink.taken.lock().unwrap(), events(0..4));
+        pipeline.deliver(events(4..5)).await;
+        let stats = pipeline.stats().await;
+        assert_eq!((stats.delivered, stats.replayed, stats.dropped, stats.spool_files), (1, 4, 0, 0));
+        assert!(stats.last_delivered_at.is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[actix_web::test]
+    async fn test_pipeline_delivers_the_queue_on_shutdown() {
+        let dir = temp_dir("spool-shutdown");
+        let sink = FlakySink::default();
+        let pipeline = Arc::new(UsagePipeline::new(Box::new(sink.clone()), Spool::new(&dir, 1 << 20), 0, Duration::from_millis(1)));
+        let (sender, receiver) = channel(10);
+        // Neither the batch size nor the flush interval is reached before the shutdown
+        let task = tokio::spawn(pipeline.clone().run(receiver, 2, Duration::from_secs(3600)));
+        for event in events(0..3) {
+            sender.try_send(event).unwrap();
+        }
+        pipeline.shutdown();
+        task.await.unwrap();
+        assert_eq!(*sink.taken.lock().unwrap(), events(0..3));
+        assert!(sender.try_send("late".to_string()).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[actix_web::test]
+    async fn test_pipeline_drops_when_spool_is_full() {
+        let dir = temp_dir("spool-full");
+        let sink = FlakySink::default();
+        let pipeline = UsagePipeline::new(Box::new(sink.clone()), Spool::new(&dir, 40), 0, Duration::from_millis(1));
+
+        *sink.down.lock().unwrap() = true;
+        pipeline.deliver(events(0..2)).await;
+        pipeline.deliver(events(2..4)).await;
+        let stats = pipeline.stats().await;
+        assert_eq!((stats.spooled, stats.dropped, stats.spool_files), (2, 2, 1));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[actix_web::test]
+    async fn test_file_sink_appends_lines() {
+        let dir = temp_dir("file-sink");
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/usage.jsonl", dir);
+        let sink = FileSink::new(&path);
+        sink.send(&events(0..2)).await.unwrap();
+        sink.send(&events(2..3)).await.unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), events(0..3).join("\n") + "\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
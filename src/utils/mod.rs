@@ -1 +1,3 @@
-pub mod log;
\ No newline at end of file
+pub mod log;
+pub mod sse;
+pub mod tokenizer;
\ No newline at end of file
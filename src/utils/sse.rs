@@ -0,0 +1,105 @@
+// Incremental decoder for `text/event-stream` bodies (https://html.spec.whatwg.org/multipage/server-sent-events.html).
+// Upstream engines do not align events with TCP reads: one read may carry several events,
+// one event may be split across reads (even inside a UTF-8 character), and lines may end with CRLF.
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,   // `event:` field, None means the default "message" type
+    pub id: Option<String>,      // `id:` field
+    pub data: String,            // `data:` lines joined with '\n'
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    id: Option<String>,
+    data: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Feed the next chunk of the body and return the events completed by it
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        let mut pos = 0;
+        while pos < self.buffer.len() {
+            let terminator_len = match self.buffer[pos] {
+                b'\n' => 1,
+                b'\r' => match self.buffer.get(pos + 1) {
+                    Some(b'\n') => 2,
+                    Some(_) => 1,
+                    // A trailing CR may be the first half of CRLF, wait for the next chunk
+                    None => break,
+                },
+                _ => {
+                    pos += 1;
+                    continue;
+                }
+            };
+
+            let line = String::from_utf8_lossy(&self.buffer[start..pos]).into_owned();
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+            pos += terminator_len;
+            start = pos;
+        }
+        self.buffer.drain(..start);
+
+        events
+    }
+
+    // Flush an event left unterminated when the body ends
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&self.buffer).into_owned();
+            self.buffer.clear();
+            if let Some(event) = self.process_line(line.trim_end_matches('\r')) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // comment, e.g. `: ping` keep-alives or DashScope `:HTTP_STATUS/200`
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => {
+                let data = self.data.get_or_insert_with(String::new);
+                data.push_str(value);
+                data.push('\n');
+            }
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            _ => {}   // `retry` and unknown fields are ignored
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        // Events without any `data:` line are not dispatched
+        self.data.take().map(|mut data| {
+            data.pop();   // drop the newline appended after the last data line
+            SseEvent { event, id: self.id.clone(), data }
+        })
+    }
+}
@@ -0,0 +1,106 @@
+// Token counts for text the upstream hasn't counted yet: prompts before a request is sent and
+// streamed completions of upstreams that don't report usage.
+// HuggingFace tokenizers are loaded from `<tokenizer_dir>/<model>/tokenizer.json` on first use;
+// other models get an approximation.
+use log::{error, info};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tokenizers::Tokenizer;
+
+use crate::configs::settings::GLOBAL_CONFIG;
+
+// model -> its tokenizer, None when there is no usable tokenizer.json
+static TOKENIZERS: Lazy<RwLock<HashMap<String, Option<Arc<Tokenizer>>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+// The tokenizer of a model. `Series/Name` models are looked up under the full name, then under `Name`.
+pub fn model_tokenizer(model: &str) -> Option<Arc<Tokenizer>> {
+    if let Some(tokenizer) = TOKENIZERS.read().unwrap().get(model) {
+        return tokenizer.clone();
+    }
+    let tokenizer = load_tokenizer(&GLOBAL_CONFIG.tokenizer_dir, model);
+    TOKENIZERS.write().unwrap().insert(model.to_string(), tokenizer.clone());
+    tokenizer
+}
+
+// Load the tokenizer of a model on the blocking pool, so that reading tokenizer.json the first time
+// a model is requested doesn't hold up an actix worker
+pub async fn prepare_tokenizer(model: &str) {
+    if TOKENIZERS.read().unwrap().contains_key(model) {
+        return;
+    }
+    let model = model.to_string();
+    if let Err(err) = tokio::task::spawn_blocking(move || model_tokenizer(&model)).await {
+        error!(target: "error_log", "Failed to load tokenizer: {}", err);
+    }
+}
+
+pub fn load_tokenizer(dir: &str, model: &str) -> Option<Arc<Tokenizer>> {
+    if dir.is_empty() {
+        return None;
+    }
+    let names = [Some(model), model.rsplit_once('/').map(|(_, name)| name)];
+    let path = names.into_iter()
+        .flatten()
+        .map(|name| Path::new(dir).join(name).join("tokenizer.json"))
+        .find(|path| path.is_file())?;
+    match Tokenizer::from_file(&path) {
+        Ok(tokenizer) => {
+            info!(target: "access_log", "Loaded tokenizer of {} from {}", model, path.display());
+            Some(Arc::new(tokenizer))
+        }
+        Err(err) => {
+            error!(target: "error_log", "Failed to load tokenizer {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+// Whether the counts of a model are exact
+pub fn has_tokenizer(model: &str) -> bool {
+    model_tokenizer(model).is_some()
+}
+
+// Count with the model's tokenizer, else approximately
+pub fn count_model_tokens(model: &str, text: &str) -> u32 {
+    model_tokenizer(model)
+        .and_then(|tokenizer| tokenizer.encode(text, false).ok())
+        .map(|encoding| encoding.len() as u32)
+        .unwrap_or_else(|| count_tokens(text))
+}
+
+// Without the model's vocabulary a CJK character is one token, punctuation is one token,
+// and other words take one token per four characters, which is close for BPE vocabularies.
+pub fn count_tokens(text: &str) -> u32 {
+    let mut tokens = 0;
+    let mut word = 0;   // characters of the current word
+    for c in text.chars() {
+        if is_cjk(c) || c.is_ascii_punctuation() {
+            tokens += word_tokens(word) + 1;
+            word = 0;
+        } else if c.is_whitespace() {
+            tokens += word_tokens(word);
+            word = 0;
+        } else {
+            word += 1;
+        }
+    }
+    tokens + word_tokens(word)
+}
+
+fn word_tokens(chars: u32) -> u32 {
+    chars.div_ceil(4)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'     // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}'   // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}'   // CJK Unified Ideographs
+        | '\u{ac00}'..='\u{d7af}'   // Hangul Syllables
+        | '\u{f900}'..='\u{faff}'   // CJK Compatibility Ideographs
+        | '\u{3000}'..='\u{303f}'   // CJK Symbols and Punctuation
+        | '\u{ff00}'..='\u{ffef}'   // Fullwidth forms
+    )
+}